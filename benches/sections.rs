@@ -0,0 +1,100 @@
+// palette sections against a flat array of blocks, run with `cargo bench`
+#[path = "../src/atlas.rs"]
+mod atlas;
+#[path = "../src/biome.rs"]
+mod biome;
+#[path = "../src/block.rs"]
+mod block;
+#[path = "../src/world.rs"]
+mod world;
+
+use block::Block;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use world::{Section, CHUNK_SIZE, SECTION_VOLUME};
+
+struct FlatSection {
+    blocks: Vec<Block>,
+}
+
+impl FlatSection {
+    fn new() -> Self {
+        Self {
+            blocks: vec![Block::Air; SECTION_VOLUME],
+        }
+    }
+
+    fn get(&self, x: usize, y: usize, z: usize) -> Block {
+        self.blocks[(y * CHUNK_SIZE + z) * CHUNK_SIZE + x]
+    }
+
+    fn set(&mut self, x: usize, y: usize, z: usize, block: Block) {
+        self.blocks[(y * CHUNK_SIZE + z) * CHUNK_SIZE + x] = block;
+    }
+}
+
+// a typical surface section: stone below, dirt and grass on top, a bit of water
+fn terrain(x: usize, y: usize, z: usize) -> Block {
+    let height = 8 + (x * 7 + z * 3) % 5;
+    match y {
+        y if y < height - 3 => Block::Stone,
+        y if y < height => Block::Dirt,
+        y if y == height => Block::Grass,
+        y if y < 10 => Block::Water,
+        _ => Block::Air,
+    }
+}
+
+fn coords() -> impl Iterator<Item = (usize, usize, usize)> {
+    (0..CHUNK_SIZE)
+        .flat_map(|y| (0..CHUNK_SIZE).flat_map(move |z| (0..CHUNK_SIZE).map(move |x| (x, y, z))))
+}
+
+fn bench_sections(c: &mut Criterion) {
+    let mut section = Section::new();
+    let mut flat = FlatSection::new();
+    for (x, y, z) in coords() {
+        section.set(x, y, z, terrain(x, y, z));
+        flat.set(x, y, z, terrain(x, y, z));
+    }
+    println!(
+        "terrain section: palette {} bytes, flat {} bytes",
+        section.memory_size(),
+        SECTION_VOLUME * std::mem::size_of::<Block>()
+    );
+
+    c.bench_function("palette fill", |b| {
+        b.iter(|| {
+            let mut section = Section::new();
+            for (x, y, z) in coords() {
+                section.set(x, y, z, terrain(x, y, z));
+            }
+            black_box(section)
+        })
+    });
+    c.bench_function("flat fill", |b| {
+        b.iter(|| {
+            let mut flat = FlatSection::new();
+            for (x, y, z) in coords() {
+                flat.set(x, y, z, terrain(x, y, z));
+            }
+            black_box(flat.blocks)
+        })
+    });
+    c.bench_function("palette read", |b| {
+        b.iter(|| {
+            coords()
+                .filter(|&(x, y, z)| section.get(x, y, z) == Block::Stone)
+                .count()
+        })
+    });
+    c.bench_function("flat read", |b| {
+        b.iter(|| {
+            coords()
+                .filter(|&(x, y, z)| flat.get(x, y, z) == Block::Stone)
+                .count()
+        })
+    });
+}
+
+criterion_group!(benches, bench_sections);
+criterion_main!(benches);
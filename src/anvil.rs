@@ -0,0 +1,528 @@
+#![allow(dead_code)]
+use anyhow::{anyhow, bail, Context, Result};
+use flate2::read::{GzDecoder, ZlibDecoder};
+use std::{collections::HashMap, fs, io::Read, path::Path};
+
+use crate::{
+    block::Block,
+    nbt::{self, Tag},
+    world::{Chunk, World, CHUNK_SIZE, SECTION_VOLUME, WORLD_HEIGHT},
+};
+
+// Minecraft Anvil region files: a table of 1024 (sector offset, sector count)
+// entries followed by timestamps, then chunks stored in 4 KiB sectors, each a
+// length, a compression byte and an NBT compound. Chunks from 1.13 onwards are
+// supported, blocks outside our world height are dropped.
+const SECTOR: usize = 4096;
+const CHUNKS_PER_REGION: usize = 1024;
+// 20w17a, the first version whose block state indices don't span two longs
+const NON_SPANNING_DATA_VERSION: i64 = 2529;
+
+// maps namespaced Minecraft block names to our blocks
+#[derive(Debug, Clone)]
+pub struct BlockMapping {
+    names: HashMap<String, Block>,
+    // used for every block that isn't mapped
+    pub fallback: Block,
+}
+
+impl Default for BlockMapping {
+    fn default() -> Self {
+        let mut mapping = Self {
+            names: HashMap::new(),
+            fallback: Block::Stone,
+        };
+        let known: &[(&[&str], Block)] = &[
+            (
+                &[
+                    "air",
+                    "cave_air",
+                    "void_air",
+                    // small decorations would be full cubes otherwise
+                    "dead_bush",
+                    "torch",
+                    "snow",
+                ],
+                Block::Air,
+            ),
+            (
+                &["grass", "short_grass", "tall_grass", "fern", "large_fern"],
+                Block::TallGrass,
+            ),
+            (
+                &[
+                    "dandelion",
+                    "poppy",
+                    "blue_orchid",
+                    "allium",
+                    "azure_bluet",
+                    "oxeye_daisy",
+                    "cornflower",
+                    "lily_of_the_valley",
+                    "red_tulip",
+                    "orange_tulip",
+                    "white_tulip",
+                    "pink_tulip",
+                ],
+                Block::Flower,
+            ),
+            (&["cactus"], Block::Cactus),
+            (&["grass_block", "mycelium"], Block::Grass),
+            (
+                &[
+                    "stone",
+                    "deepslate",
+                    "granite",
+                    "diorite",
+                    "andesite",
+                    "tuff",
+                ],
+                Block::Stone,
+            ),
+            (
+                &["cobblestone", "mossy_cobblestone", "stone_bricks"],
+                Block::Cobblestone,
+            ),
+            (&["dirt_path", "grass_path"], Block::Path),
+            (&["bedrock"], Block::Bedrock),
+            (&["coal_ore", "deepslate_coal_ore"], Block::CoalOre),
+            (&["iron_ore", "deepslate_iron_ore"], Block::IronOre),
+            (
+                &["gold_ore", "deepslate_gold_ore", "nether_gold_ore"],
+                Block::GoldOre,
+            ),
+            (&["diamond_ore", "deepslate_diamond_ore"], Block::DiamondOre),
+            (
+                &[
+                    "dirt",
+                    "coarse_dirt",
+                    "rooted_dirt",
+                    "podzol",
+                    "farmland",
+                    "mud",
+                ],
+                Block::Dirt,
+            ),
+            (
+                &["sand", "red_sand", "sandstone", "red_sandstone"],
+                Block::Sand,
+            ),
+            (&["glass", "glass_pane"], Block::Glass),
+            (
+                &[
+                    "water",
+                    "bubble_column",
+                    "seagrass",
+                    "tall_seagrass",
+                    "kelp",
+                    "kelp_plant",
+                ],
+                Block::Water,
+            ),
+            (
+                &["ice", "packed_ice", "blue_ice", "frosted_ice"],
+                Block::Ice,
+            ),
+        ];
+        for (names, block) in known {
+            for name in *names {
+                mapping.insert(&format!("minecraft:{}", name), *block);
+            }
+        }
+        mapping
+    }
+}
+
+impl BlockMapping {
+    // adds or overrides the block a full name such as "minecraft:stone" maps to
+    pub fn insert(&mut self, name: &str, block: Block) {
+        self.names.insert(name.to_string(), block);
+    }
+
+    pub fn block(&self, name: &str) -> Block {
+        if let Some(&block) = self.names.get(name) {
+            return block;
+        }
+        // whole families of wood, leaves and glass share a suffix
+        let name = name.strip_prefix("minecraft:").unwrap_or(name);
+        if name.ends_with("_stained_glass") || name.ends_with("_stained_glass_pane") {
+            Block::StainedGlass
+        } else if ["_log", "_wood", "_stem", "_hyphae"]
+            .iter()
+            .any(|suffix| name.ends_with(suffix))
+        {
+            Block::Log
+        } else if name.ends_with("_leaves") {
+            Block::Leaves
+        } else if name.ends_with("_planks") {
+            Block::Planks
+        } else {
+            self.fallback
+        }
+    }
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn decompress(compression: u8, data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    match compression {
+        1 => GzDecoder::new(data).read_to_end(&mut out)?,
+        2 => ZlibDecoder::new(data).read_to_end(&mut out)?,
+        3 => {
+            out.extend_from_slice(data);
+            0
+        }
+        _ => bail!("unsupported chunk compression {}", compression),
+    };
+    Ok(out)
+}
+
+// palette index of every block in a section. Older versions pack the indices
+// back to back so they can span two longs
+fn unpack_indices(data: &[i64], bits: usize, spanning: bool) -> Result<Vec<usize>> {
+    let mask = (1u64 << bits) - 1;
+    let per_long = 64 / bits;
+    let needed = if spanning {
+        (SECTION_VOLUME * bits).div_ceil(64)
+    } else {
+        SECTION_VOLUME.div_ceil(per_long)
+    };
+    if data.len() < needed {
+        bail!("block state array too short");
+    }
+    Ok((0..SECTION_VOLUME)
+        .map(|i| {
+            let value = if spanning {
+                let bit = i * bits;
+                let (long, shift) = (bit / 64, bit % 64);
+                let mut value = data[long] as u64 >> shift;
+                if shift + bits > 64 {
+                    value |= (data[long + 1] as u64) << (64 - shift);
+                }
+                value
+            } else {
+                data[i / per_long] as u64 >> ((i % per_long) * bits)
+            };
+            (value & mask) as usize
+        })
+        .collect())
+}
+
+fn import_section(
+    chunk: &mut Chunk,
+    section: &Tag,
+    data_version: i64,
+    mapping: &BlockMapping,
+) -> Result<()> {
+    let Some(section_y) = section.get("Y").and_then(Tag::as_i64) else {
+        return Ok(());
+    };
+    // 1.18 moved the palette and indices into a block_states compound
+    let (palette, states) = match section.get("block_states") {
+        Some(states) => (states.get("palette"), states.get("data")),
+        None => (section.get("Palette"), section.get("BlockStates")),
+    };
+    let Some(palette) = palette.and_then(Tag::as_list) else {
+        // sections without blocks, e.g. only holding light data
+        return Ok(());
+    };
+    let palette = palette
+        .iter()
+        .map(|state| {
+            state
+                .get("Name")
+                .and_then(Tag::as_str)
+                .map(|name| mapping.block(name))
+                .ok_or_else(|| anyhow!("block state without a name"))
+        })
+        .collect::<Result<Vec<Block>>>()?;
+    if palette.is_empty() {
+        return Ok(());
+    }
+
+    let indices = match states.and_then(Tag::as_long_array) {
+        Some(data) if palette.len() > 1 => {
+            let bits = (usize::BITS - (palette.len() - 1).leading_zeros()).max(4) as usize;
+            unpack_indices(data, bits, data_version < NON_SPANNING_DATA_VERSION)?
+        }
+        // a single entry palette fills the whole section
+        _ => vec![0; SECTION_VOLUME],
+    };
+    for (i, &index) in indices.iter().enumerate() {
+        let block = *palette
+            .get(index)
+            .ok_or_else(|| anyhow!("block state index out of range"))?;
+        let y = section_y * CHUNK_SIZE as i64 + (i / (CHUNK_SIZE * CHUNK_SIZE)) as i64;
+        if block == Block::Air || y < 0 || y >= WORLD_HEIGHT as i64 {
+            continue;
+        }
+        let z = (i / CHUNK_SIZE) % CHUNK_SIZE;
+        let x = i % CHUNK_SIZE;
+        chunk.set(x, y as usize, z, block);
+    }
+    Ok(())
+}
+
+fn import_chunk(root: &Tag, world: &mut World, mapping: &BlockMapping) -> Result<()> {
+    let data_version = root
+        .get("DataVersion")
+        .and_then(Tag::as_i64)
+        .unwrap_or_default();
+    // before 1.18 everything lives in a Level compound
+    let level = root.get("Level").unwrap_or(root);
+    let position = |key| level.get(key).and_then(Tag::as_i64);
+    let (Some(cx), Some(cz)) = (position("xPos"), position("zPos")) else {
+        bail!("chunk without a position");
+    };
+    let sections = level
+        .get("sections")
+        .or_else(|| level.get("Sections"))
+        .and_then(Tag::as_list)
+        .unwrap_or_default();
+    if sections.is_empty() && level.get("Blocks").is_some() {
+        bail!("pre-1.13 chunks with numeric block ids aren't supported");
+    }
+
+    let mut chunk = Chunk::new();
+    for section in sections {
+        import_section(&mut chunk, section, data_version, mapping)?;
+    }
+    world.insert_chunk([cx as i32, cz as i32], chunk);
+    Ok(())
+}
+
+// loads every chunk of one .mca file into the world, returning how many were imported
+pub fn import_region(path: &Path, world: &mut World, mapping: &BlockMapping) -> Result<usize> {
+    let data = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    // the game leaves empty region files behind
+    if data.is_empty() {
+        return Ok(0);
+    }
+    if data.len() < 2 * SECTOR {
+        bail!("{} is too short to be a region file", path.display());
+    }
+
+    let mut imported = 0;
+    for i in 0..CHUNKS_PER_REGION {
+        let entry = be_u32(&data[i * 4..]);
+        let offset = (entry >> 8) as usize * SECTOR;
+        if offset == 0 {
+            continue;
+        }
+        let chunk = || -> Result<Tag> {
+            let header = data
+                .get(offset..offset + 5)
+                .ok_or_else(|| anyhow!("chunk offset out of bounds"))?;
+            let length = be_u32(header) as usize;
+            let compression = header[4];
+            if compression & 0x80 != 0 {
+                bail!("chunks stored in external .mcc files aren't supported");
+            }
+            let payload = data
+                .get(offset + 5..offset + 4 + length)
+                .ok_or_else(|| anyhow!("chunk data out of bounds"))?;
+            Ok(nbt::read(&decompress(compression, payload)?)?.1)
+        };
+        let root = chunk().with_context(|| format!("chunk {} in {}", i, path.display()))?;
+        import_chunk(&root, world, mapping)
+            .with_context(|| format!("chunk {} in {}", i, path.display()))?;
+        imported += 1;
+    }
+    Ok(imported)
+}
+
+// imports a single .mca file or every .mca file in a directory such as a
+// save's region folder
+pub fn import(path: &Path, world: &mut World, mapping: &BlockMapping) -> Result<usize> {
+    if !path.is_dir() {
+        return import_region(path, world, mapping);
+    }
+    let mut imported = 0;
+    let entries =
+        fs::read_dir(path).with_context(|| format!("failed to read {}", path.display()))?;
+    for entry in entries {
+        let file = entry?.path();
+        if file.extension().is_some_and(|ext| ext == "mca") {
+            imported += import_region(&file, world, mapping)?;
+        }
+    }
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::World;
+
+    // written by tests/fixtures/make_region.py
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/r.0.0.mca");
+    const MODERN: [&str; 5] = ["air", "stone", "grass_block", "oak_log", "amethyst_block"];
+    const LEGACY: [&str; 17] = [
+        "air",
+        "dirt",
+        "sand",
+        "birch_leaves",
+        "white_stained_glass",
+        "spruce_planks",
+        "coal_ore",
+        "iron_ore",
+        "gold_ore",
+        "diamond_ore",
+        "bedrock",
+        "water",
+        "ice",
+        "cactus",
+        "poppy",
+        "cobblestone",
+        "dirt_path",
+    ];
+
+    // the palette index the fixture stores at a section index
+    fn pattern(i: usize, count: usize) -> usize {
+        let (x, z, y) = (i % 16, (i / 16) % 16, i / 256);
+        (x + 2 * z + 3 * y) % count
+    }
+
+    #[test]
+    fn unpack_non_spanning() {
+        // 12 five bit indices to a long, the top 4 bits unused
+        let mut data = vec![0i64; SECTION_VOLUME.div_ceil(12)];
+        data[0] = (31 << 55) | (0b1111 << 60) | 3;
+        data[1] = 1;
+        let indices = unpack_indices(&data, 5, false).unwrap();
+        assert_eq!(indices[0], 3);
+        assert_eq!(indices[11], 31);
+        assert_eq!(indices[12], 1);
+        assert!(indices[13..].iter().all(|&i| i == 0));
+    }
+
+    #[test]
+    fn unpack_spanning() {
+        // index 12 starts at bit 60 and runs into the next long
+        let mut data = vec![0i64; (SECTION_VOLUME * 5).div_ceil(64)];
+        data[0] = (31 << 55) | (0b1111 << 60) | 3;
+        data[1] = 1;
+        let indices = unpack_indices(&data, 5, true).unwrap();
+        assert_eq!(indices[0], 3);
+        assert_eq!(indices[11], 31);
+        assert_eq!(indices[12], 31);
+        assert!(indices[13..].iter().all(|&i| i == 0));
+    }
+
+    #[test]
+    fn unpack_every_value() {
+        for bits in 4..=12 {
+            let values: Vec<usize> = (0..SECTION_VOLUME).map(|i| (i * 7) % (1 << bits)).collect();
+            for spanning in [false, true] {
+                let per_long = 64 / bits;
+                // enough for either packing
+                let mut data = vec![0u64; SECTION_VOLUME.div_ceil(per_long)];
+                for (i, &value) in values.iter().enumerate() {
+                    let value = value as u64;
+                    if spanning {
+                        let bit = i * bits;
+                        data[bit / 64] |= value << (bit % 64);
+                        if bit % 64 + bits > 64 {
+                            data[bit / 64 + 1] |= value >> (64 - bit % 64);
+                        }
+                    } else {
+                        data[i / per_long] |= value << ((i % per_long) * bits);
+                    }
+                }
+                let data: Vec<i64> = data.iter().map(|&v| v as i64).collect();
+                assert_eq!(unpack_indices(&data, bits, spanning).unwrap(), values);
+            }
+        }
+    }
+
+    #[test]
+    fn unpack_short_data_is_an_error() {
+        assert!(unpack_indices(&[0; 255], 4, false).is_err());
+        assert!(unpack_indices(&[0; 319], 5, true).is_err());
+        assert!(unpack_indices(&[0; 320], 5, true).is_ok());
+    }
+
+    #[test]
+    fn fallback_mapping() {
+        let mut mapping = BlockMapping::default();
+        assert_eq!(mapping.block("minecraft:stone"), Block::Stone);
+        assert_eq!(mapping.block("minecraft:cave_air"), Block::Air);
+        // families by suffix
+        assert_eq!(mapping.block("minecraft:dark_oak_log"), Block::Log);
+        assert_eq!(mapping.block("minecraft:crimson_hyphae"), Block::Log);
+        assert_eq!(mapping.block("minecraft:jungle_leaves"), Block::Leaves);
+        assert_eq!(mapping.block("minecraft:acacia_planks"), Block::Planks);
+        assert_eq!(
+            mapping.block("minecraft:red_stained_glass_pane"),
+            Block::StainedGlass
+        );
+        // everything else takes the fallback
+        assert_eq!(mapping.block("minecraft:amethyst_block"), Block::Stone);
+        assert_eq!(mapping.block("somemod:machine"), Block::Stone);
+        mapping.fallback = Block::Air;
+        assert_eq!(mapping.block("minecraft:amethyst_block"), Block::Air);
+        // and inserted names override both
+        mapping.insert("minecraft:amethyst_block", Block::Glass);
+        mapping.insert("minecraft:oak_log", Block::Planks);
+        assert_eq!(mapping.block("minecraft:amethyst_block"), Block::Glass);
+        assert_eq!(mapping.block("minecraft:oak_log"), Block::Planks);
+    }
+
+    #[test]
+    fn import_fixture_region() {
+        let mut world = World::new(0);
+        let mapping = BlockMapping::default();
+        let imported = import_region(Path::new(FIXTURE), &mut world, &mapping).unwrap();
+        assert_eq!(imported, 2);
+        assert_eq!(world.chunks().count(), 2);
+
+        // chunk 0, 0: the 1.18 layout, its section at Y 1
+        let name = |names: &[&str], i| format!("minecraft:{}", names[i]);
+        for i in 0..SECTION_VOLUME {
+            let (x, z, y) = (i % 16, (i / 16) % 16, i / 256);
+            let block = mapping.block(&name(&MODERN, pattern(i, MODERN.len())));
+            assert_eq!(world.get_block(x as i32, 16 + y as i32, z as i32), block);
+        }
+        // the section at Y -1 is below the world, nothing else was filled
+        assert_eq!(world.get_block(0, 0, 0), Block::Air);
+        assert_eq!(world.get_block(0, 40, 0), Block::Air);
+
+        // chunk 1, 0: pre-1.16, gzip compressed, indices spanning longs
+        for i in 0..SECTION_VOLUME {
+            let (x, z, y) = (i % 16, (i / 16) % 16, i / 256);
+            let block = mapping.block(&name(&LEGACY, pattern(i, LEGACY.len())));
+            assert_eq!(
+                world.get_block(16 + x as i32, 32 + y as i32, z as i32),
+                block
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_regions() {
+        let dir = std::env::temp_dir().join(format!("anvil-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut world = World::new(0);
+        let mapping = BlockMapping::default();
+
+        // the game leaves empty files behind
+        let empty = dir.join("r.5.5.mca");
+        fs::write(&empty, []).unwrap();
+        assert_eq!(import_region(&empty, &mut world, &mapping).unwrap(), 0);
+
+        let short = dir.join("r.6.6.mca");
+        fs::write(&short, [0; 100]).unwrap();
+        assert!(import_region(&short, &mut world, &mapping).is_err());
+
+        // a chunk pointing past the end of the file
+        let mut data = fs::read(FIXTURE).unwrap();
+        data.truncate(2 * SECTOR + 100);
+        let truncated = dir.join("r.7.7.mca");
+        fs::write(&truncated, &data).unwrap();
+        assert!(import_region(&truncated, &mut world, &mapping).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
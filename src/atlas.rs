@@ -0,0 +1,188 @@
+#![allow(dead_code)]
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use std::ops::Range;
+
+pub const TILE_SIZE: u32 = 64;
+
+// tiles are laid out left to right in this order
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Tile {
+    Grass,
+    Solid,
+    Glass,
+    Leaves,
+    StainedGlass,
+    Liquid,
+    Ore,
+    Plant,
+    Flower,
+}
+
+pub const TILE_COUNT: u32 = 9;
+
+// maps a face uv in [0, 1] into the tile, inset by half a texel so linear
+// filtering doesn't bleed into the neighbouring tile
+pub fn tile_uv(tile: Tile, uv: [f32; 2]) -> [f32; 2] {
+    let inset = 0.5 / TILE_SIZE as f32;
+    let u = inset + uv[0] * (1.0 - 2.0 * inset);
+    let v = inset + uv[1] * (1.0 - 2.0 * inset);
+    [(tile as u32 as f32 + u) / TILE_COUNT as f32, v]
+}
+
+fn hash(x: u32, y: u32, seed: u32) -> u32 {
+    let mut h = x
+        .wrapping_mul(374_761_393)
+        .wrapping_add(y.wrapping_mul(668_265_263))
+        .wrapping_add(seed.wrapping_mul(2_246_822_519));
+    h = (h ^ (h >> 13)).wrapping_mul(1_274_126_177);
+    h ^ (h >> 16)
+}
+
+// brightness jitter in [lo, hi] so flat tiles don't look like plastic
+fn grain(x: u32, y: u32, seed: u32, lo: f32, hi: f32) -> u8 {
+    let t = (hash(x, y, seed) & 0xff) as f32 / 255.0;
+    ((lo + (hi - lo) * t) * 255.0) as u8
+}
+
+fn on_frame(x: u32, y: u32, width: u32) -> bool {
+    x < width || y < width || x >= TILE_SIZE - width || y >= TILE_SIZE - width
+}
+
+fn tile_pixel(tile: Tile, x: u32, y: u32) -> Rgba<u8> {
+    match tile {
+        Tile::Grass => Rgba([255, 255, 255, 255]),
+        Tile::Solid => {
+            let g = grain(x, y, 1, 0.8, 1.0);
+            Rgba([g, g, g, 255])
+        }
+        Tile::Glass => {
+            if on_frame(x, y, 3) {
+                Rgba([220, 235, 240, 255])
+            } else if (x + TILE_SIZE - y).is_multiple_of(24) && x > 8 && x < 40 {
+                // a few streaks so the glass reads as glass
+                Rgba([240, 250, 255, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        }
+        Tile::Leaves => {
+            let g = grain(x / 4, y / 4, 2, 0.6, 1.0);
+            let alpha = if hash(x / 4, y / 4, 3).is_multiple_of(5) {
+                0
+            } else {
+                255
+            };
+            Rgba([g, g, g, alpha])
+        }
+        Tile::StainedGlass => {
+            if on_frame(x, y, 3) {
+                Rgba([255, 255, 255, 230])
+            } else {
+                Rgba([255, 255, 255, 110])
+            }
+        }
+        Tile::Liquid => {
+            let g = grain(x / 2, y / 2, 4, 0.85, 1.0);
+            Rgba([g, g, g, 255])
+        }
+        Tile::Ore => {
+            // clusters of bright specks on darker stone
+            if hash(x / 6, y / 6, 5).is_multiple_of(4) && !hash(x / 2, y / 2, 6).is_multiple_of(3) {
+                let g = grain(x / 2, y / 2, 7, 0.85, 1.0);
+                Rgba([g, g, g, 255])
+            } else {
+                let g = grain(x, y, 1, 0.45, 0.6);
+                Rgba([g, g, g, 255])
+            }
+        }
+        Tile::Plant => {
+            // blades of different heights, leaning a little, with gaps between them
+            let blade = x / 4;
+            let height = TILE_SIZE / 3 + hash(blade, 0, 8) % (TILE_SIZE * 2 / 3);
+            let lean = (TILE_SIZE - y) / 16 * (hash(blade, 1, 8) % 2);
+            let inside = (x + lean) % 4 < 2;
+            if inside && y >= TILE_SIZE - height {
+                let g = grain(x, y / 4, 9, 0.7, 1.0);
+                Rgba([g, g, g, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        }
+        Tile::Flower => {
+            let (dx, dy) = (x as i32 - 32, y as i32 - 22);
+            let petal = dx * dx + dy * dy;
+            if petal < 5 * 5 {
+                Rgba([240, 200, 40, 255])
+            } else if petal < 12 * 12 {
+                Rgba([200, 30, 35, 255])
+            } else if (30..34).contains(&x) && y > 22 {
+                Rgba([60, 140, 40, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        }
+    }
+}
+
+// builds the block texture atlas: the grass texture followed by procedurally drawn tiles
+pub fn build(grass: &DynamicImage) -> RgbaImage {
+    let mut atlas = RgbaImage::new(TILE_SIZE * TILE_COUNT, TILE_SIZE);
+    let tiles = [
+        Tile::Grass,
+        Tile::Solid,
+        Tile::Glass,
+        Tile::Leaves,
+        Tile::StainedGlass,
+        Tile::Liquid,
+        Tile::Ore,
+        Tile::Plant,
+        Tile::Flower,
+    ];
+    for tile in tiles {
+        let x0 = tile as u32 * TILE_SIZE;
+        for y in 0..TILE_SIZE {
+            for x in 0..TILE_SIZE {
+                atlas.put_pixel(x0 + x, y, tile_pixel(tile, x, y));
+            }
+        }
+    }
+
+    let grass = imageops::resize(
+        &grass.to_rgba8(),
+        TILE_SIZE,
+        TILE_SIZE,
+        imageops::FilterType::Triangle,
+    );
+    imageops::replace(&mut atlas, &grass, 0, 0);
+    atlas
+}
+
+// the mean colour of the visible texels in some rows of a tile, for drawing a
+// block without its texture
+pub fn average_color(atlas: &RgbaImage, tile: Tile, rows: Range<u32>) -> [f32; 3] {
+    let x0 = tile as u32 * TILE_SIZE;
+    let mut sum = [0.0; 3];
+    let mut count = 0.0;
+    for y in rows {
+        for x in x0..x0 + TILE_SIZE {
+            let pixel = atlas.get_pixel(x, y);
+            if pixel[3] == 0 {
+                continue;
+            }
+            for (s, channel) in sum.iter_mut().zip(pixel.0) {
+                *s += channel as f32 / 255.0;
+            }
+            count += 1.0;
+        }
+    }
+    match count > 0.0 {
+        true => sum.map(|s| s / count),
+        false => [1.0; 3],
+    }
+}
+
+// the atlas the renderer and exporters use
+pub fn block_atlas() -> RgbaImage {
+    let grass = image::load_from_memory(include_bytes!("grass_block.jpg")).unwrap();
+    build(&grass)
+}
@@ -0,0 +1,227 @@
+#![allow(dead_code)]
+use crate::common::{Mesh, Vertex};
+
+// about how many ticks the longest axis gets, shorter ones get fewer. nice_ticks
+// picks the nearest round step
+const TARGET_TICKS: usize = 5;
+// tick marks are this fraction of the longest side of the box
+const TICK_LENGTH: f32 = 0.03;
+const BOX_COLOR: [f32; 3] = [0.1, 0.1, 0.1];
+const GRID_COLOR: [f32; 3] = [0.35, 0.35, 0.35];
+const AXIS_NAMES: [&str; 3] = ["x", "y", "z"];
+
+// where a plot sits in the world, min to max, and the data values its corners
+// stand for before the surface was scaled or normalized
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlotBox {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    pub data_min: [f32; 3],
+    pub data_max: [f32; 3],
+}
+
+impl PlotBox {
+    pub fn translate(&mut self, offset: [f32; 3]) {
+        for (axis, d) in offset.into_iter().enumerate() {
+            self.min[axis] += d;
+            self.max[axis] += d;
+        }
+    }
+
+    // the world coordinate of a data value along an axis
+    pub fn world(&self, axis: usize, value: f32) -> f32 {
+        let (dmin, dmax) = (self.data_min[axis], self.data_max[axis]);
+        match dmax > dmin {
+            true => {
+                self.min[axis] + (value - dmin) / (dmax - dmin) * (self.max[axis] - self.min[axis])
+            }
+            false => self.min[axis],
+        }
+    }
+}
+
+// text pinned to a point in the world
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub position: [f32; 3],
+    pub text: String,
+}
+
+// round values from min to max about count of them apart, 1, 2 or 5 times a
+// power of ten, and the step between them. An empty range has just min
+pub fn nice_ticks(min: f32, max: f32, count: usize) -> (Vec<f32>, f32) {
+    let range = max - min;
+    if !range.is_finite() || range <= 0.0 {
+        return (vec![min], 1.0);
+    }
+    let raw = range / count.max(1) as f32;
+    let magnitude = 10f32.powf(raw.log10().floor());
+    let step = magnitude
+        * match raw / magnitude {
+            n if n < 1.5 => 1.0,
+            n if n < 3.0 => 2.0,
+            n if n < 7.0 => 5.0,
+            _ => 10.0,
+        };
+    let first = (min / step).ceil() as i64;
+    let last = (max / step + 1e-4).floor() as i64;
+    ((first..=last).map(|i| i as f32 * step).collect(), step)
+}
+
+// a tick value with as many decimals as the step needs, 0 never shown as -0
+pub fn tick_label(value: f32, step: f32) -> String {
+    let decimals = (-step.log10().floor()).clamp(0.0, 6.0) as usize;
+    let value = match value.abs() < step * 1e-3 {
+        true => 0.0,
+        false => value,
+    };
+    format!("{:.*}", decimals, value)
+}
+
+// the walls behind a plot seen from +z: the floor, the back wall at z min and
+// the left wall at x min, ruled at every tick, with tick marks and values along
+// the front edge of the floor (x), its right edge (z) and the front left
+// upright (y). The mesh is a line list
+pub fn axes(plot: &PlotBox) -> (Mesh, Vec<Label>) {
+    let [x0, y0, z0] = plot.min;
+    let [x1, y1, z1] = plot.max;
+    let longest = (0..3)
+        .map(|axis| plot.max[axis] - plot.min[axis])
+        .fold(0.0, f32::max);
+    let tick = TICK_LENGTH * longest;
+
+    let mut mesh = Mesh::default();
+    let mut line = |a: [f32; 3], b: [f32; 3], color: [f32; 3]| {
+        let start = mesh.vertices.len() as u32;
+        for p in [a, b] {
+            mesh.vertices.push(Vertex {
+                position: [p[0], p[1], p[2], 1.0],
+                normal: [0.0, 1.0, 0.0, 1.0],
+                color: [color[0], color[1], color[2], 1.0],
+                tex_coords: [0.0, 0.0],
+            });
+        }
+        mesh.indices.extend([start, start + 1]);
+    };
+    let mut labels = Vec::new();
+
+    for axis in 0..3 {
+        let side = plot.max[axis] - plot.min[axis];
+        let count = (TARGET_TICKS as f32 * side / longest).round() as usize;
+        let (ticks, step) = nice_ticks(plot.data_min[axis], plot.data_max[axis], count.max(2));
+        for value in ticks {
+            let w = plot.world(axis, value);
+            // gridlines across the two walls this axis runs along, then the
+            // tick mark and where its value goes
+            let (grid, mark, text) = match axis {
+                0 => (
+                    [([w, y0, z0], [w, y0, z1]), ([w, y0, z0], [w, y1, z0])],
+                    ([w, y0, z1], [w, y0, z1 + tick]),
+                    [w, y0, z1 + 3.0 * tick],
+                ),
+                1 => (
+                    [([x0, w, z0], [x1, w, z0]), ([x0, w, z0], [x0, w, z1])],
+                    ([x0, w, z1], [x0 - tick, w, z1]),
+                    [x0 - 3.0 * tick, w, z1],
+                ),
+                _ => (
+                    [([x0, y0, w], [x1, y0, w]), ([x0, y0, w], [x0, y1, w])],
+                    ([x1, y0, w], [x1 + tick, y0, w]),
+                    [x1 + 3.0 * tick, y0, w],
+                ),
+            };
+            for (a, b) in grid {
+                line(a, b, GRID_COLOR);
+            }
+            line(mark.0, mark.1, BOX_COLOR);
+            labels.push(Label {
+                position: text,
+                text: tick_label(value, step),
+            });
+        }
+    }
+
+    // the outlines of the three walls
+    for (a, b) in [
+        ([x0, y0, z0], [x1, y0, z0]),
+        ([x0, y0, z1], [x1, y0, z1]),
+        ([x0, y1, z0], [x1, y1, z0]),
+        ([x0, y0, z0], [x0, y1, z0]),
+        ([x1, y0, z0], [x1, y1, z0]),
+        ([x0, y0, z1], [x0, y1, z1]),
+        ([x0, y0, z0], [x0, y0, z1]),
+        ([x1, y0, z0], [x1, y0, z1]),
+        ([x0, y1, z0], [x0, y1, z1]),
+    ] {
+        line(a, b, BOX_COLOR);
+    }
+
+    // axis names beyond the tick values, halfway along
+    let (xm, ym, zm) = ((x0 + x1) / 2.0, (y0 + y1) / 2.0, (z0 + z1) / 2.0);
+    for (name, position) in AXIS_NAMES.iter().zip([
+        [xm, y0, z1 + 6.0 * tick],
+        [x0 - 7.0 * tick, ym, z1],
+        [x1 + 6.0 * tick, y0, zm],
+    ]) {
+        labels.push(Label {
+            position,
+            text: name.to_string(),
+        });
+    }
+    (mesh, labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_ticks(ticks: (Vec<f32>, f32), expected: &[f32], step: f32) {
+        assert!(
+            (ticks.1 - step).abs() < 1e-6,
+            "step {} not {}",
+            ticks.1,
+            step
+        );
+        assert_eq!(ticks.0.len(), expected.len(), "{:?}", ticks.0);
+        for (tick, expected) in ticks.0.iter().zip(expected) {
+            assert!((tick - expected).abs() < 1e-5, "{:?}", ticks.0);
+        }
+    }
+
+    #[test]
+    fn ticks() {
+        assert_ticks(
+            nice_ticks(0.0, 1.0, 5),
+            &[0.0, 0.2, 0.4, 0.6, 0.8, 1.0],
+            0.2,
+        );
+        assert_ticks(
+            nice_ticks(-7.5, -2.2, 4),
+            &[-7.0, -6.0, -5.0, -4.0, -3.0],
+            1.0,
+        );
+        assert_ticks(nice_ticks(-0.03, -0.01, 2), &[-0.03, -0.02, -0.01], 0.01);
+        assert_ticks(
+            nice_ticks(-3.0, 3.0, 5),
+            &[-3.0, -2.0, -1.0, 0.0, 1.0, 2.0, 3.0],
+            1.0,
+        );
+        assert_ticks(nice_ticks(-3.0, 3.0, 3), &[-2.0, 0.0, 2.0], 2.0);
+        assert_ticks(nice_ticks(0.0, 1000.0, 2), &[0.0, 500.0, 1000.0], 500.0);
+        // empty and broken ranges
+        assert_ticks(nice_ticks(2.0, 2.0, 5), &[2.0], 1.0);
+        assert_ticks(nice_ticks(3.0, 1.0, 5), &[3.0], 1.0);
+        assert_eq!(nice_ticks(0.0, f32::NAN, 5).0.len(), 1);
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(tick_label(0.6000001, 0.2), "0.6");
+        assert_eq!(tick_label(-3.0, 1.0), "-3");
+        assert_eq!(tick_label(1500.0, 500.0), "1500");
+        assert_eq!(tick_label(-0.02, 0.01), "-0.02");
+        // no -0 from rounding errors around zero
+        assert_eq!(tick_label(-1e-8, 0.2), "0.0");
+        assert_eq!(tick_label(-0.0, 1.0), "0");
+    }
+}
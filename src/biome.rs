@@ -0,0 +1,132 @@
+#![allow(dead_code)]
+use crate::block::Block;
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Biome {
+    #[default]
+    Plains,
+    Desert,
+    Forest,
+    Taiga,
+    Mountains,
+    Ocean,
+    Swamp,
+}
+
+impl Biome {
+    // every biome, indexed by its id
+    pub const ALL: [Biome; 7] = [
+        Biome::Plains,
+        Biome::Desert,
+        Biome::Forest,
+        Biome::Taiga,
+        Biome::Mountains,
+        Biome::Ocean,
+        Biome::Swamp,
+    ];
+
+    // numeric id used in save files, new biomes must be appended
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<Biome> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Biome::Plains => "plains",
+            Biome::Desert => "desert",
+            Biome::Forest => "forest",
+            Biome::Taiga => "taiga",
+            Biome::Mountains => "mountains",
+            Biome::Ocean => "ocean",
+            Biome::Swamp => "swamp",
+        }
+    }
+
+    // land biome for a temperature and humidity in [-1, 1]. Oceans and mountains
+    // come from how far inland a column is instead, see TerrainGenerator
+    pub fn from_climate(temperature: f64, humidity: f64) -> Biome {
+        match (temperature, humidity) {
+            (t, _) if t < -0.3 => Biome::Taiga,
+            (t, h) if t > 0.35 && h < 0.0 => Biome::Desert,
+            (_, h) if h > 0.45 => Biome::Swamp,
+            (_, h) if h > 0.0 => Biome::Forest,
+            _ => Biome::Plains,
+        }
+    }
+
+    // average terrain height above or below sea level
+    pub fn base_height(self) -> f32 {
+        match self {
+            Biome::Ocean => -18.0,
+            Biome::Swamp => 1.0,
+            Biome::Desert => 5.0,
+            Biome::Plains => 6.0,
+            Biome::Forest | Biome::Taiga => 9.0,
+            Biome::Mountains => 38.0,
+        }
+    }
+
+    // how far hills rise and fall around the base height
+    pub fn height_variation(self) -> f32 {
+        match self {
+            Biome::Swamp => 2.0,
+            Biome::Plains | Biome::Desert => 5.0,
+            Biome::Ocean => 8.0,
+            Biome::Forest | Biome::Taiga => 10.0,
+            Biome::Mountains => 32.0,
+        }
+    }
+
+    pub fn surface(self) -> Block {
+        match self {
+            Biome::Desert => Block::Sand,
+            Biome::Ocean => Block::Sand,
+            Biome::Swamp => Block::Dirt,
+            _ => Block::Grass,
+        }
+    }
+
+    // the few blocks between the surface and stone
+    pub fn subsurface(self) -> Block {
+        match self {
+            Biome::Desert | Biome::Ocean => Block::Sand,
+            _ => Block::Dirt,
+        }
+    }
+
+    // water at sea level freezes over
+    pub fn is_frozen(self) -> bool {
+        self == Biome::Taiga
+    }
+
+    // multiplied into the top of grass blocks
+    pub fn grass_color(self) -> [f32; 3] {
+        match self {
+            Biome::Plains => [0.95, 1.0, 0.8],
+            Biome::Desert => [1.1, 1.0, 0.65],
+            Biome::Forest => [0.8, 1.0, 0.7],
+            Biome::Taiga => [0.75, 0.9, 0.85],
+            Biome::Mountains => [0.8, 0.9, 0.85],
+            Biome::Ocean => [0.85, 1.0, 0.8],
+            Biome::Swamp => [0.7, 0.75, 0.5],
+        }
+    }
+
+    // multiplied into leaves
+    pub fn foliage_color(self) -> [f32; 3] {
+        match self {
+            Biome::Plains => [1.0, 1.0, 0.9],
+            Biome::Desert => [1.15, 1.05, 0.7],
+            Biome::Forest => [0.85, 1.0, 0.8],
+            Biome::Taiga => [0.7, 0.85, 0.85],
+            Biome::Mountains => [0.75, 0.9, 0.85],
+            Biome::Ocean => [0.9, 1.0, 0.85],
+            Biome::Swamp => [0.75, 0.8, 0.5],
+        }
+    }
+}
@@ -0,0 +1,188 @@
+#![allow(dead_code)]
+use crate::atlas::Tile;
+
+#[repr(u16)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Block {
+    #[default]
+    Air,
+    Grass,
+    Dirt,
+    Stone,
+    Sand,
+    Log,
+    Leaves,
+    // clear glass, rendered like a pane: opaque frame and see-through center
+    Glass,
+    StainedGlass,
+    Water,
+    Ice,
+    // the unbreakable floor of generated worlds
+    Bedrock,
+    CoalOre,
+    IronOre,
+    GoldOre,
+    DiamondOre,
+    // plants are drawn as two crossed quads instead of a cube
+    TallGrass,
+    Flower,
+    Cactus,
+    // building blocks for generated structures
+    Planks,
+    Cobblestone,
+    Path,
+}
+
+// which pass a block is drawn in
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RenderLayer {
+    Opaque,
+    // alpha tested in the shader, still writes depth
+    Cutout,
+    // alpha blended, sorted back to front and drawn without depth writes
+    Translucent,
+    // translucent too, but with its own animated shader
+    Water,
+}
+
+impl Block {
+    // every block, indexed by its id
+    pub const ALL: [Block; 22] = [
+        Block::Air,
+        Block::Grass,
+        Block::Dirt,
+        Block::Stone,
+        Block::Sand,
+        Block::Log,
+        Block::Leaves,
+        Block::Glass,
+        Block::StainedGlass,
+        Block::Water,
+        Block::Ice,
+        Block::Bedrock,
+        Block::CoalOre,
+        Block::IronOre,
+        Block::GoldOre,
+        Block::DiamondOre,
+        Block::TallGrass,
+        Block::Flower,
+        Block::Cactus,
+        Block::Planks,
+        Block::Cobblestone,
+        Block::Path,
+    ];
+
+    // numeric id used in save files, new blocks must be appended to keep old saves valid
+    pub fn id(self) -> u16 {
+        self as u16
+    }
+
+    pub fn from_id(id: u16) -> Option<Block> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    // lowercase name used on the command line and in import mappings
+    pub fn name(self) -> &'static str {
+        match self {
+            Block::Air => "air",
+            Block::Grass => "grass",
+            Block::Dirt => "dirt",
+            Block::Stone => "stone",
+            Block::Sand => "sand",
+            Block::Log => "log",
+            Block::Leaves => "leaves",
+            Block::Glass => "glass",
+            Block::StainedGlass => "stained_glass",
+            Block::Water => "water",
+            Block::Ice => "ice",
+            Block::Bedrock => "bedrock",
+            Block::CoalOre => "coal_ore",
+            Block::IronOre => "iron_ore",
+            Block::GoldOre => "gold_ore",
+            Block::DiamondOre => "diamond_ore",
+            Block::TallGrass => "tall_grass",
+            Block::Flower => "flower",
+            Block::Cactus => "cactus",
+            Block::Planks => "planks",
+            Block::Cobblestone => "cobblestone",
+            Block::Path => "path",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Block> {
+        Self::ALL.into_iter().find(|block| block.name() == name)
+    }
+
+    pub fn render_layer(self) -> Option<RenderLayer> {
+        match self {
+            Block::Air => None,
+            Block::Leaves | Block::Glass | Block::TallGrass | Block::Flower => {
+                Some(RenderLayer::Cutout)
+            }
+            Block::StainedGlass | Block::Ice => Some(RenderLayer::Translucent),
+            Block::Water => Some(RenderLayer::Water),
+            _ => Some(RenderLayer::Opaque),
+        }
+    }
+
+    pub fn is_opaque(self) -> bool {
+        self.render_layer() == Some(RenderLayer::Opaque)
+    }
+
+    pub fn is_plant(self) -> bool {
+        matches!(self, Block::TallGrass | Block::Flower)
+    }
+
+    // blocks that generated features may overwrite
+    pub fn is_replaceable(self) -> bool {
+        self == Block::Air || self.is_plant()
+    }
+
+    // a face is hidden behind opaque blocks and between two blocks of the same kind,
+    // so the inside of a lake or a glass wall isn't drawn
+    pub fn face_visible(self, neighbor: Block) -> bool {
+        !(neighbor.is_opaque() || neighbor == self)
+    }
+
+    pub fn tile(self) -> Tile {
+        match self {
+            Block::Grass => Tile::Grass,
+            Block::Leaves => Tile::Leaves,
+            Block::Glass => Tile::Glass,
+            Block::StainedGlass => Tile::StainedGlass,
+            Block::Water | Block::Ice => Tile::Liquid,
+            Block::CoalOre | Block::IronOre | Block::GoldOre | Block::DiamondOre => Tile::Ore,
+            Block::TallGrass => Tile::Plant,
+            Block::Flower => Tile::Flower,
+            _ => Tile::Solid,
+        }
+    }
+
+    // tint multiplied with the atlas tile, alpha included
+    pub fn color(self) -> [f32; 4] {
+        match self {
+            Block::Air | Block::Grass | Block::Glass => [1.0, 1.0, 1.0, 1.0],
+            Block::Dirt => [0.55, 0.38, 0.25, 1.0],
+            Block::Stone => [0.5, 0.5, 0.5, 1.0],
+            Block::Sand => [0.86, 0.8, 0.55, 1.0],
+            Block::Log => [0.45, 0.32, 0.18, 1.0],
+            Block::Leaves => [0.3, 0.6, 0.25, 1.0],
+            Block::StainedGlass => [0.8, 0.25, 0.3, 1.0],
+            Block::Water => [0.2, 0.4, 0.85, 0.7],
+            Block::Ice => [0.7, 0.85, 1.0, 0.8],
+            Block::Bedrock => [0.25, 0.25, 0.27, 1.0],
+            // the ore tile is grey stone with bright specks, so these mostly tint the specks
+            Block::CoalOre => [0.45, 0.45, 0.45, 1.0],
+            Block::IronOre => [0.9, 0.7, 0.55, 1.0],
+            Block::GoldOre => [1.0, 0.85, 0.3, 1.0],
+            Block::DiamondOre => [0.5, 0.95, 1.0, 1.0],
+            Block::TallGrass => [0.45, 0.75, 0.3, 1.0],
+            // the flower tile has its own colours
+            Block::Flower => [1.0, 1.0, 1.0, 1.0],
+            Block::Cactus => [0.3, 0.6, 0.25, 1.0],
+            Block::Planks => [0.74, 0.58, 0.36, 1.0],
+            Block::Cobblestone => [0.4, 0.4, 0.42, 1.0],
+            Block::Path => [0.55, 0.45, 0.3, 1.0],
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std::{f32::consts::FRAC_PI_2, time::Duration};
 
-use cgmath::{perspective, InnerSpace, Matrix4, Point3, Rad, Vector3};
+use cgmath::{perspective, InnerSpace, Matrix4, Point3, Rad, SquareMatrix, Vector3};
 use winit::{
     dpi::PhysicalPosition,
     event::{ElementState, MouseScrollDelta, VirtualKeyCode},
@@ -16,8 +16,8 @@ pub struct Camera {
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
     0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.5,
-    0.0, 0.0, 0.0, 1.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
 );
 
 const SAFE_FRAC_PI_2: f32 = FRAC_PI_2 - 0.0001;
@@ -56,21 +56,47 @@ pub struct CameraUniform {
     // to convert the Matrix4 into a 4x4 f32 array
     pub view_proj: [[f32; 4]; 4],
     view_position: [f32; 4],
+    // used by the sky pass to turn screen positions back into view directions
+    inv_view_proj: [[f32; 4]; 4],
+    // xyz is the direction towards the sun, w is the time of day in [0, 1)
+    sun_direction: [f32; 4],
+    // x is the number of seconds since start, used to animate water
+    time: [f32; 4],
 }
 
 impl CameraUniform {
     pub fn new() -> Self {
-        use cgmath::SquareMatrix;
         Self {
             view_proj: cgmath::Matrix4::identity().into(),
             view_position: [0.0; 4],
+            inv_view_proj: cgmath::Matrix4::identity().into(),
+            sun_direction: [0.0, 1.0, 0.0, 0.25],
+            time: [0.0; 4],
         }
     }
 
     pub fn update_view_proj(&mut self, camera: &Camera, projection: &Projection) {
+        let view_proj = projection.calc_matrix() * camera.calc_matrix();
         self.view_position = camera.position.to_homogeneous().into();
-        self.view_proj = (projection.calc_matrix() * camera.calc_matrix()).into();
+        self.view_proj = view_proj.into();
+        self.inv_view_proj = view_proj.invert().unwrap_or_else(Matrix4::identity).into();
     }
+
+    // time_of_day: 0.0 is sunrise, 0.25 noon, 0.5 sunset and 0.75 midnight
+    pub fn update_sun(&mut self, time_of_day: f32) {
+        let sun = sun_direction(time_of_day);
+        self.sun_direction = [sun.x, sun.y, sun.z, time_of_day];
+    }
+
+    pub fn update_time(&mut self, seconds: f32) {
+        self.time[0] = seconds;
+    }
+}
+
+pub fn sun_direction(time_of_day: f32) -> Vector3<f32> {
+    let angle = time_of_day * 2.0 * std::f32::consts::PI;
+    // tilt the sun path slightly so it doesn't pass straight overhead
+    Vector3::new(angle.cos(), angle.sin(), 0.25).normalize()
 }
 
 pub struct Projection {
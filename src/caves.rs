@@ -0,0 +1,368 @@
+#![allow(dead_code)]
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use rand::Rng;
+use std::f32::consts::PI;
+
+use crate::{
+    block::Block,
+    terrain::{chunk_rng, Column, BEDROCK_HEIGHT, SEA_LEVEL},
+    world::{Chunk, ChunkPos, CHUNK_SIZE, WORLD_HEIGHT},
+};
+
+// solid blocks kept under sea, river and lake beds so caves don't drain them
+const SEABED_SEAL: i32 = 4;
+// the cave noise is sampled every few blocks and interpolated in between
+const LATTICE_STEP: usize = 4;
+// cheese caves get rarer towards the surface over this many blocks
+const SURFACE_FADE: i32 = 10;
+const CHEESE_THRESHOLD: f64 = 0.3;
+// squared radius of the tube around where both spaghetti noises cross zero
+const SPAGHETTI_RADIUS: f64 = 0.006;
+// columns of aquifers share a water level, or are dry
+const AQUIFER_CELL: i32 = 16;
+// one chunk in this many starts a ravine
+const RAVINE_CHANCE: u32 = 48;
+const RAVINE_MAX_LENGTH: i32 = 112;
+// ravines starting this many chunks away can still reach into a chunk
+const RAVINE_RANGE: i32 = (RAVINE_MAX_LENGTH + CHUNK_SIZE as i32) / CHUNK_SIZE as i32 + 1;
+const RAVINE_SALT: u64 = 0x7261_7669_6e65;
+
+// noise sampled on a coarse grid over a chunk and interpolated trilinearly
+struct Lattice {
+    values: Vec<f64>,
+    height: usize,
+}
+
+impl Lattice {
+    const SIDE: usize = CHUNK_SIZE / LATTICE_STEP + 1;
+
+    fn sample(origin: [i32; 2], top: usize, noise: impl Fn([f64; 3]) -> f64) -> Self {
+        let height = top / LATTICE_STEP + 2;
+        let mut values = Vec::with_capacity(Self::SIDE * Self::SIDE * height);
+        for y in 0..height {
+            for z in 0..Self::SIDE {
+                for x in 0..Self::SIDE {
+                    values.push(noise([
+                        (origin[0] + (x * LATTICE_STEP) as i32) as f64,
+                        (y * LATTICE_STEP) as f64,
+                        (origin[1] + (z * LATTICE_STEP) as i32) as f64,
+                    ]));
+                }
+            }
+        }
+        Self { values, height }
+    }
+
+    fn get(&self, x: usize, y: usize, z: usize) -> f64 {
+        let corner = |dx: usize, dy: usize, dz: usize| {
+            let (x, y, z) = (
+                x / LATTICE_STEP + dx,
+                y / LATTICE_STEP + dy,
+                z / LATTICE_STEP + dz,
+            );
+            self.values[(y.min(self.height - 1) * Self::SIDE + z) * Self::SIDE + x]
+        };
+        let t = |v: usize| (v % LATTICE_STEP) as f64 / LATTICE_STEP as f64;
+        let (tx, ty, tz) = (t(x), t(y), t(z));
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        let plane = |dy| {
+            lerp(
+                lerp(corner(0, dy, 0), corner(1, dy, 0), tx),
+                lerp(corner(0, dy, 1), corner(1, dy, 1), tx),
+                tz,
+            )
+        };
+        lerp(plane(0), plane(1), ty)
+    }
+}
+
+// carves caves into generated chunks: big "cheese" caverns, long "spaghetti"
+// tunnels and the odd ravine. Carved space below an aquifer's water level floods
+pub struct CaveCarver {
+    seed: u64,
+    cheese: Fbm<Perlin>,
+    spaghetti: [Perlin; 2],
+    aquifer: Perlin,
+}
+
+impl CaveCarver {
+    pub fn new(seed: u64) -> Self {
+        let seed32 = seed as u32;
+        Self {
+            seed,
+            cheese: Fbm::<Perlin>::new(seed32.wrapping_add(10))
+                .set_frequency(1.0 / 80.0)
+                .set_octaves(3),
+            spaghetti: [
+                Perlin::new(seed32.wrapping_add(11)),
+                Perlin::new(seed32.wrapping_add(12)),
+            ],
+            aquifer: Perlin::new(seed32.wrapping_add(13)),
+        }
+    }
+
+    // the water level of the aquifer a column belongs to, None where it's dry
+    fn water_level(&self, x: i32, z: i32) -> Option<i32> {
+        let cell = [x.div_euclid(AQUIFER_CELL), z.div_euclid(AQUIFER_CELL)];
+        // off the noise's integer lattice, where it's always zero
+        let n = self
+            .aquifer
+            .get([cell[0] as f64 * 0.37 + 0.5, cell[1] as f64 * 0.37 + 0.5]);
+        if n < -0.2 {
+            return None;
+        }
+        Some(SEA_LEVEL - ((0.6 - n.min(0.6)) * 40.0) as i32)
+    }
+
+    // what a carved block turns into, or None to leave it solid because it
+    // would open a flooded aquifer into a dry one or one at another level
+    fn fill(&self, x: i32, y: i32, z: i32) -> Option<Block> {
+        let level = self.water_level(x, z);
+        // the direction of the neighbouring cell, if there's one next to this block
+        let edge = |v: i32| match v.rem_euclid(AQUIFER_CELL) {
+            0 => Some(-1),
+            r if r == AQUIFER_CELL - 1 => Some(1),
+            _ => None,
+        };
+        let neighbours = [edge(x).map(|d| (x + d, z)), edge(z).map(|d| (x, z + d))];
+        for (nx, nz) in neighbours.into_iter().flatten() {
+            let other = self.water_level(nx, nz);
+            let highest = level.max(other);
+            if other != level && highest.is_some_and(|highest| y <= highest) {
+                return None;
+            }
+        }
+        Some(match level {
+            Some(level) if y <= level => Block::Water,
+            _ => Block::Air,
+        })
+    }
+
+    // carves a block of the chunk unless it's bedrock or seals a body of water
+    fn carve(
+        &self,
+        chunk: &mut Chunk,
+        origin: [i32; 2],
+        columns: &[Column],
+        [x, y, z]: [usize; 3],
+    ) {
+        let column = columns[z * CHUNK_SIZE + x];
+        let height = column.height;
+        let yi = y as i32;
+        if yi < BEDROCK_HEIGHT || yi > height {
+            return;
+        }
+        if column.is_underwater() && yi > height - SEABED_SEAL {
+            return;
+        }
+        if matches!(
+            chunk.get(x, y, z),
+            Block::Air | Block::Water | Block::Bedrock
+        ) {
+            return;
+        }
+        if let Some(block) = self.fill(origin[0] + x as i32, yi, origin[1] + z as i32) {
+            chunk.set(x, y, z, block);
+        }
+    }
+
+    // columns are the chunk's ground and water in z, x order
+    pub fn carve_chunk(&self, chunk: &mut Chunk, pos: ChunkPos, columns: &[Column]) {
+        let origin = [pos[0] * CHUNK_SIZE as i32, pos[1] * CHUNK_SIZE as i32];
+        let top = columns.iter().map(|c| c.height).max().unwrap_or(0).max(0) as usize;
+        // caves are squashed vertically so they run more sideways than down
+        let cheese = Lattice::sample(origin, top, |[x, y, z]| self.cheese.get([x, y * 2.0, z]));
+        let spaghetti = self.spaghetti.each_ref().map(|noise| {
+            Lattice::sample(origin, top, |[x, y, z]| {
+                noise.get([x / 40.0, y / 28.0, z / 40.0])
+            })
+        });
+
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let height = columns[z * CHUNK_SIZE + x].height;
+                for y in BEDROCK_HEIGHT..=height.min(WORLD_HEIGHT as i32 - 1) {
+                    let depth = height - y;
+                    let fade = (SURFACE_FADE - depth).max(0) as f64 / SURFACE_FADE as f64;
+                    let y = y as usize;
+                    let in_cheese = cheese.get(x, y, z) > CHEESE_THRESHOLD + fade * 0.4;
+                    let [a, b] = spaghetti.each_ref().map(|lattice| lattice.get(x, y, z));
+                    let in_spaghetti = a * a + b * b < SPAGHETTI_RADIUS;
+                    if in_cheese || in_spaghetti {
+                        self.carve(chunk, origin, columns, [x, y, z]);
+                    }
+                }
+            }
+        }
+        self.carve_ravines(chunk, pos, columns);
+    }
+
+    // ravines are worms that start in nearby chunks and wander for up to
+    // RAVINE_MAX_LENGTH blocks, the parts inside this chunk are carved. Each
+    // one only depends on the chunk it starts in, so they line up across chunks
+    fn carve_ravines(&self, chunk: &mut Chunk, pos: ChunkPos, columns: &[Column]) {
+        let origin = [pos[0] * CHUNK_SIZE as i32, pos[1] * CHUNK_SIZE as i32];
+        let size = CHUNK_SIZE as f32;
+        for sz in pos[1] - RAVINE_RANGE..=pos[1] + RAVINE_RANGE {
+            for sx in pos[0] - RAVINE_RANGE..=pos[0] + RAVINE_RANGE {
+                let mut rng = chunk_rng(self.seed, [sx, sz], RAVINE_SALT);
+                if !rng.gen_ratio(1, RAVINE_CHANCE) {
+                    continue;
+                }
+                let mut position = [
+                    sx as f32 * size + rng.gen_range(0.0..size),
+                    rng.gen_range(20.0..SEA_LEVEL as f32),
+                    sz as f32 * size + rng.gen_range(0.0..size),
+                ];
+                let mut yaw = rng.gen_range(0.0..2.0 * PI);
+                let mut pitch = rng.gen_range(-0.15..0.15f32);
+                let width = rng.gen_range(1.5..3.5f32);
+                let length = rng.gen_range(64..=RAVINE_MAX_LENGTH);
+                for step in 0..length {
+                    position[0] += yaw.cos() * pitch.cos();
+                    position[1] += pitch.sin();
+                    position[2] += yaw.sin() * pitch.cos();
+                    yaw += rng.gen_range(-0.08..0.08);
+                    pitch = pitch * 0.7 + rng.gen_range(-0.05..0.05);
+
+                    // widest in the middle and tall and narrow throughout
+                    let radius = 1.0 + width * (PI * step as f32 / length as f32).sin();
+                    let tall = radius * 3.0;
+                    let min = |axis: usize, r: f32| (position[axis] - r).floor() as i32;
+                    let max = |axis: usize, r: f32| (position[axis] + r).ceil() as i32;
+                    if max(0, radius) < origin[0]
+                        || min(0, radius) >= origin[0] + CHUNK_SIZE as i32
+                        || max(2, radius) < origin[1]
+                        || min(2, radius) >= origin[1] + CHUNK_SIZE as i32
+                    {
+                        continue;
+                    }
+                    for wy in min(1, tall).max(0)..=max(1, tall).min(WORLD_HEIGHT as i32 - 1) {
+                        for wz in min(2, radius).max(origin[1])
+                            ..=max(2, radius).min(origin[1] + CHUNK_SIZE as i32 - 1)
+                        {
+                            for wx in min(0, radius).max(origin[0])
+                                ..=max(0, radius).min(origin[0] + CHUNK_SIZE as i32 - 1)
+                            {
+                                let dx = (wx as f32 + 0.5 - position[0]) / radius;
+                                let dy = (wy as f32 + 0.5 - position[1]) / tall;
+                                let dz = (wz as f32 + 0.5 - position[2]) / radius;
+                                if dx * dx + dy * dy + dz * dz < 1.0 {
+                                    let local = [
+                                        (wx - origin[0]) as usize,
+                                        wy as usize,
+                                        (wz - origin[1]) as usize,
+                                    ];
+                                    self.carve(chunk, origin, columns, local);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEIGHT: i32 = 100;
+
+    // dry land of stone up to HEIGHT, with bedrock filling the bottom layer and
+    // scattered through the ones above it
+    fn solid_chunk() -> (Chunk, Vec<Column>) {
+        let mut chunk = Chunk::new();
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                for y in 0..=HEIGHT as usize {
+                    let bedrock = y == 0 || (y < BEDROCK_HEIGHT as usize && (x + z + y) % 3 == 0);
+                    chunk.set(
+                        x,
+                        y,
+                        z,
+                        if bedrock {
+                            Block::Bedrock
+                        } else {
+                            Block::Stone
+                        },
+                    );
+                }
+            }
+        }
+        let column = Column {
+            height: HEIGHT,
+            water_level: 0,
+        };
+        (chunk, vec![column; CHUNK_SIZE * CHUNK_SIZE])
+    }
+
+    fn carved(carver: &CaveCarver, pos: ChunkPos) -> Chunk {
+        let (mut chunk, columns) = solid_chunk();
+        carver.carve_chunk(&mut chunk, pos, &columns);
+        chunk
+    }
+
+    fn same_blocks(a: &Chunk, b: &Chunk) -> bool {
+        (0..=HEIGHT as usize).all(|y| {
+            (0..CHUNK_SIZE).all(|z| (0..CHUNK_SIZE).all(|x| a.get(x, y, z) == b.get(x, y, z)))
+        })
+    }
+
+    #[test]
+    fn carving_does_not_depend_on_order() {
+        let area: Vec<ChunkPos> = (-2..=2)
+            .flat_map(|z| (-2..=2).map(move |x| [x, z]))
+            .collect();
+        let carver = CaveCarver::new(42);
+        let forward: Vec<Chunk> = area.iter().map(|&pos| carved(&carver, pos)).collect();
+        // a carver of its own going the other way, and each chunk again after it
+        let other = CaveCarver::new(42);
+        let backward: Vec<Chunk> = area.iter().rev().map(|&pos| carved(&other, pos)).collect();
+        for (i, &pos) in area.iter().enumerate() {
+            assert!(
+                same_blocks(&forward[i], &backward[area.len() - 1 - i]),
+                "chunk {:?}",
+                pos
+            );
+            assert!(
+                same_blocks(&forward[i], &carved(&carver, pos)),
+                "chunk {:?} again",
+                pos
+            );
+        }
+        let (solid, _) = solid_chunk();
+        assert!(
+            forward.iter().any(|chunk| !same_blocks(chunk, &solid)),
+            "nothing was carved"
+        );
+    }
+
+    #[test]
+    fn bedrock_layers_are_never_carved() {
+        let carver = CaveCarver::new(7);
+        let (solid, _) = solid_chunk();
+        let mut carved_any = false;
+        for z in -3..=3 {
+            for x in -3..=3 {
+                let chunk = carved(&carver, [x, z]);
+                carved_any |= !same_blocks(&chunk, &solid);
+                for y in 0..BEDROCK_HEIGHT as usize {
+                    for lz in 0..CHUNK_SIZE {
+                        for lx in 0..CHUNK_SIZE {
+                            assert_eq!(
+                                chunk.get(lx, y, lz),
+                                solid.get(lx, y, lz),
+                                "{:?} in chunk {:?}",
+                                (lx, y, lz),
+                                [x, z]
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        assert!(carved_any, "nothing was carved");
+    }
+}
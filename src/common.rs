@@ -1,9 +1,11 @@
 #![allow(dead_code)]
 use bytemuck::{cast_slice, Pod, Zeroable};
-use cgmath::{Matrix, Matrix4, SquareMatrix};
+use cgmath::{Matrix, Matrix4, SquareMatrix, Vector4};
 use std::{
+    collections::{HashMap, HashSet},
     iter, mem,
-    time::{Duration, Instant},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use wgpu::util::DeviceExt;
 use winit::{
@@ -13,14 +15,39 @@ use winit::{
 };
 
 use crate::{
+    atlas,
+    axes::{self, Label, PlotBox},
+    biome::Biome,
+    block::{Block, RenderLayer},
     camera::{self, flatten, Camera, CameraUniform},
-    texture,
+    sd::colormap::Colormap,
+    smooth,
+    storage::{Level, WorldStorage},
+    text, texture,
+    vertex_data::create_cube,
+    world::{chunk_pos, ChunkPos, World, CHUNK_SIZE},
 };
 #[path = "transforms.rs"]
 mod transforms;
 
 const ANIMATION_SPEED: f32 = 1.0;
 const IS_PERSPECTIVE: bool = true;
+// MSAA samples per pixel, 1 turns multisampling off
+const SAMPLE_COUNT: u32 = 4;
+// size in pixels of a colorbar in the legend, and the space around each one
+const COLORBAR_SIZE: [u32; 2] = [16, 192];
+const LEGEND_MARGIN: u32 = 12;
+// length of a full day/night cycle in seconds
+const DAY_LENGTH: f32 = 240.0;
+// how far a water surface sits below the top of its block, keep in sync with vs_water
+const WATER_SURFACE_DROP: f32 = 0.125;
+// translucent faces are sorted again once the camera is this many blocks from
+// where they were last sorted from
+const RESORT_DISTANCE: f32 = 0.5;
+// how often a world with storage attached is written to disk
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+// saved chunks within this many chunks of the player are loaded as they move
+pub const LOAD_RADIUS: i32 = 8;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
@@ -108,7 +135,7 @@ impl Vertex {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Mesh {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>, // Change to u32 if you need more than 65,536 indices
@@ -117,7 +144,11 @@ pub struct Mesh {
 pub struct State {
     pub init: transforms::InitWgpu,
     pipeline: wgpu::RenderPipeline,
-    vertex_buffer_vec: wgpu::Buffer,
+    cutout_pipeline: wgpu::RenderPipeline,
+    translucent_pipeline: wgpu::RenderPipeline,
+    water_pipeline: wgpu::RenderPipeline,
+    surface_pipeline: wgpu::RenderPipeline,
+    sky_pipeline: wgpu::RenderPipeline,
     uniform_bind_group: wgpu::BindGroup,
     vertex_uniform_buffer: wgpu::Buffer,
 
@@ -129,14 +160,137 @@ pub struct State {
     projection: camera::Projection,
     camera_controller: camera::CameraController,
     mouse_pressed: bool,
+    time_of_day: f32,
+    time: f32,
 
     // texture
     diffuse_bind_group: wgpu::BindGroup,
     diffuse_texture: texture::Texture,
-    num_vertices_vec: u32,
-    index_buffer_vec: wgpu::Buffer,
+    depth_texture: texture::Texture,
+    // None when multisampling is off
+    msaa_texture: Option<texture::Texture>,
+
+    // blocks and surfaces
+    buffers: WorldBuffers,
+    style: TerrainStyle,
+    // None with block terrain
+    smooth_mesher: Option<smooth::SmoothMesher>,
+    // colorbars of the colormaps on the surfaces, None without any
+    legend: Option<Overlay>,
+    axes: LayerBuffers,
+    labels: Option<Labels>,
+    line_pipeline: wgpu::RenderPipeline,
+    overlay_pipeline: wgpu::RenderPipeline,
+
+    // world
+    world: World,
+    // None for worlds that aren't saved
+    storage: Option<WorldStorage>,
+    since_autosave: Duration,
+    // the chunk the player was in when saved chunks were last loaded around
+    // them
+    loaded_around: Option<ChunkPos>,
+    // where the camera was when translucent faces were last sorted, None once
+    // the meshes changed
+    sorted_from: Option<[f32; 3]>,
+    // the chunks with translucent or water faces, farthest first
+    translucent_order: Vec<ChunkPos>,
+}
+
+// the meshes of a chunk's blocks, by pass
+struct BlockBuffers {
+    opaque: LayerBuffers,
+    cutout: LayerBuffers,
+    translucent: LayerBuffers,
+    translucent_vertices: Vec<Vertex>,
+    water: LayerBuffers,
+    water_vertices: Vec<Vertex>,
+}
+
+impl BlockBuffers {
+    fn new(device: &wgpu::Device, world: &World, pos: ChunkPos, style: TerrainStyle) -> Self {
+        let layers = mesh_chunk(world, pos, style);
+        Self {
+            opaque: LayerBuffers::new(device, &layers.opaque, "Opaque"),
+            cutout: LayerBuffers::new(device, &layers.cutout, "Cutout"),
+            translucent: LayerBuffers::new(device, &layers.translucent, "Translucent"),
+            translucent_vertices: layers.translucent.vertices,
+            water: LayerBuffers::new(device, &layers.water, "Water"),
+            water_vertices: layers.water.vertices,
+        }
+    }
+}
+
+// the blocks of each chunk, the lit surfaces and with smooth terrain its mesh
+// of each chunk. Chunks are meshed on their own, so only the ones that change
+// are meshed again
+struct WorldBuffers {
+    blocks: HashMap<ChunkPos, BlockBuffers>,
+    // smooth meshes such as parametric surfaces, drawn lit by the sun
+    surfaces: LayerBuffers,
+    // smooth terrain is lit like the surfaces. Empty with block terrain
+    smooth: HashMap<ChunkPos, LayerBuffers>,
+}
+
+impl WorldBuffers {
+    fn new(
+        device: &wgpu::Device,
+        world: &World,
+        style: TerrainStyle,
+        surfaces: &Mesh,
+        mesher: Option<&smooth::SmoothMesher>,
+    ) -> Self {
+        let mut buffers = Self {
+            blocks: HashMap::new(),
+            surfaces: LayerBuffers::new(device, surfaces, "Surface"),
+            smooth: HashMap::new(),
+        };
+        let chunks: Vec<ChunkPos> = world.chunks().map(|(&pos, _)| pos).collect();
+        buffers.mesh_chunks(device, world, style, mesher, chunks);
+        buffers
+    }
+
+    // meshes chunks that came in, and the chunks around them whose faces on
+    // the border may now be hidden and whose smooth terrain joins theirs
+    fn chunks_added(
+        &mut self,
+        device: &wgpu::Device,
+        world: &World,
+        style: TerrainStyle,
+        mesher: Option<&smooth::SmoothMesher>,
+        added: &[ChunkPos],
+    ) {
+        let around: HashSet<ChunkPos> = added
+            .iter()
+            .flat_map(|pos| {
+                (-1..=1).flat_map(move |dz| (-1..=1).map(move |dx| [pos[0] + dx, pos[1] + dz]))
+            })
+            .filter(|&pos| world.chunk(pos).is_some())
+            .collect();
+        self.mesh_chunks(device, world, style, mesher, around);
+    }
+
+    fn mesh_chunks(
+        &mut self,
+        device: &wgpu::Device,
+        world: &World,
+        style: TerrainStyle,
+        mesher: Option<&smooth::SmoothMesher>,
+        chunks: impl IntoIterator<Item = ChunkPos>,
+    ) {
+        for pos in chunks {
+            self.blocks
+                .insert(pos, BlockBuffers::new(device, world, pos, style));
+            if let Some(mesher) = mesher {
+                let mesh = mesher.mesh_chunk(world, pos);
+                self.smooth
+                    .insert(pos, LayerBuffers::new(device, &mesh, "Smooth Terrain"));
+            }
+        }
+    }
 }
 
+#[derive(Copy, Clone, Debug)]
 enum Direction {
     X,
     Y,
@@ -144,167 +298,630 @@ enum Direction {
     NegX,
     NegY,
     NegZ,
-    None,
-}
-fn check_visibility(x: usize, y: usize, z: usize, cubes: &Vec<Vec<Vec<Mesh>>>) -> Vec<Direction> {
-    let mut directions: Vec<Direction> = Vec::new();
-    match cubes.get(x + 1) {
-        Some(inner_vec) => match inner_vec.get(y) {
-            Some(inner_inner_vec) => match inner_inner_vec.get(z) {
-                Some(_) => {}
-                None => directions.push(Direction::X),
-            },
-            None => directions.push(Direction::X),
-        },
-        None => directions.push(Direction::X),
+}
+
+const DIRECTIONS: [Direction; 6] = [
+    Direction::X,
+    Direction::Y,
+    Direction::Z,
+    Direction::NegX,
+    Direction::NegY,
+    Direction::NegZ,
+];
+
+impl Direction {
+    fn offset(&self) -> [i32; 3] {
+        match self {
+            Direction::X => [1, 0, 0],
+            Direction::Y => [0, 1, 0],
+            Direction::Z => [0, 0, 1],
+            Direction::NegX => [-1, 0, 0],
+            Direction::NegY => [0, -1, 0],
+            Direction::NegZ => [0, 0, -1],
+        }
+    }
+
+    // vertices of this face in the mesh returned by create_cube
+    fn face_range(&self) -> std::ops::Range<usize> {
+        match self {
+            Direction::Z => 0..6,
+            Direction::X => 6..12,
+            Direction::NegZ => 12..18,
+            Direction::NegX => 18..24,
+            Direction::Y => 24..30,
+            Direction::NegY => 30..36,
+        }
+    }
+}
+
+fn check_visibility(x: i32, y: i32, z: i32, world: &World) -> Vec<Direction> {
+    let block = world.get_block(x, y, z);
+    DIRECTIONS
+        .into_iter()
+        .filter(|direction| {
+            let [dx, dy, dz] = direction.offset();
+            block.face_visible(world.get_block(x + dx, y + dy, z + dz))
+        })
+        .collect()
+}
+
+impl Mesh {
+    // adds another mesh's triangles to this one
+    pub fn append(&mut self, other: &Mesh) {
+        let start = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(&other.vertices);
+        self.indices.extend(other.indices.iter().map(|i| start + i));
+    }
+
+    pub fn translate(&mut self, offset: [f32; 3]) {
+        for vertex in &mut self.vertices {
+            for (p, d) in vertex.position.iter_mut().zip(offset) {
+                *p += d;
+            }
+        }
+    }
+
+    fn push_face(&mut self, face: &[Vertex]) {
+        let start = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(face);
+        self.indices.extend(start..start + face.len() as u32);
+    }
+}
+
+// how the ground of the world is drawn
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TerrainStyle {
+    Blocks,
+    // one smooth surface over the blocks, see smooth::SmoothMesher
+    Smooth,
+}
+
+// meshes drawn lit by the sun alongside the blocks, such as parametric
+// surfaces, and the colormaps on them that get a colorbar in the legend.
+// Plotted surfaces can have axes, a line list and the labels on it
+#[derive(Debug, Clone, Default)]
+pub struct Surfaces {
+    pub mesh: Mesh,
+    pub colormaps: Vec<Colormap>,
+    pub axes: Mesh,
+    pub labels: Vec<Label>,
+}
+
+impl Surfaces {
+    // a colormap already in the legend isn't added twice
+    pub fn add(&mut self, mesh: &Mesh, colormap: Option<&Colormap>) {
+        self.mesh.append(mesh);
+        if let Some(colormap) = colormap {
+            if !self.colormaps.contains(colormap) {
+                self.colormaps.push(colormap.clone());
+            }
+        }
+    }
+
+    // gridlines, ticks and tick values around a plot, see axes::axes
+    pub fn add_axes(&mut self, plot: &PlotBox) {
+        let (mesh, labels) = axes::axes(plot);
+        self.axes.append(&mesh);
+        self.labels.extend(labels);
+    }
+}
+
+// visible block faces, split by the pass they are drawn in
+#[derive(Debug, Clone, Default)]
+pub struct LayeredMesh {
+    pub opaque: Mesh,
+    pub cutout: Mesh,
+    pub translucent: Mesh,
+    pub water: Mesh,
+}
+
+// with smooth terrain the ground blocks are left to smooth::SmoothMesher
+pub fn mesh_world(world: &World, style: TerrainStyle) -> LayeredMesh {
+    let mut layers = LayeredMesh::default();
+    for (&pos, _) in world.chunks() {
+        mesh_chunk_into(&mut layers, world, pos, style);
+    }
+    layers
+}
+
+// the blocks of one chunk. Faces against the neighbouring chunks are hidden
+// by their blocks, so those are meshed again when a neighbour comes in
+pub fn mesh_chunk(world: &World, pos: ChunkPos, style: TerrainStyle) -> LayeredMesh {
+    let mut layers = LayeredMesh::default();
+    mesh_chunk_into(&mut layers, world, pos, style);
+    layers
+}
+
+fn mesh_chunk_into(layers: &mut LayeredMesh, world: &World, pos: ChunkPos, style: TerrainStyle) {
+    let Some(chunk) = world.chunk(pos) else {
+        return;
     };
-    // y = z
-    match cubes.get(x) {
-        Some(inner_vec) => match inner_vec.get(y + 1) {
-            Some(inner_inner_vec) => match inner_inner_vec.get(z) {
-                Some(_) => {}
-                None => directions.push(Direction::Y),
-            },
-            None => directions.push(Direction::Y),
-        },
-        None => directions.push(Direction::Y),
+    let [cx, cz] = pos;
+    for local_x in 0..CHUNK_SIZE {
+        for y in 0..chunk.top() {
+            for local_z in 0..CHUNK_SIZE {
+                let block = chunk.get(local_x, y, local_z);
+                if style == TerrainStyle::Smooth && smooth::is_ground(block) {
+                    continue;
+                }
+                let biome = chunk.biome(local_x, local_z);
+                let x = cx * CHUNK_SIZE as i32 + local_x as i32;
+                let z = cz * CHUNK_SIZE as i32 + local_z as i32;
+                let y = y as i32;
+                mesh_block(layers, world, block, biome, x, y, z);
+            }
+        }
+    }
+}
+
+fn mesh_block(
+    layers: &mut LayeredMesh,
+    world: &World,
+    block: Block,
+    biome: Option<Biome>,
+    x: i32,
+    y: i32,
+    z: i32,
+) {
+    let mesh = match block.render_layer() {
+        Some(RenderLayer::Opaque) => &mut layers.opaque,
+        Some(RenderLayer::Cutout) => &mut layers.cutout,
+        Some(RenderLayer::Translucent) => &mut layers.translucent,
+        Some(RenderLayer::Water) => &mut layers.water,
+        None => return,
     };
-    match cubes.get(x) {
-        Some(inner_vec) => match inner_vec.get(y) {
-            Some(inner_inner_vec) => match inner_inner_vec.get(z + 1) {
-                Some(_) => {}
-                None => directions.push(Direction::Z),
-            },
-            None => {}
-        },
-        None => {}
+    if block.is_plant() {
+        mesh_plant(mesh, block, biome, x, y, z);
+        return;
+    }
+    // the top of a body of water sits a little below the block above it
+    let top = y as f32 + 0.5;
+    let lowered = block == Block::Water && world.get_block(x, y + 1, z) != Block::Water;
+    let visible = check_visibility(x, y, z, world);
+    if visible.is_empty() {
+        return;
+    }
+    let cube = create_cube([x as f32, y as f32, z as f32], 1.0);
+    for direction in visible {
+        let color = block_color(block, biome, matches!(direction, Direction::Y));
+        let face: Vec<Vertex> = cube.vertices[direction.face_range()]
+            .iter()
+            .map(|vertex| {
+                let mut position = vertex.position;
+                if lowered && position[1] == top {
+                    position[1] -= WATER_SURFACE_DROP;
+                }
+                Vertex {
+                    position,
+                    color,
+                    tex_coords: atlas::tile_uv(block.tile(), vertex.tex_coords),
+                    ..*vertex
+                }
+            })
+            .collect();
+        mesh.push_face(&face);
+    }
+}
+
+// grass tops, tall grass and leaves take the colour of their biome
+pub fn block_color(block: Block, biome: Option<Biome>, top: bool) -> [f32; 4] {
+    let tint = match (block, biome) {
+        (Block::Grass, Some(biome)) if top => Some(biome.grass_color()),
+        (Block::TallGrass, Some(biome)) => Some(biome.grass_color()),
+        (Block::Leaves, Some(biome)) => Some(biome.foliage_color()),
+        _ => None,
     };
-    if x == 0 {
-        directions.push(Direction::NegX);
-    } else {
-        match cubes.get(x - 1) {
-            Some(inner_vec) => match inner_vec.get(y) {
-                Some(inner_inner_vec) => match inner_inner_vec.get(z) {
-                    Some(_) => {}
-                    None => directions.push(Direction::NegX),
-                },
-                None => {}
-            },
-            None => {}
-        };
+    let mut color = block.color();
+    if let Some(tint) = tint {
+        for (channel, tint) in color.iter_mut().zip(tint) {
+            *channel *= tint;
+        }
+    }
+    color
+}
+
+// two quads along the block's diagonals, made from its front face and lit
+// like the ground they stand on
+fn mesh_plant(mesh: &mut Mesh, block: Block, biome: Option<Biome>, x: i32, y: i32, z: i32) {
+    let color = block_color(block, biome, false);
+    let cube = create_cube([x as f32, y as f32, z as f32], 1.0);
+    for flip in [1.0, -1.0] {
+        let face: Vec<Vertex> = cube.vertices[Direction::Z.face_range()]
+            .iter()
+            .map(|vertex| {
+                let mut position = vertex.position;
+                position[2] = z as f32 + (position[0] - x as f32) * flip;
+                Vertex {
+                    position,
+                    normal: [0.0, 1.0, 0.0, vertex.normal[3]],
+                    color,
+                    tex_coords: atlas::tile_uv(block.tile(), vertex.tex_coords),
+                }
+            })
+            .collect();
+        mesh.push_face(&face);
     }
-    if y == 0 {
-        directions.push(Direction::NegY);
-    } else {
-        match cubes.get(x) {
-            Some(inner_vec) => match inner_vec.get(y - 1) {
-                Some(inner_inner_vec) => match inner_inner_vec.get(z) {
-                    Some(_) => {}
-                    None => directions.push(Direction::NegY),
+}
+
+// translucent faces have to be blended far to near, so their indices are
+// re-sorted as the camera moves, see RESORT_DISTANCE
+fn back_to_front_indices(vertices: &[Vertex], eye: [f32; 3]) -> Vec<u32> {
+    let mut faces: Vec<(f32, u32)> = vertices
+        .chunks(6)
+        .enumerate()
+        .map(|(i, face)| {
+            let distance: f32 = eye
+                .iter()
+                .enumerate()
+                .map(|(axis, e)| {
+                    let center =
+                        face.iter().map(|v| v.position[axis]).sum::<f32>() / face.len() as f32;
+                    (center - e) * (center - e)
+                })
+                .sum();
+            (distance, i as u32 * 6)
+        })
+        .collect();
+    faces.sort_by(|a, b| b.0.total_cmp(&a.0));
+    faces
+        .iter()
+        .flat_map(|&(_, start)| start..start + 6)
+        .collect()
+}
+
+struct LayerBuffers {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    num_indices: u32,
+}
+
+// an image drawn over the frame at its own size in pixels, such as the legend
+struct Overlay {
+    texture: texture::Texture,
+    bind_group: wgpu::BindGroup,
+    buffers: LayerBuffers,
+}
+
+impl Overlay {
+    fn new(
+        init: &transforms::InitWgpu,
+        layout: &wgpu::BindGroupLayout,
+        image: &image::RgbaImage,
+        label: &str,
+    ) -> Self {
+        // the colours are linear like vertex colours, so the legend matches
+        // the surfaces
+        let texture = texture::Texture::from_rgba(
+            &init.device,
+            &init.queue,
+            image,
+            wgpu::TextureFormat::Rgba8Unorm,
+            Some(label),
+        );
+        let bind_group = init.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
                 },
-                None => {}
-            },
-            None => {}
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+            label: Some(label),
+        });
+        let buffers = LayerBuffers::new(&init.device, &Mesh::default(), label);
+        Self {
+            texture,
+            bind_group,
+            buffers,
+        }
+    }
+
+    // against the right edge of the screen, halfway down
+    fn place(&mut self, init: &transforms::InitWgpu) {
+        let (width, height) = (self.texture.texture.width(), self.texture.texture.height());
+        let [screen_w, screen_h] = [init.config.width as f32, init.config.height as f32];
+        let left = screen_w - (width + LEGEND_MARGIN) as f32;
+        let top = ((screen_h - height as f32) / 2.0).floor();
+        let mesh = Mesh {
+            vertices: self.quad(init, [left, top], [0, 0, width, height]).to_vec(),
+            indices: vec![0, 1, 2, 2, 3, 0],
         };
+        self.buffers = LayerBuffers::new(&init.device, &mesh, "Overlay");
     }
-    if z == 0 {
-        directions.push(Direction::NegZ);
-    } else {
-        match cubes.get(x) {
-            Some(inner_vec) => match inner_vec.get(y) {
-                Some(inner_inner_vec) => match inner_inner_vec.get(z - 1) {
-                    Some(_) => {}
-                    None => directions.push(Direction::NegZ),
-                },
-                None => {}
-            },
-            None => {}
+
+    // the part of the texture at [left, top, width, height] drawn at its own
+    // size with its top left corner at a point on the screen in pixels
+    fn quad(
+        &self,
+        init: &transforms::InitWgpu,
+        [x, y]: [f32; 2],
+        [left, top, width, height]: [u32; 4],
+    ) -> [Vertex; 4] {
+        let [screen_w, screen_h] = [init.config.width as f32, init.config.height as f32];
+        let size = [self.texture.texture.width(), self.texture.texture.height()];
+        let clip = |x: f32, y: f32| [2.0 * x / screen_w - 1.0, 1.0 - 2.0 * y / screen_h];
+        let uv = |u: u32, v: u32| [u as f32 / size[0] as f32, v as f32 / size[1] as f32];
+        let (w, h) = (width as f32, height as f32);
+        [
+            (clip(x, y), uv(left, top)),
+            (clip(x, y + h), uv(left, top + height)),
+            (clip(x + w, y + h), uv(left + width, top + height)),
+            (clip(x + w, y), uv(left + width, top)),
+        ]
+        .map(|([x, y], tex_coords)| Vertex {
+            position: [x, y, 0.0, 1.0],
+            normal: [0.0, 0.0, 1.0, 1.0],
+            color: [1.0; 4],
+            tex_coords,
+        })
+    }
+}
+
+// text pinned to points in the world, such as the values on plot axes, drawn
+// over the frame facing the screen wherever those points are this frame
+struct Labels {
+    overlay: Overlay,
+    positions: Vec<[f32; 3]>,
+    rects: Vec<[u32; 4]>,
+}
+
+impl Labels {
+    fn new(init: &transforms::InitWgpu, layout: &wgpu::BindGroupLayout, labels: &[Label]) -> Self {
+        let texts: Vec<&str> = labels.iter().map(|label| label.text.as_str()).collect();
+        let (image, rects) = text::text_atlas(&texts);
+        let mut overlay = Overlay::new(init, layout, &image, "Labels");
+        // a quad per label, moved every frame by update
+        let mesh = Mesh {
+            vertices: vec![Vertex::zeroed(); 4 * labels.len()],
+            indices: (0..labels.len() as u32)
+                .flat_map(|i| [0, 1, 2, 2, 3, 0].map(|k| 4 * i + k))
+                .collect(),
         };
+        overlay.buffers = LayerBuffers::new(&init.device, &mesh, "Labels");
+        Self {
+            overlay,
+            positions: labels.iter().map(|label| label.position).collect(),
+            rects,
+        }
+    }
+
+    // centres each label on where its point lands on the screen, snapped to
+    // whole pixels to keep the text sharp, at the depth of the point so
+    // surfaces in front hide it. Points behind the camera are hidden
+    fn update(&self, init: &transforms::InitWgpu, view_proj: Matrix4<f32>) {
+        let [screen_w, screen_h] = [init.config.width as f32, init.config.height as f32];
+        let mut vertices = Vec::with_capacity(4 * self.positions.len());
+        for (&[x, y, z], &rect) in self.positions.iter().zip(&self.rects) {
+            let clip = view_proj * Vector4::new(x, y, z, 1.0);
+            if clip.w <= 0.0 {
+                vertices.extend([Vertex::zeroed(); 4]);
+                continue;
+            }
+            let (sx, sy) = (
+                (clip.x / clip.w + 1.0) / 2.0 * screen_w,
+                (1.0 - clip.y / clip.w) / 2.0 * screen_h,
+            );
+            let corner = [
+                (sx - rect[2] as f32 / 2.0).round(),
+                (sy - rect[3] as f32 / 2.0).round(),
+            ];
+            vertices.extend(self.overlay.quad(init, corner, rect).map(|mut vertex| {
+                vertex.position[2] = clip.z / clip.w;
+                vertex
+            }));
+        }
+        init.queue.write_buffer(
+            &self.overlay.buffers.vertex_buffer,
+            0,
+            cast_slice(&vertices),
+        );
     }
-    directions
 }
 
-fn get_visible_cubes(cubes: &Vec<Vec<Vec<Mesh>>>) -> Vec<Vertex> {
-    let mut visible_cubes: Vec<Vertex> = Vec::new();
-    for x in 0..cubes.len() {
-        for y in 0..cubes[x].len() {
-            for z in 0..cubes[x][y].len() {
-                for direction in check_visibility(x, y, z, cubes) {
-                    match direction {
-                        Direction::X => {
-                            visible_cubes.append(cubes[x][y][z].vertices[6..12].to_vec().as_mut());
-                        }
-                        Direction::Y => {
-                            visible_cubes.append(cubes[x][y][z].vertices[24..30].to_vec().as_mut());
-                        }
-                        // x?
-                        Direction::Z => {
-                            visible_cubes.append(cubes[x][y][z].vertices[0..6].to_vec().as_mut());
-                        }
-                        Direction::NegX => {
-                            visible_cubes.append(cubes[x][y][z].vertices[18..24].to_vec().as_mut());
-                        }
-                        // neg z
-                        Direction::NegY => {
-                            visible_cubes.append(cubes[x][y][z].vertices[30..36].to_vec().as_mut());
-                        }
-                        // neg y
-                        Direction::NegZ => {
-                            visible_cubes.append(cubes[x][y][z].vertices[12..18].to_vec().as_mut());
-                        }
-                        Direction::None => {}
-                    }
-                }
+// the colorbars side by side with a dark outline, on a clear background
+fn legend_image(colormaps: &[Colormap]) -> image::RgbaImage {
+    let [bar_w, bar_h] = COLORBAR_SIZE;
+    let count = colormaps.len() as u32;
+    let mut legend =
+        image::RgbaImage::new(count * (bar_w + 2) + (count - 1) * LEGEND_MARGIN, bar_h + 2);
+    for (i, colormap) in colormaps.iter().enumerate() {
+        let left = i as u32 * (bar_w + 2 + LEGEND_MARGIN);
+        for y in 0..bar_h + 2 {
+            for x in left..left + bar_w + 2 {
+                legend.put_pixel(x, y, image::Rgba([20, 20, 20, 255]));
             }
         }
+        image::imageops::replace(
+            &mut legend,
+            &colormap.colorbar(bar_w, bar_h),
+            left as i64 + 1,
+            1,
+        );
     }
-    visible_cubes
+    legend
 }
 
-impl State {
-    pub async fn new(window: &Window, shape_data: &Vec<Vec<Vec<Mesh>>>, light_data: Light) -> Self {
-        let mut vertex_data_vec: Vec<Vertex> = Vec::new();
-        let visible_cubes: Vec<Vertex> = get_visible_cubes(shape_data);
-        dbg!(&visible_cubes.len());
-        // let mut vertex_data: Vec<Vertex> = Vec::with_capacity(visible_cubes.len());
-        for x in 0..visible_cubes.len() {
-            // for j in 0..visible_cubes[x].vertices.len() {
-            vertex_data_vec.push(visible_cubes[x]);
-            // }
-            // vertex_data_vec.push(vertex_data);
+impl LayerBuffers {
+    fn new(device: &wgpu::Device, mesh: &Mesh, label: &str) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Vertex Buffer", label)),
+            contents: cast_slice(&mesh.vertices),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Index Buffer", label)),
+            contents: cast_slice(&mesh.indices),
+            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+        });
+        Self {
+            vertex_buffer,
+            index_buffer,
+            num_indices: mesh.indices.len() as u32,
         }
-        // let mut vertex_data: Vec<Vertex> = Vec::with_capacity(visible_cubes[i].vertices.len());
-        // for j in 0..visible_cubes[i].vertices.len() {
-        //     vertex_data.push(visible_cubes[i].vertices[j]);
-        // }
-        // vertex_data_vec.push(vertex_data);
-
-        // for shape_item in shape_data.iter() {
-        //     if let Some(positions) = &shape_item.0 {
-        //         let mut vertex_data: Vec<Vertex> = Vec::new();
-        //         for (i, position) in positions.iter().enumerate() {
-        //             // Attempt to retrieve corresponding color and other data, if they exist
-        //             let color = shape_item
-        //                 .1
-        //                 .as_ref()
-        //                 .and_then(|c| c.get(i))
-        //                 .copied()
-        //                 .unwrap_or_default(); // Provide default if not available
-        //             let other_data = shape_item
-        //                 .2
-        //                 .as_ref()
-        //                 .and_then(|o| o.get(i))
-        //                 .copied()
-        //                 .unwrap_or_default(); // Provide default if not available
-
-        //             // Assuming `vertex` can handle optional inputs or defaults
-        //             vertex_data.push(vertex(*position, color, other_data));
-        //         }
-        //         vertex_data_vec.push(vertex_data);
-        //     }
-        // }
-        let init = transforms::InitWgpu::init_wgpu(window).await;
+    }
+
+    fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.num_indices == 0 {
+            return;
+        }
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    }
+}
+
+fn create_msaa_texture(init: &transforms::InitWgpu) -> Option<texture::Texture> {
+    if init.sample_count == 1 {
+        return None;
+    }
+    Some(texture::Texture::create_msaa_texture(
+        &init.device,
+        &init.config,
+        init.sample_count,
+        "msaa_texture",
+    ))
+}
+
+fn create_block_pipeline(
+    init: &transforms::InitWgpu,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    label: &str,
+    (vs_entry, fs_entry): (&str, &str),
+    blend: wgpu::BlendState,
+    depth_write_enabled: bool,
+) -> wgpu::RenderPipeline {
+    init.device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: vs_entry,
+                buffers: &[Vertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: fs_entry,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: init.config.format,
+                    blend: Some(blend),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: init.sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+        })
+}
+
+// unlit lines in their vertex colours, depth tested against the world
+fn create_line_pipeline(
+    init: &transforms::InitWgpu,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    init.device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Line Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_line",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: init.config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: init.sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+        })
+}
+
+pub fn default_camera(position: [f32; 3]) -> Camera {
+    camera::Camera::new(position, cgmath::Deg(-90.0), cgmath::Deg(-20.0))
+}
+
+impl State {
+    pub async fn new(
+        window: &Window,
+        world: World,
+        surfaces: &Surfaces,
+        style: TerrainStyle,
+        light_data: Light,
+    ) -> Self {
+        let init = transforms::InitWgpu::init_wgpu(window, SAMPLE_COUNT).await;
+        Self::with_init(
+            init,
+            default_camera(world.spawn),
+            world,
+            surfaces,
+            style,
+            light_data,
+        )
+    }
+
+    // renders into textures instead of a window, see render_to_image
+    pub async fn new_headless(
+        width: u32,
+        height: u32,
+        camera: Camera,
+        world: World,
+        surfaces: &Surfaces,
+        style: TerrainStyle,
+        light_data: Light,
+    ) -> anyhow::Result<Self> {
+        let init = transforms::InitWgpu::init_headless(width, height, SAMPLE_COUNT).await?;
+        Ok(Self::with_init(
+            init, camera, world, surfaces, style, light_data,
+        ))
+    }
 
+    fn with_init(
+        init: transforms::InitWgpu,
+        camera: Camera,
+        world: World,
+        surfaces: &Surfaces,
+        style: TerrainStyle,
+        light_data: Light,
+    ) -> Self {
         let shader = init
             .device
             .create_shader_module(wgpu::ShaderModuleDescriptor {
@@ -312,7 +929,6 @@ impl State {
                 source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
                 //source: wgpu::ShaderSource::Wgsl(include_str!(concat!(env!("CARGO_MANIFEST_DIR"),"/examples/ch06/line3d.wgsl")).into()),
             });
-        let camera = camera::Camera::new((0.0, 5.0, 10.0), cgmath::Deg(-90.0), cgmath::Deg(-20.0));
         let projection = camera::Projection::new(
             init.config.width,
             init.config.height,
@@ -334,8 +950,10 @@ impl State {
         //     znear: 0.1,
         //     zfar: 100.0,
         // };
+        let time_of_day = 0.1;
         let mut camera_uniform = CameraUniform::new();
         camera_uniform.update_view_proj(&camera, &projection);
+        camera_uniform.update_sun(time_of_day);
         let camera_buffer = init
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -348,7 +966,8 @@ impl State {
                 .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                     entries: &[wgpu::BindGroupLayoutEntry {
                         binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX,
+                        // the sky pass reads the camera in its fragment shader
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
                         ty: wgpu::BindingType::Buffer {
                             ty: wgpu::BufferBindingType::Uniform,
                             has_dynamic_offset: false,
@@ -367,14 +986,13 @@ impl State {
             label: Some("camera_bind_group"),
         });
 
-        let diffuse_bytes = include_bytes!("grass_block.jpg"); // CHANGED!
-        let diffuse_texture = texture::Texture::from_bytes(
+        let diffuse_texture = texture::Texture::from_image(
             &init.device,
             &init.queue,
-            diffuse_bytes,
-            "grass_block.png",
+            &image::DynamicImage::ImageRgba8(atlas::block_atlas()),
+            Some("block_atlas"),
         )
-        .unwrap(); // CHANGED!
+        .unwrap();
         let texture_bind_group_layout =
             init.device
                 .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -543,110 +1161,137 @@ impl State {
                 push_constant_ranges: &[],
             });
 
-        let pipeline = init
+        let pipeline = create_block_pipeline(
+            &init,
+            &pipeline_layout,
+            &shader,
+            "Render Pipeline",
+            ("vs_main", "fs_main"),
+            wgpu::BlendState::REPLACE,
+            true,
+        );
+        let cutout_pipeline = create_block_pipeline(
+            &init,
+            &pipeline_layout,
+            &shader,
+            "Cutout Pipeline",
+            ("vs_main", "fs_cutout"),
+            wgpu::BlendState::REPLACE,
+            true,
+        );
+        let translucent_pipeline = create_block_pipeline(
+            &init,
+            &pipeline_layout,
+            &shader,
+            "Translucent Pipeline",
+            ("vs_main", "fs_main"),
+            wgpu::BlendState::ALPHA_BLENDING,
+            false,
+        );
+        let water_pipeline = create_block_pipeline(
+            &init,
+            &pipeline_layout,
+            &shader,
+            "Water Pipeline",
+            ("vs_water", "fs_water"),
+            wgpu::BlendState::ALPHA_BLENDING,
+            false,
+        );
+        let surface_pipeline = create_block_pipeline(
+            &init,
+            &pipeline_layout,
+            &shader,
+            "Surface Pipeline",
+            ("vs_main", "fs_surface"),
+            wgpu::BlendState::REPLACE,
+            true,
+        );
+        // overlays sit at depth 0 in front of everything, unless they're given
+        // a depth like labels
+        let overlay_pipeline = create_block_pipeline(
+            &init,
+            &pipeline_layout,
+            &shader,
+            "Overlay Pipeline",
+            ("vs_overlay", "fs_overlay"),
+            wgpu::BlendState::ALPHA_BLENDING,
+            false,
+        );
+        let line_pipeline = create_line_pipeline(&init, &pipeline_layout, &shader);
+
+        // the sky is a single full-screen triangle drawn behind the terrain
+        let sky_pipeline = init
             .device
             .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("Render Pipeline"),
+                label: Some("Sky Pipeline"),
                 layout: Some(&pipeline_layout),
                 vertex: wgpu::VertexState {
                     module: &shader,
-                    entry_point: "vs_main",
-                    buffers: &[Vertex::desc()],
+                    entry_point: "vs_sky",
+                    buffers: &[],
                 },
                 fragment: Some(wgpu::FragmentState {
                     module: &shader,
-                    entry_point: "fs_main",
+                    entry_point: "fs_sky",
                     targets: &[Some(wgpu::ColorTargetState {
                         format: init.config.format,
-                        blend: Some(wgpu::BlendState {
-                            color: wgpu::BlendComponent::REPLACE,
-                            alpha: wgpu::BlendComponent::REPLACE,
-                        }),
+                        blend: Some(wgpu::BlendState::REPLACE),
                         write_mask: wgpu::ColorWrites::ALL,
                     })],
                 }),
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    ..Default::default()
-                },
-                //depth_stencil: None,
+                primitive: wgpu::PrimitiveState::default(),
                 depth_stencil: Some(wgpu::DepthStencilState {
-                    format: wgpu::TextureFormat::Depth24Plus,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::LessEqual,
+                    format: texture::Texture::DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
                     stencil: wgpu::StencilState::default(),
                     bias: wgpu::DepthBiasState::default(),
                 }),
-                multisample: wgpu::MultisampleState::default(),
+                multisample: wgpu::MultisampleState {
+                    count: init.sample_count,
+                    ..Default::default()
+                },
                 multiview: None,
             });
-        let vertex_buffer_vec = init
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: cast_slice(&vertex_data_vec),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
-        let num_vertices_vec = visible_cubes.len() as u32;
-        let index_buffer_vec = init
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Index Buffer"),
-                contents: cast_slice(&(0..visible_cubes.len() as u32).collect::<Vec<u32>>()),
-                usage: wgpu::BufferUsages::INDEX,
-            });
-        // let vertex_buffer_vec = vertex_data_vec
-        //     .iter()
-        //     .map(|vertex_data| {
-        //         init.device
-        //             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        //                 label: Some("Vertex Buffer"),
-        //                 contents: cast_slice(&vertex_data),
-        //                 usage: wgpu::BufferUsages::VERTEX,
-        //             })
-        //     })
-        //     .collect::<Vec<wgpu::Buffer>>();
-        // let index_buffer_vec = shape_data
-        //     .iter()
-        //     .flatten()
-        //     .flatten()
-        //     .map(|shape_data| {
-        //         init.device
-        //             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        //                 label: Some("Index Buffer"),
-        //                 contents: cast_slice(&shape_data.indices),
-        //                 usage: wgpu::BufferUsages::INDEX,
-        //             })
-        //     })
-        //     .collect::<Vec<wgpu::Buffer>>();
-
-        // let num_vertices_vec = shape_data
-        //     .iter()
-        //     .flatten()
-        //     .flatten()
-        //     .map(|shape_data| shape_data.indices.len() as u32)
-        //     .collect::<Vec<u32>>();
-
-        // let index_buffer_vec = shape_data
-        //     .iter()
-        //     .map(|shape_data| {
-        //         init.device
-        //             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        //                 label: Some("Index Buffer"),
-        //                 contents: cast_slice(&shape_data.3),
-        //                 usage: wgpu::BufferUsages::INDEX,
-        //             })
-        //     })
-        //     .collect::<Vec<wgpu::Buffer>>();
-        // let num_vertices_vec = shape_data
-        //     .iter()
-        //     .map(|shape_data| shape_data.3.len() as u32)
-        //     .collect::<Vec<u32>>();
+        let depth_texture = texture::Texture::create_depth_texture(
+            &init.device,
+            &init.config,
+            init.sample_count,
+            "depth_texture",
+        );
+        let msaa_texture = create_msaa_texture(&init);
+
+        let smooth_mesher =
+            (style == TerrainStyle::Smooth).then(|| smooth::SmoothMesher::new(&world));
+        let buffers = WorldBuffers::new(
+            &init.device,
+            &world,
+            style,
+            &surfaces.mesh,
+            smooth_mesher.as_ref(),
+        );
+        let legend = (!surfaces.colormaps.is_empty()).then(|| {
+            let mut legend = Overlay::new(
+                &init,
+                &texture_bind_group_layout,
+                &legend_image(&surfaces.colormaps),
+                "Legend",
+            );
+            legend.place(&init);
+            legend
+        });
+        let axes = LayerBuffers::new(&init.device, &surfaces.axes, "Axes");
+        let labels = (!surfaces.labels.is_empty())
+            .then(|| Labels::new(&init, &texture_bind_group_layout, &surfaces.labels));
 
         Self {
             init,
             pipeline,
-            vertex_buffer_vec,
+            cutout_pipeline,
+            translucent_pipeline,
+            water_pipeline,
+            surface_pipeline,
+            sky_pipeline,
             uniform_bind_group,
             vertex_uniform_buffer,
             camera,
@@ -656,23 +1301,145 @@ impl State {
             camera_controller,
             camera_uniform,
             mouse_pressed: false,
-            num_vertices_vec,
-            index_buffer_vec,
+            time_of_day,
+            time: 0.0,
             diffuse_bind_group,
             diffuse_texture,
+            depth_texture,
+            msaa_texture,
+            buffers,
+            style,
+            smooth_mesher,
+            legend,
+            overlay_pipeline,
+            axes,
+            labels,
+            line_pipeline,
+            world,
+            storage: None,
+            since_autosave: Duration::ZERO,
+            loaded_around: None,
+            sorted_from: None,
+            translucent_order: Vec::new(),
+        }
+    }
+
+    // saves the world into storage from now on, restoring the player and the
+    // time of day if it was saved before
+    pub fn attach_storage(&mut self, storage: WorldStorage) -> anyhow::Result<()> {
+        if let Some(level) = storage.load_level()? {
+            self.camera.position = level.player_position.into();
+            self.time_of_day = level.time_of_day;
+        }
+        self.storage = Some(storage);
+        Ok(())
+    }
+
+    // loads the saved chunks around the player that aren't in the world yet
+    // each time they walk into another chunk, and remeshes if any came in
+    fn load_chunks(&mut self) {
+        let Some(storage) = &self.storage else {
+            return;
+        };
+        let position: [f32; 3] = self.camera.position.into();
+        let center = chunk_pos(position[0].floor() as i32, position[2].floor() as i32);
+        if self.loaded_around == Some(center) {
+            return;
+        }
+        self.loaded_around = Some(center);
+        match storage.load_around(&mut self.world, position, LOAD_RADIUS) {
+            Ok(loaded) if loaded.is_empty() => {}
+            Ok(loaded) => {
+                self.buffers.chunks_added(
+                    &self.init.device,
+                    &self.world,
+                    self.style,
+                    self.smooth_mesher.as_ref(),
+                    &loaded,
+                );
+                self.sorted_from = None;
+            }
+            Err(e) => eprintln!("Failed to load chunks: {:?}", e),
         }
     }
 
+    // sorts the translucent and water faces back to front from the camera, if
+    // it moved far enough since they were last sorted or the meshes changed
+    fn sort_translucent(&mut self) {
+        let eye: [f32; 3] = self.camera.position.into();
+        let moved = |from: [f32; 3]| {
+            let d: f32 = (0..3).map(|axis| (eye[axis] - from[axis]).powi(2)).sum();
+            d > RESORT_DISTANCE * RESORT_DISTANCE
+        };
+        if self.sorted_from.is_some_and(|from| !moved(from)) {
+            return;
+        }
+        self.sorted_from = Some(eye);
+        // faces are sorted within each chunk and the chunks are drawn far to near
+        let mut order = Vec::new();
+        for (&pos, chunk) in &self.buffers.blocks {
+            let layers = [
+                (&chunk.translucent, &chunk.translucent_vertices),
+                (&chunk.water, &chunk.water_vertices),
+            ];
+            if layers.iter().all(|(layer, _)| layer.num_indices == 0) {
+                continue;
+            }
+            for (layer, vertices) in layers {
+                if layer.num_indices > 0 {
+                    let indices = back_to_front_indices(vertices, eye);
+                    self.init.queue.write_buffer(
+                        &layer.index_buffer,
+                        0,
+                        bytemuck::cast_slice(&indices),
+                    );
+                }
+            }
+            let center = pos.map(|c| (c as f32 + 0.5) * CHUNK_SIZE as f32);
+            let distance = (center[0] - eye[0]).powi(2) + (center[1] - eye[2]).powi(2);
+            order.push((distance, pos));
+        }
+        order.sort_by(|a, b| b.0.total_cmp(&a.0));
+        self.translucent_order = order.into_iter().map(|(_, pos)| pos).collect();
+    }
+
+    // writes the changed chunks and the level file, does nothing without storage
+    pub fn save(&mut self) -> anyhow::Result<()> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+        storage.save_chunks(&mut self.world)?;
+        storage.save_level(&Level {
+            seed: self.world.seed,
+            spawn: self.world.spawn,
+            time_of_day: self.time_of_day,
+            player_position: self.camera.position.into(),
+            generator: self.world.generator.clone(),
+        })?;
+        self.since_autosave = Duration::ZERO;
+        Ok(())
+    }
+
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.init.instance.poll_all(true);
             self.init.size = new_size;
             self.init.config.width = new_size.width;
             self.init.config.height = new_size.height;
-            self.init
-                .surface
-                .configure(&self.init.device, &self.init.config);
+            if let Some(surface) = &self.init.surface {
+                surface.configure(&self.init.device, &self.init.config);
+            }
             self.projection.resize(new_size.width, new_size.height);
+            self.depth_texture = texture::Texture::create_depth_texture(
+                &self.init.device,
+                &self.init.config,
+                self.init.sample_count,
+                "depth_texture",
+            );
+            self.msaa_texture = create_msaa_texture(&self.init);
+            if let Some(legend) = &mut self.legend {
+                legend.place(&self.init);
+            }
             // self.project_mat = transforms::create_projection(
             //     new_size.width as f32 / new_size.height as f32,
             //     IS_PERSPECTIVE,
@@ -711,13 +1478,33 @@ impl State {
     pub fn update(&mut self, dt: std::time::Duration) {
         // let dt = ANIMATION_SPEED * dt;
         self.camera_controller.update_camera(&mut self.camera, dt);
+        self.time += dt.as_secs_f32();
+        self.time_of_day = (self.time_of_day + dt.as_secs_f32() / DAY_LENGTH).fract();
+        if self.storage.is_some() {
+            self.since_autosave += dt;
+            if self.since_autosave >= AUTOSAVE_INTERVAL {
+                if let Err(e) = self.save() {
+                    eprintln!("Autosave failed: {:?}", e);
+                }
+                // don't retry every frame after a failure
+                self.since_autosave = Duration::ZERO;
+            }
+        }
+        self.load_chunks();
         self.camera_uniform
             .update_view_proj(&self.camera, &self.projection);
+        self.camera_uniform.update_sun(self.time_of_day);
+        self.camera_uniform.update_time(self.time);
         self.init.queue.write_buffer(
             &self.camera_buffer,
             0,
             bytemuck::cast_slice(&[self.camera_uniform]),
         );
+        if let Some(labels) = &self.labels {
+            labels.update(&self.init, self.camera_uniform.view_proj.into());
+        }
+
+        self.sort_translucent();
 
         let model_mat =
             transforms::create_transforms([0.0, 0.0, 0.0], [0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
@@ -747,27 +1534,39 @@ impl State {
     }
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
         //let output = self.init.surface.get_current_frame()?.output;
-        let output = self.init.surface.get_current_texture()?;
+        let surface = self
+            .init
+            .surface
+            .as_ref()
+            .expect("render needs a window, use render_to_image when headless");
+        let output = surface.get_current_texture()?;
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
 
-        let depth_texture = self.init.device.create_texture(&wgpu::TextureDescriptor {
-            size: wgpu::Extent3d {
-                width: self.init.config.width,
-                height: self.init.config.height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Depth24Plus,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            label: None,
-            view_formats: &[],
-        });
-        let depth_view = depth_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.draw_frame(&view);
+        output.present();
+
+        Ok(())
+    }
+
+    // renders the current frame offscreen at the surface size and reads it back
+    pub fn render_to_image(&mut self) -> anyhow::Result<image::RgbaImage> {
+        let target = texture::Texture::create_readback_texture(
+            &self.init.device,
+            &self.init.config,
+            "offscreen_texture",
+        );
+        self.draw_frame(&target.view);
+        target.to_image(&self.init.device, &self.init.queue)
+    }
+
+    pub fn save_screenshot(&mut self, path: &Path) -> anyhow::Result<()> {
+        self.render_to_image()?.save(path)?;
+        Ok(())
+    }
 
+    fn draw_frame(&self, view: &wgpu::TextureView) {
         let mut encoder =
             self.init
                 .device
@@ -779,21 +1578,21 @@ impl State {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
+                    // with MSAA on, draw into the multisampled target and resolve into the frame
+                    view: match &self.msaa_texture {
+                        Some(msaa) => &msaa.view,
+                        None => view,
+                    },
+                    resolve_target: self.msaa_texture.as_ref().map(|_| view),
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.2,
-                            g: 0.247,
-                            b: 0.314,
-                            a: 1.0,
-                        }),
+                        // every pixel is covered by the sky pass
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
                         store: true,
                     },
                 })],
                 //depth_stencil_attachment: None,
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &depth_view,
+                    view: &self.depth_texture.view,
                     depth_ops: Some(wgpu::Operations {
                         load: wgpu::LoadOp::Clear(1.0),
                         store: false,
@@ -802,23 +1601,90 @@ impl State {
                 }),
             });
 
-            render_pass.set_pipeline(&self.pipeline);
             render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
             render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
             render_pass.set_bind_group(2, &self.diffuse_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer_vec.slice(..));
-            render_pass
-                .set_index_buffer(self.index_buffer_vec.slice(..), wgpu::IndexFormat::Uint32);
-            render_pass.draw_indexed(0..self.num_vertices_vec, 0, 0..1);
+
+            render_pass.set_pipeline(&self.sky_pipeline);
+            render_pass.draw(0..3, 0..1);
+
+            render_pass.set_pipeline(&self.pipeline);
+            for chunk in self.buffers.blocks.values() {
+                chunk.opaque.draw(&mut render_pass);
+            }
+            render_pass.set_pipeline(&self.cutout_pipeline);
+            for chunk in self.buffers.blocks.values() {
+                chunk.cutout.draw(&mut render_pass);
+            }
+            render_pass.set_pipeline(&self.surface_pipeline);
+            self.buffers.surfaces.draw(&mut render_pass);
+            for chunk in self.buffers.smooth.values() {
+                chunk.draw(&mut render_pass);
+            }
+            render_pass.set_pipeline(&self.line_pipeline);
+            self.axes.draw(&mut render_pass);
+            let translucent: Vec<&BlockBuffers> = self
+                .translucent_order
+                .iter()
+                .filter_map(|pos| self.buffers.blocks.get(pos))
+                .collect();
+            render_pass.set_pipeline(&self.water_pipeline);
+            for chunk in &translucent {
+                chunk.water.draw(&mut render_pass);
+            }
+            render_pass.set_pipeline(&self.translucent_pipeline);
+            for chunk in &translucent {
+                chunk.translucent.draw(&mut render_pass);
+            }
+
+            if let Some(legend) = &self.legend {
+                render_pass.set_pipeline(&self.overlay_pipeline);
+                render_pass.set_bind_group(2, &legend.bind_group, &[]);
+                legend.buffers.draw(&mut render_pass);
+            }
+            if let Some(labels) = &self.labels {
+                render_pass.set_pipeline(&self.overlay_pipeline);
+                render_pass.set_bind_group(2, &labels.overlay.bind_group, &[]);
+                labels.overlay.buffers.draw(&mut render_pass);
+            }
         }
 
         self.init.queue.submit(iter::once(encoder.finish()));
-        output.present();
-
-        Ok(())
     }
 }
-pub fn run(shape_data: &Vec<Vec<Vec<Mesh>>>, light_data: Light) {
+
+// renders one frame of the world and the surfaces in it without opening a
+// window and writes it as a PNG
+pub fn screenshot(
+    world: World,
+    surfaces: &Surfaces,
+    style: TerrainStyle,
+    light_data: Light,
+    camera: Camera,
+    [width, height]: [u32; 2],
+    path: &Path,
+) -> anyhow::Result<()> {
+    let mut state = pollster::block_on(State::new_headless(
+        width, height, camera, world, surfaces, style, light_data,
+    ))?;
+    state.update(Duration::ZERO);
+    state.save_screenshot(path)
+}
+
+fn screenshot_path() -> PathBuf {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    PathBuf::from(format!("screenshot-{}.png", seconds))
+}
+pub fn run(
+    world: World,
+    surfaces: &Surfaces,
+    style: TerrainStyle,
+    storage: Option<WorldStorage>,
+    light_data: Light,
+) {
     env_logger::init();
     let event_loop = EventLoop::new();
     let window = winit::window::WindowBuilder::new()
@@ -826,7 +1692,13 @@ pub fn run(shape_data: &Vec<Vec<Vec<Mesh>>>, light_data: Light) {
         .unwrap();
     window.set_title(&*format!("Parametric 3D Surface"));
 
-    let mut state = pollster::block_on(State::new(&window, &shape_data, light_data));
+    let mut state = pollster::block_on(State::new(&window, world, surfaces, style, light_data));
+    if let Some(storage) = storage {
+        if let Err(e) = state.attach_storage(storage) {
+            eprintln!("Failed to load world: {:?}", e);
+            std::process::exit(1);
+        }
+    }
     let mut render_start_time = std::time::Instant::now();
     let mut frame_count = 0;
     let mut elapsed_time = Duration::new(0, 0);
@@ -848,6 +1720,21 @@ pub fn run(shape_data: &Vec<Vec<Vec<Mesh>>>, light_data: Light) {
                             },
                         ..
                     } => *control_flow = ControlFlow::Exit,
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F2),
+                                ..
+                            },
+                        ..
+                    } => {
+                        let path = screenshot_path();
+                        match state.save_screenshot(&path) {
+                            Ok(()) => println!("Saved screenshot to {}", path.display()),
+                            Err(e) => eprintln!("Failed to save screenshot: {:?}", e),
+                        }
+                    }
                     WindowEvent::Resized(physical_size) => {
                         state.resize(*physical_size);
                     }
@@ -885,6 +1772,11 @@ pub fn run(shape_data: &Vec<Vec<Vec<Mesh>>>, light_data: Light) {
         Event::MainEventsCleared => {
             window.request_redraw();
         }
+        Event::LoopDestroyed => {
+            if let Err(e) = state.save() {
+                eprintln!("Failed to save world: {:?}", e);
+            }
+        }
         _ => {}
     });
 }
@@ -0,0 +1,276 @@
+#![allow(dead_code)]
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    biome::Biome,
+    block::Block,
+    terrain::chunk_rng,
+    world::{Chunk, ChunkPos, World, CHUNK_SIZE},
+};
+
+const DECORATION_SALT: u64 = 0x6465_636f;
+// trees keep this far from structures so their crowns don't grow into them
+const STRUCTURE_CLEARANCE: usize = 3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TreeShape {
+    // a short trunk under a round crown
+    Oak,
+    // a taller trunk with a narrow crown
+    Birch,
+    // layered cone of leaves around a tall trunk
+    Spruce,
+    // a wide, flat crown low over the water
+    Swamp,
+    // a single log in a ball of leaves
+    Bush,
+}
+
+// what grows in a biome, counts are per chunk on average
+struct Vegetation {
+    // the ground trees, grass and flowers grow on, cacti always grow on sand
+    soil: Block,
+    trees: f32,
+    shapes: &'static [TreeShape],
+    grass: u32,
+    flowers: u32,
+    cacti: u32,
+}
+
+fn vegetation(biome: Biome) -> Vegetation {
+    let v = |trees, shapes, grass, flowers, cacti| Vegetation {
+        soil: Block::Grass,
+        trees,
+        shapes,
+        grass,
+        flowers,
+        cacti,
+    };
+    match biome {
+        Biome::Plains => v(0.4, &[TreeShape::Oak, TreeShape::Bush], 24, 4, 0),
+        Biome::Forest => v(
+            7.0,
+            &[TreeShape::Oak, TreeShape::Oak, TreeShape::Birch],
+            8,
+            2,
+            0,
+        ),
+        Biome::Taiga => v(5.0, &[TreeShape::Spruce], 6, 0, 0),
+        Biome::Mountains => v(0.6, &[TreeShape::Spruce], 4, 0, 0),
+        // swamps are muddy, plants grow straight out of the dirt
+        Biome::Swamp => Vegetation {
+            soil: Block::Dirt,
+            ..v(2.0, &[TreeShape::Swamp, TreeShape::Bush], 12, 1, 0)
+        },
+        Biome::Desert => v(0.0, &[], 0, 0, 3),
+        Biome::Ocean => v(0.0, &[], 0, 0, 0),
+    }
+}
+
+// the highest block of a column and the block on it, looking through trees
+// from neighbouring chunks so it doesn't matter which chunk is decorated first
+fn ground(chunk: &Chunk, x: usize, z: usize) -> Option<(usize, Block)> {
+    (0..chunk.top())
+        .rev()
+        .map(|y| (y, chunk.get(x, y, z)))
+        .find(|&(_, block)| !block.is_replaceable() && !matches!(block, Block::Leaves | Block::Log))
+}
+
+// whether any ground within STRUCTURE_CLEARANCE of a column is part of a
+// structure, only looking inside the chunk
+fn near_structure(chunk: &Chunk, x: usize, z: usize) -> bool {
+    let range = |v: usize| {
+        v.saturating_sub(STRUCTURE_CLEARANCE)..=(v + STRUCTURE_CLEARANCE).min(CHUNK_SIZE - 1)
+    };
+    range(z).any(|z| {
+        range(x).any(|x| {
+            matches!(
+                ground(chunk, x, z),
+                Some((_, Block::Path | Block::Planks | Block::Cobblestone))
+            )
+        })
+    })
+}
+
+// places trees, grass, flowers and cacti on a chunk that's already in the
+// world. Trees near the edge reach into neighbouring chunks, which get the
+// blocks now if they exist and when they're generated otherwise
+pub fn decorate(world: &mut World, pos: ChunkPos, seed: u64) {
+    let Some(chunk) = world.chunk(pos) else {
+        return;
+    };
+    let mut rng = chunk_rng(seed, pos, DECORATION_SALT);
+    // features are chosen from the undecorated chunk first, so what one places
+    // doesn't change where the others go
+    let mut features = Vec::new();
+    let biome = |x, z| chunk.biome(x, z).unwrap_or_default();
+    let column = |rng: &mut StdRng| {
+        let (x, z) = (rng.gen_range(0..CHUNK_SIZE), rng.gen_range(0..CHUNK_SIZE));
+        ground(chunk, x, z).map(|(y, block)| ([x, y, z], block))
+    };
+    // the ground of a column plants can grow on, by its own biome
+    let soil = |rng: &mut StdRng| {
+        column(rng).filter(|&([x, _, z], block)| block == vegetation(biome(x, z)).soil)
+    };
+
+    // the biome in the middle of the chunk decides how much grows in it
+    let middle = vegetation(biome(CHUNK_SIZE / 2, CHUNK_SIZE / 2));
+    let trees = middle.trees as u32 + rng.gen_bool(middle.trees.fract() as f64) as u32;
+    for _ in 0..trees {
+        if let Some(([x, y, z], _)) = soil(&mut rng) {
+            let shapes = vegetation(biome(x, z)).shapes;
+            if !shapes.is_empty() && !near_structure(chunk, x, z) {
+                let shape = shapes[rng.gen_range(0..shapes.len())];
+                features.push(([x, y + 1, z], Feature::Tree(shape, rng.gen())));
+            }
+        }
+    }
+    for _ in 0..middle.grass {
+        if let Some(([x, y, z], _)) = soil(&mut rng) {
+            features.push(([x, y + 1, z], Feature::Block(Block::TallGrass)));
+        }
+    }
+    for _ in 0..middle.flowers {
+        if let Some(([x, y, z], _)) = soil(&mut rng) {
+            features.push(([x, y + 1, z], Feature::Block(Block::Flower)));
+        }
+    }
+    for _ in 0..middle.cacti {
+        if let Some(([x, y, z], Block::Sand)) = column(&mut rng) {
+            let height = rng.gen_range(1..=3);
+            features.push(([x, y + 1, z], Feature::Cactus(height)));
+        }
+    }
+
+    let origin = [pos[0] * CHUNK_SIZE as i32, pos[1] * CHUNK_SIZE as i32];
+    for ([x, y, z], feature) in features {
+        let at = [origin[0] + x as i32, y as i32, origin[1] + z as i32];
+        feature.place(world, at);
+    }
+}
+
+enum Feature {
+    Block(Block),
+    Cactus(i32),
+    // the shape and a seed for its size and leaves
+    Tree(TreeShape, u64),
+}
+
+impl Feature {
+    fn place(self, world: &mut World, [x, y, z]: [i32; 3]) {
+        match self {
+            Feature::Block(block) => world.place_block(x, y, z, block),
+            Feature::Cactus(height) => {
+                for dy in 0..height {
+                    world.place_block(x, y + dy, z, Block::Cactus);
+                }
+            }
+            Feature::Tree(shape, seed) => place_tree(world, [x, y, z], shape, seed),
+        }
+    }
+}
+
+// leaves in a disc around a trunk, the corners only some of the time
+fn leaf_layer(world: &mut World, rng: &mut StdRng, [x, y, z]: [i32; 3], radius: i32) {
+    for dz in -radius..=radius {
+        for dx in -radius..=radius {
+            let corner = dx.abs() == radius && dz.abs() == radius;
+            if radius > 0 && corner && rng.gen_bool(0.6) {
+                continue;
+            }
+            world.place_block(x + dx, y, z + dz, Block::Leaves);
+        }
+    }
+}
+
+fn place_tree(world: &mut World, [x, y, z]: [i32; 3], shape: TreeShape, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let trunk = match shape {
+        TreeShape::Oak => rng.gen_range(4..=6),
+        TreeShape::Birch => rng.gen_range(6..=8),
+        TreeShape::Spruce => rng.gen_range(6..=9),
+        TreeShape::Swamp => rng.gen_range(4..=5),
+        TreeShape::Bush => 1,
+    };
+    let top = y + trunk - 1;
+    // leaves first so the trunk replaces the ones it runs through
+    match shape {
+        TreeShape::Oak => {
+            leaf_layer(world, &mut rng, [x, top - 2, z], 2);
+            leaf_layer(world, &mut rng, [x, top - 1, z], 2);
+            leaf_layer(world, &mut rng, [x, top, z], 1);
+            leaf_layer(world, &mut rng, [x, top + 1, z], 1);
+        }
+        TreeShape::Birch => {
+            for dy in -3..=0 {
+                leaf_layer(world, &mut rng, [x, top + dy, z], 1);
+            }
+            leaf_layer(world, &mut rng, [x, top + 1, z], 0);
+        }
+        TreeShape::Spruce => {
+            // widening downwards in steps, each step a little narrower on top
+            for dy in 0..trunk - 1 {
+                let radius = ((dy + 1) / 2).min(3) - (dy % 2 == 0 && dy > 0) as i32;
+                leaf_layer(world, &mut rng, [x, top + 1 - dy, z], radius);
+            }
+        }
+        TreeShape::Swamp => {
+            leaf_layer(world, &mut rng, [x, top - 1, z], 3);
+            leaf_layer(world, &mut rng, [x, top, z], 3);
+            leaf_layer(world, &mut rng, [x, top + 1, z], 2);
+        }
+        TreeShape::Bush => {
+            leaf_layer(world, &mut rng, [x, y, z], 1);
+            leaf_layer(world, &mut rng, [x, y + 1, z], 1);
+        }
+    }
+    for dy in 0..trunk {
+        world.place_block(x, y + dy, z, Block::Log);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a flat chunk of one biome with the given block on top
+    fn flat_chunk(biome: Biome, surface: Block) -> Chunk {
+        let mut chunk = Chunk::new();
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                for y in 0..64 {
+                    chunk.set(x, y, z, Block::Dirt);
+                }
+                chunk.set(x, 64, z, surface);
+            }
+        }
+        chunk.set_biomes(Some(vec![biome; CHUNK_SIZE * CHUNK_SIZE]));
+        chunk
+    }
+
+    fn decorated(biome: Biome, surface: Block) -> Vec<Block> {
+        let mut world = World::new(0);
+        world.insert_chunk([0, 0], flat_chunk(biome, surface));
+        decorate(&mut world, [0, 0], 3);
+        let chunk = world.chunk([0, 0]).unwrap();
+        (0..CHUNK_SIZE)
+            .flat_map(|z| (0..CHUNK_SIZE).map(move |x| chunk.get(x, 65, z)))
+            .collect()
+    }
+
+    #[test]
+    fn swamps_grow_on_dirt() {
+        let blocks = decorated(Biome::Swamp, Biome::Swamp.surface());
+        assert!(blocks
+            .iter()
+            .any(|&block| matches!(block, Block::TallGrass | Block::Log)));
+    }
+
+    #[test]
+    fn plains_need_grass() {
+        let on_grass = decorated(Biome::Plains, Block::Grass);
+        assert!(on_grass.contains(&Block::TallGrass));
+        let on_dirt = decorated(Biome::Plains, Block::Dirt);
+        assert!(on_dirt.iter().all(|&block| block == Block::Air));
+    }
+}
@@ -0,0 +1,343 @@
+#![allow(dead_code)]
+use anyhow::{bail, Context, Result};
+use image::{ImageOutputFormat, RgbaImage};
+use std::{fmt::Write as _, fs, io::Cursor, path::Path};
+
+use crate::{
+    atlas,
+    common::{mesh_world, Mesh, TerrainStyle},
+    world::World,
+};
+
+// meshes of each material, in the order the renderer draws them
+type Layers = Vec<(&'static str, Mesh)>;
+
+fn layers(world: &World) -> Layers {
+    let layers = mesh_world(world, TerrainStyle::Blocks);
+    vec![
+        ("opaque", layers.opaque),
+        ("cutout", layers.cutout),
+        ("water", layers.water),
+        ("translucent", layers.translucent),
+    ]
+}
+
+fn png_bytes(image: &RgbaImage) -> Result<Vec<u8>> {
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+    Ok(png)
+}
+
+// writes the world's meshes as .obj (with a .mtl and the atlas as a .png next
+// to it) or as a binary glTF with the atlas embedded, depending on the extension
+pub fn export(world: &World, path: &Path) -> Result<()> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase);
+    if !matches!(extension.as_deref(), Some("obj" | "glb")) {
+        bail!("can only export .obj or .glb files, not {}", path.display());
+    }
+    let layers = layers(world);
+    if layers.iter().all(|(_, mesh)| mesh.indices.is_empty()) {
+        bail!("nothing to export, the selection is empty");
+    }
+    if extension.as_deref() == Some("obj") {
+        write_obj(layers, path)
+    } else {
+        write_glb(layers, path)
+    }
+}
+
+fn write_obj(layers: Layers, path: &Path) -> Result<()> {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("world");
+    let mtl_name = format!("{}.mtl", stem);
+    let atlas_name = format!("{}_atlas.png", stem);
+
+    let mut obj = String::new();
+    writeln!(obj, "mtllib {}", mtl_name)?;
+    writeln!(obj, "o {}", stem)?;
+    // obj indices are 1-based and shared across the whole file
+    let mut offset = 1;
+    for (name, mesh) in layers {
+        if mesh.indices.is_empty() {
+            continue;
+        }
+        writeln!(obj, "g {}\nusemtl {}", name, name)?;
+        for v in &mesh.vertices {
+            // vertex colours after the position are understood by Blender and MeshLab
+            let [x, y, z, _] = v.position;
+            let [r, g, b, _] = v.color;
+            writeln!(obj, "v {} {} {} {} {} {}", x, y, z, r, g, b)?;
+        }
+        for v in &mesh.vertices {
+            // obj puts v = 0 at the bottom of the image
+            writeln!(obj, "vt {} {}", v.tex_coords[0], 1.0 - v.tex_coords[1])?;
+        }
+        for v in &mesh.vertices {
+            let [x, y, z, _] = v.normal;
+            writeln!(obj, "vn {} {} {}", x, y, z)?;
+        }
+        for triangle in mesh.indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize + offset);
+            writeln!(obj, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+        }
+        offset += mesh.vertices.len();
+    }
+
+    let mut mtl = String::new();
+    for (name, dissolve) in [
+        ("opaque", 1.0),
+        ("cutout", 1.0),
+        ("water", 0.7),
+        ("translucent", 0.8),
+    ] {
+        writeln!(
+            mtl,
+            "newmtl {}\nKd 1 1 1\nd {}\nmap_Kd {}",
+            name, dissolve, atlas_name
+        )?;
+        if name != "opaque" {
+            writeln!(mtl, "map_d {}", atlas_name)?;
+        }
+        writeln!(mtl)?;
+    }
+
+    let dir = path.parent().unwrap_or(Path::new(""));
+    fs::write(path, obj).with_context(|| format!("failed to write {}", path.display()))?;
+    fs::write(dir.join(&mtl_name), mtl).with_context(|| format!("failed to write {}", mtl_name))?;
+    atlas::block_atlas()
+        .save(dir.join(&atlas_name))
+        .with_context(|| format!("failed to write {}", atlas_name))?;
+    Ok(())
+}
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+
+// the binary chunk of a .glb and the JSON describing what's in it
+#[derive(Default)]
+struct GlbBuffer {
+    bin: Vec<u8>,
+    views: Vec<String>,
+    accessors: Vec<String>,
+}
+
+impl GlbBuffer {
+    // appends data, returning the index of its buffer view
+    fn view(&mut self, data: &[u8], target: Option<u32>) -> usize {
+        let target = target.map_or(String::new(), |t| format!(",\"target\":{}", t));
+        self.views.push(format!(
+            "{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{}{}}}",
+            self.bin.len(),
+            data.len(),
+            target
+        ));
+        self.bin.extend_from_slice(data);
+        // every view starts on a 4 byte boundary
+        self.bin.resize(self.bin.len().next_multiple_of(4), 0);
+        self.views.len() - 1
+    }
+
+    // appends data with an accessor over it, returning the accessor's index
+    fn accessor<T: bytemuck::Pod>(
+        &mut self,
+        data: &[T],
+        target: u32,
+        component: u32,
+        kind: &str,
+        extra: &str,
+    ) -> usize {
+        let view = self.view(bytemuck::cast_slice(data), Some(target));
+        self.accessors.push(format!(
+            "{{\"bufferView\":{},\"componentType\":{},\"count\":{},\"type\":\"{}\"{}}}",
+            view,
+            component,
+            data.len(),
+            kind,
+            extra
+        ));
+        self.accessors.len() - 1
+    }
+}
+
+fn write_glb(layers: Layers, path: &Path) -> Result<()> {
+    let mut buffer = GlbBuffer::default();
+    let mut primitives = Vec::new();
+    for (material, (_, mesh)) in layers.into_iter().enumerate() {
+        if mesh.indices.is_empty() {
+            continue;
+        }
+        let positions: Vec<[f32; 3]> = mesh
+            .vertices
+            .iter()
+            .map(|v| [v.position[0], v.position[1], v.position[2]])
+            .collect();
+        let normals: Vec<[f32; 3]> = mesh
+            .vertices
+            .iter()
+            .map(|v| [v.normal[0], v.normal[1], v.normal[2]])
+            .collect();
+        let uvs: Vec<[f32; 2]> = mesh.vertices.iter().map(|v| v.tex_coords).collect();
+        let colors: Vec<[f32; 4]> = mesh.vertices.iter().map(|v| v.color).collect();
+
+        // glTF requires bounds on positions
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for p in &positions {
+            for i in 0..3 {
+                min[i] = min[i].min(p[i]);
+                max[i] = max[i].max(p[i]);
+            }
+        }
+        let bounds = format!(
+            ",\"min\":[{},{},{}],\"max\":[{},{},{}]",
+            min[0], min[1], min[2], max[0], max[1], max[2]
+        );
+
+        let position = buffer.accessor(&positions, ARRAY_BUFFER, FLOAT, "VEC3", &bounds);
+        let normal = buffer.accessor(&normals, ARRAY_BUFFER, FLOAT, "VEC3", "");
+        let uv = buffer.accessor(&uvs, ARRAY_BUFFER, FLOAT, "VEC2", "");
+        let color = buffer.accessor(&colors, ARRAY_BUFFER, FLOAT, "VEC4", "");
+        let indices = buffer.accessor(
+            &mesh.indices,
+            ELEMENT_ARRAY_BUFFER,
+            UNSIGNED_INT,
+            "SCALAR",
+            "",
+        );
+        primitives.push(format!(
+            "{{\"attributes\":{{\"POSITION\":{},\"NORMAL\":{},\"TEXCOORD_0\":{},\"COLOR_0\":{}}},\"indices\":{},\"material\":{}}}",
+            position, normal, uv, color, indices, material
+        ));
+    }
+
+    let image_view = buffer.view(&png_bytes(&atlas::block_atlas())?, None);
+    let material = |name: &str, alpha: &str| {
+        format!(
+            "{{\"name\":\"{}\",\"pbrMetallicRoughness\":{{\"baseColorTexture\":{{\"index\":0}},\"metallicFactor\":0,\"roughnessFactor\":1}},{}}}",
+            name, alpha
+        )
+    };
+    // the sampler filters nearest to keep the blocky look
+    let json = format!(
+        concat!(
+            "{{\"asset\":{{\"version\":\"2.0\",\"generator\":\"wgpu18-klein-bottle\"}},",
+            "\"scene\":0,\"scenes\":[{{\"nodes\":[0]}}],\"nodes\":[{{\"mesh\":0,\"name\":\"world\"}}],",
+            "\"meshes\":[{{\"primitives\":[{}]}}],",
+            "\"materials\":[{},{},{},{}],",
+            "\"textures\":[{{\"sampler\":0,\"source\":0}}],",
+            "\"samplers\":[{{\"magFilter\":9728,\"minFilter\":9728}}],",
+            "\"images\":[{{\"bufferView\":{},\"mimeType\":\"image/png\"}}],",
+            "\"accessors\":[{}],\"bufferViews\":[{}],\"buffers\":[{{\"byteLength\":{}}}]}}"
+        ),
+        primitives.join(","),
+        material("opaque", "\"alphaMode\":\"OPAQUE\""),
+        material("cutout", "\"alphaMode\":\"MASK\",\"alphaCutoff\":0.5"),
+        material("water", "\"alphaMode\":\"BLEND\""),
+        material("translucent", "\"alphaMode\":\"BLEND\""),
+        image_view,
+        buffer.accessors.join(","),
+        buffer.views.join(","),
+        buffer.bin.len()
+    );
+
+    // header, then the JSON chunk padded with spaces and the binary chunk
+    let bin = buffer.bin;
+    let mut json = json.into_bytes();
+    json.resize(json.len().next_multiple_of(4), b' ');
+    let total = 12 + 8 + json.len() + 8 + bin.len();
+    let mut glb = Vec::with_capacity(total);
+    glb.extend_from_slice(b"glTF");
+    glb.extend_from_slice(&2u32.to_le_bytes());
+    glb.extend_from_slice(&(total as u32).to_le_bytes());
+    glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    glb.extend_from_slice(b"JSON");
+    glb.extend_from_slice(&json);
+    glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+    glb.extend_from_slice(b"BIN\0");
+    glb.extend_from_slice(&bin);
+    fs::write(path, glb).with_context(|| format!("failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+
+    // a directory of its own under the system temp dir, emptied first
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("export-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn one_block() -> World {
+        let mut world = World::new(0);
+        world.set_block(0, 10, 0, Block::Stone);
+        world
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> usize {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()) as usize
+    }
+
+    #[test]
+    fn obj_of_one_block() {
+        let dir = temp_dir("obj");
+        export(&one_block(), &dir.join("block.obj")).unwrap();
+        let obj = fs::read_to_string(dir.join("block.obj")).unwrap();
+        let count = |prefix: &str| obj.lines().filter(|line| line.starts_with(prefix)).count();
+        // six faces of two triangles, each with corners of its own
+        assert_eq!(count("v "), 36);
+        assert_eq!(count("vt "), 36);
+        assert_eq!(count("vn "), 36);
+        assert_eq!(count("f "), 12);
+        assert!(dir.join("block.mtl").exists());
+        assert!(dir.join("block_atlas.png").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn glb_of_one_block() {
+        let dir = temp_dir("glb");
+        let path = dir.join("block.glb");
+        export(&one_block(), &path).unwrap();
+        let glb = fs::read(&path).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(&glb[0..4], b"glTF");
+        assert_eq!(u32_at(&glb, 4), 2);
+        assert_eq!(u32_at(&glb, 8), glb.len());
+        let json_len = u32_at(&glb, 12);
+        assert_eq!(&glb[16..20], b"JSON");
+        assert_eq!(json_len % 4, 0);
+        let bin_at = 20 + json_len;
+        let bin_len = u32_at(&glb, bin_at);
+        assert_eq!(&glb[bin_at + 4..bin_at + 8], b"BIN\0");
+        assert_eq!(bin_len % 4, 0);
+        assert_eq!(bin_at + 8 + bin_len, glb.len());
+        // padded with spaces to a multiple of 4
+        let json = std::str::from_utf8(&glb[20..bin_at]).unwrap().trim_end();
+        assert!(json.ends_with(&format!("\"buffers\":[{{\"byteLength\":{}}}]}}", bin_len)));
+    }
+
+    #[test]
+    fn empty_selection_fails_for_both_formats() {
+        let dir = temp_dir("empty");
+        for name in ["empty.obj", "empty.glb"] {
+            let error = export(&World::new(0), &dir.join(name)).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "nothing to export, the selection is empty"
+            );
+            assert!(!dir.join(name).exists());
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,630 @@
+#![allow(dead_code)]
+use anyhow::{anyhow, bail, Context, Result};
+use std::{collections::HashMap, f32::consts::PI, path::Path, sync::Arc};
+
+use crate::{
+    heightfield::HeightField,
+    sd::{colormap::Colormap, ParametricSurface, SurfaceFunction},
+    voxelize::HeightFunction,
+};
+
+// the variables of x(u, v), y(u, v) and z(u, v), in the order eval takes them
+pub const SURFACE_VARIABLES: [&str; 7] = ["u", "v", "p0", "p1", "p2", "p3", "p4"];
+// the variables of y(x, z)
+pub const HEIGHT_VARIABLES: [&str; 7] = ["x", "z", "p0", "p1", "p2", "p3", "p4"];
+// values an expression can have waiting on the stack at once
+const MAX_STACK: usize = 32;
+// cells along each side of a drawn height field, however many samples it has
+const MAX_DATA_SEGMENTS: usize = 512;
+
+#[derive(Copy, Clone, Debug)]
+enum Op {
+    Number(f32),
+    Variable(usize),
+    Negate,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+    Call1(fn(f32) -> f32),
+    Call2(fn(f32, f32) -> f32),
+}
+
+// a math expression such as "(p0 + p1 * cos(v)) * cos(u)", compiled to a
+// list of stack operations so it's quick to evaluate at every sample of a
+// surface. It has numbers, variables, pi and e, + - * / and ^, and the
+// functions in `function`
+#[derive(Clone, Debug)]
+pub struct Expr {
+    pub source: String,
+    ops: Vec<Op>,
+}
+
+impl Expr {
+    // compiles an expression of the given variables
+    pub fn parse(source: &str, variables: &[&str]) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            next: 0,
+            variables,
+            ops: Vec::new(),
+        };
+        parser.sum()?;
+        if let Some(token) = parser.tokens.get(parser.next) {
+            bail!("unexpected {} in {:?}", token, source);
+        }
+        let expr = Self {
+            source: source.to_string(),
+            ops: parser.ops,
+        };
+        if expr.depth() > MAX_STACK {
+            bail!("{:?} is nested too deeply", source);
+        }
+        Ok(expr)
+    }
+
+    // the value with the variables set to values, in the order they were
+    // given to parse
+    pub fn eval(&self, values: &[f32]) -> f32 {
+        let mut stack = [0.0; MAX_STACK];
+        let mut len = 0;
+        for op in &self.ops {
+            match *op {
+                Op::Number(n) => {
+                    stack[len] = n;
+                    len += 1;
+                }
+                Op::Variable(i) => {
+                    stack[len] = values[i];
+                    len += 1;
+                }
+                Op::Negate => stack[len - 1] = -stack[len - 1],
+                Op::Call1(f) => stack[len - 1] = f(stack[len - 1]),
+                op => {
+                    len -= 1;
+                    let (a, b) = (stack[len - 1], stack[len]);
+                    stack[len - 1] = match op {
+                        Op::Add => a + b,
+                        Op::Subtract => a - b,
+                        Op::Multiply => a * b,
+                        Op::Divide => a / b,
+                        Op::Power => a.powf(b),
+                        Op::Call2(f) => f(a, b),
+                        _ => unreachable!(),
+                    };
+                }
+            }
+        }
+        stack[0]
+    }
+
+    // the most values on the stack while evaluating
+    fn depth(&self) -> usize {
+        let (mut len, mut most) = (0usize, 0);
+        for op in &self.ops {
+            match op {
+                Op::Number(_) | Op::Variable(_) => len += 1,
+                Op::Negate | Op::Call1(_) => {}
+                _ => len -= 1,
+            }
+            most = most.max(len);
+        }
+        most
+    }
+}
+
+// a number expression with no variables, like "2 * pi"
+pub fn constant(source: &str) -> Result<f32> {
+    Ok(Expr::parse(source, &[])?.eval(&[]))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f32),
+    Name(String),
+    Symbol(char),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Symbol(c) => write!(f, "{:?}", c),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                // an exponent's sign belongs to the number
+                let sign = (c == '+' || c == '-') && source[..i].ends_with(['e', 'E']);
+                if !(c.is_ascii_alphanumeric() || c == '.' || sign) {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let number = &source[start..end];
+            tokens.push(Token::Number(number.parse().map_err(|_| {
+                anyhow!("invalid number {} in {:?}", number, source)
+            })?));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token::Name(source[start..end].to_string()));
+        } else if "+-*/^(),".contains(c) {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        } else {
+            bail!("unexpected {:?} in {:?}", c, source);
+        }
+    }
+    Ok(tokens)
+}
+
+// recursive descent, one method per level of precedence, writing the
+// operations in the order they run
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    next: usize,
+    variables: &'a [&'a str],
+    ops: Vec<Op>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        let found = self.peek() == Some(&Token::Symbol(symbol));
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<()> {
+        match self.eat(symbol) {
+            true => Ok(()),
+            false => match self.peek() {
+                Some(token) => bail!("expected {:?} but found {}", symbol, token),
+                None => bail!("expected {:?} at the end", symbol),
+            },
+        }
+    }
+
+    // terms joined by + and -
+    fn sum(&mut self) -> Result<()> {
+        self.product()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol('+')) => Op::Add,
+                Some(Token::Symbol('-')) => Op::Subtract,
+                _ => return Ok(()),
+            };
+            self.next += 1;
+            self.product()?;
+            self.ops.push(op);
+        }
+    }
+
+    // factors joined by * and /
+    fn product(&mut self) -> Result<()> {
+        self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol('*')) => Op::Multiply,
+                Some(Token::Symbol('/')) => Op::Divide,
+                _ => return Ok(()),
+            };
+            self.next += 1;
+            self.unary()?;
+            self.ops.push(op);
+        }
+    }
+
+    // a minus binds looser than ^, so -x^2 is -(x^2)
+    fn unary(&mut self) -> Result<()> {
+        if self.eat('-') {
+            self.unary()?;
+            self.ops.push(Op::Negate);
+            return Ok(());
+        }
+        if self.eat('+') {
+            return self.unary();
+        }
+        self.power()
+    }
+
+    // ^ groups to the right, so 2^3^2 is 2^(3^2)
+    fn power(&mut self) -> Result<()> {
+        self.atom()?;
+        if self.eat('^') {
+            self.unary()?;
+            self.ops.push(Op::Power);
+        }
+        Ok(())
+    }
+
+    fn atom(&mut self) -> Result<()> {
+        let Some(token) = self.peek().cloned() else {
+            bail!("expression ends early");
+        };
+        self.next += 1;
+        match token {
+            Token::Number(n) => self.ops.push(Op::Number(n)),
+            Token::Symbol('(') => {
+                self.sum()?;
+                self.expect(')')?;
+            }
+            Token::Name(name) if self.eat('(') => {
+                let mut count = 0;
+                if !self.eat(')') {
+                    loop {
+                        self.sum()?;
+                        count += 1;
+                        if !self.eat(',') {
+                            break;
+                        }
+                    }
+                    self.expect(')')?;
+                }
+                let op = function(&name, count)
+                    .ok_or_else(|| anyhow!("unknown function {} of {} arguments", name, count))?;
+                self.ops.push(op);
+            }
+            Token::Name(name) => {
+                let op = match self.variables.iter().position(|&v| v == name) {
+                    Some(i) => Op::Variable(i),
+                    None => match name.as_str() {
+                        "pi" => Op::Number(PI),
+                        "e" => Op::Number(std::f32::consts::E),
+                        _ => bail!("unknown variable {}", name),
+                    },
+                };
+                self.ops.push(op);
+            }
+            token => bail!("unexpected {}", token),
+        }
+        Ok(())
+    }
+}
+
+fn function(name: &str, arguments: usize) -> Option<Op> {
+    let op = match (name, arguments) {
+        ("sin", 1) => Op::Call1(f32::sin),
+        ("cos", 1) => Op::Call1(f32::cos),
+        ("tan", 1) => Op::Call1(f32::tan),
+        ("asin", 1) => Op::Call1(f32::asin),
+        ("acos", 1) => Op::Call1(f32::acos),
+        ("atan", 1) => Op::Call1(f32::atan),
+        ("sinh", 1) => Op::Call1(f32::sinh),
+        ("cosh", 1) => Op::Call1(f32::cosh),
+        ("tanh", 1) => Op::Call1(f32::tanh),
+        ("exp", 1) => Op::Call1(f32::exp),
+        ("ln", 1) | ("log", 1) => Op::Call1(f32::ln),
+        ("log10", 1) => Op::Call1(f32::log10),
+        ("sqrt", 1) => Op::Call1(f32::sqrt),
+        ("abs", 1) => Op::Call1(f32::abs),
+        ("floor", 1) => Op::Call1(f32::floor),
+        ("ceil", 1) => Op::Call1(f32::ceil),
+        ("round", 1) => Op::Call1(f32::round),
+        ("sign", 1) => Op::Call1(f32::signum),
+        ("atan2", 2) => Op::Call2(f32::atan2),
+        ("pow", 2) => Op::Call2(f32::powf),
+        ("min", 2) => Op::Call2(f32::min),
+        ("max", 2) => Op::Call2(f32::max),
+        ("mod", 2) => Op::Call2(f32::rem_euclid),
+        _ => return None,
+    };
+    Some(op)
+}
+
+// a shape read from a surface file
+pub enum Definition {
+    Parametric(ParametricSurface),
+    Height(HeightFunction),
+}
+
+impl Definition {
+    // a surface file: `key = value` lines, with // comments. Parametric
+    // surfaces set x, y and z as expressions of u, v and p0 to p4, height
+    // functions set height as an expression of x, z and p0 to p4:
+    //
+    //   x = (p0 + p1 * cos(v)) * cos(u)    height = sin(x) * cos(z)
+    //   y = p1 * sin(v)                    xrange = -pi pi
+    //   z = (p0 + p1 * cos(v)) * sin(u)    zrange = -pi pi
+    //   urange = 0 2*pi                    aspect = 0.5
+    //   vrange = 0 2*pi
+    //   params = 1 0.3
+    //
+    // surfaces can also set segments, scale and direction, and both can set
+    // aspect and colormap. Height functions can be measured data instead,
+    // with `data = <file>` naming a heightfield::HeightField file
+    pub fn parse(name: &str, text: &str) -> Result<Self> {
+        Self::parse_in(name, text, Path::new(""))
+    }
+
+    // data files are found relative to dir
+    fn parse_in(name: &str, text: &str, dir: &Path) -> Result<Self> {
+        let mut values = HashMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow!("{} line {}: can't read {:?}", name, number + 1, line))?;
+            values.insert(key.trim(), value.trim());
+        }
+        let numbers = |key: &str| -> Result<Option<Vec<f32>>> {
+            let Some(value) = values.get(key) else {
+                return Ok(None);
+            };
+            let numbers = value
+                .split_whitespace()
+                .map(constant)
+                .collect::<Result<_>>()
+                .with_context(|| format!("invalid {} in {}", key, name))?;
+            Ok(Some(numbers))
+        };
+        let number = |key: &str| -> Result<Option<f32>> {
+            numbers(key)?
+                .map(|n| match n[..] {
+                    [n] => Ok(n),
+                    _ => Err(anyhow!("{} in {} needs one number", key, name)),
+                })
+                .transpose()
+        };
+        let pair = |key: &str| -> Result<Option<[f32; 2]>> {
+            numbers(key)?
+                .map(|n| {
+                    n.try_into()
+                        .map_err(|_| anyhow!("{} in {} needs two numbers", key, name))
+                })
+                .transpose()
+        };
+        let expression = |key: &str, variables: &[&str]| -> Result<Expr> {
+            let value = values
+                .get(key)
+                .ok_or_else(|| anyhow!("missing {} in {}", key, name))?;
+            Expr::parse(value, variables).with_context(|| format!("invalid {} in {}", key, name))
+        };
+
+        let mut params = [0.0; 5];
+        if let Some(given) = numbers("params")? {
+            if given.len() > params.len() {
+                bail!("{} has more than {} params", name, params.len());
+            }
+            params[..given.len()].copy_from_slice(&given);
+        }
+        let colormap = match values.get("colormap") {
+            Some(setting) => Colormap::from_setting(setting)
+                .with_context(|| format!("invalid colormap in {}", name))?,
+            None => Colormap::default(),
+        };
+
+        if let Some(data) = values.get("data") {
+            let mut field = HeightField::load(&dir.join(data))?;
+            if let Some([xmin, xmax]) = pair("xrange")? {
+                (field.xmin, field.xmax) = (xmin, xmax);
+            }
+            if let Some([zmin, zmax]) = pair("zrange")? {
+                (field.zmin, field.zmax) = (zmin, zmax);
+            }
+            let aspect = number("aspect")?.unwrap_or(1.0);
+            return Ok(Definition::Height(field.function(aspect, colormap)));
+        }
+        if values.contains_key("height") {
+            let height = expression("height", &HEIGHT_VARIABLES)?;
+            let x = Expr::parse("x", &HEIGHT_VARIABLES)?;
+            let z = Expr::parse("z", &HEIGHT_VARIABLES)?;
+            let [xmin, xmax] = pair("xrange")?.unwrap_or([-1.0, 1.0]);
+            let [zmin, zmax] = pair("zrange")?.unwrap_or([-1.0, 1.0]);
+            return Ok(Definition::Height(HeightFunction {
+                f: SurfaceFunction::Expressions(Arc::new([x, height, z])),
+                xmin,
+                xmax,
+                zmin,
+                zmax,
+                aspect: number("aspect")?.unwrap_or(1.0),
+                colormap,
+                params,
+            }));
+        }
+
+        let [x, y, z] = ["x", "y", "z"].map(|key| expression(key, &SURFACE_VARIABLES));
+        let defaults = ParametricSurface::default();
+        let [umin, umax] = pair("urange")?.unwrap_or([defaults.umin, defaults.umax]);
+        let [vmin, vmax] = pair("vrange")?.unwrap_or([defaults.vmin, defaults.vmax]);
+        let [u_segments, v_segments] = match pair("segments")? {
+            Some(segments) => segments.map(|n| n.max(1.0) as usize),
+            None => [defaults.u_segments, defaults.v_segments],
+        };
+        Ok(Definition::Parametric(ParametricSurface {
+            f: SurfaceFunction::Expressions(Arc::new([x?, y?, z?])),
+            normal: None,
+            umin,
+            umax,
+            vmin,
+            vmax,
+            u_segments,
+            v_segments,
+            scale: number("scale")?.unwrap_or(1.0),
+            aspect: number("aspect")?.unwrap_or(1.0),
+            colormap,
+            colormap_direction: match values.get("direction").copied() {
+                Some("x") => "x",
+                Some("z") => "z",
+                _ => "y",
+            },
+            params,
+            ..defaults
+        }))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        Self::parse_in(&path.display().to_string(), &text, dir)
+    }
+
+    // a parametric surface to draw, height functions drawn as the surface
+    // y = height(x, z) over their rectangle
+    pub fn surface(self) -> ParametricSurface {
+        match self {
+            Definition::Parametric(surface) => surface,
+            Definition::Height(function) => {
+                // data is drawn with a cell per sample, up to a limit
+                let [u_segments, v_segments] = match &function.f {
+                    SurfaceFunction::Grid(field, _) => {
+                        [field.width, field.length].map(|n| (n - 1).min(MAX_DATA_SEGMENTS))
+                    }
+                    _ => [64, 64],
+                };
+                ParametricSurface {
+                    f: function.f,
+                    normal: None,
+                    umin: function.xmin,
+                    umax: function.xmax,
+                    vmin: function.zmin,
+                    vmax: function.zmax,
+                    u_segments,
+                    v_segments,
+                    scale: 1.0,
+                    aspect: function.aspect,
+                    colormap: function.colormap,
+                    params: function.params,
+                    ..Default::default()
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str, x: f32) -> f32 {
+        Expr::parse(source, &["x"]).unwrap().eval(&[x])
+    }
+
+    fn error(source: &str) -> String {
+        Expr::parse(source, &["x"]).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("-x^2", 3.0), -9.0);
+        assert_eq!(eval("(-x)^2", 3.0), 9.0);
+        assert_eq!(eval("2^3^2", 0.0), 512.0);
+        assert_eq!(eval("2^-1", 0.0), 0.5);
+        assert_eq!(eval("10-x-2", 3.0), 5.0);
+        assert_eq!(eval("12/x/2", 3.0), 2.0);
+        assert_eq!(eval("1+2*x", 3.0), 7.0);
+        assert_eq!(eval("--x", 3.0), 3.0);
+    }
+
+    #[test]
+    fn numbers_and_functions() {
+        assert_eq!(eval("1e-3", 0.0), 0.001);
+        assert_eq!(eval("2.5E2", 0.0), 250.0);
+        assert_eq!(eval("x*1e3", 2.0), 2000.0);
+        assert_eq!(eval("atan2(1, 0)", 0.0), PI / 2.0);
+        assert_eq!(eval("atan2(0, -1)", 0.0), PI);
+        // mod is always positive, like rem_euclid
+        assert_eq!(eval("mod(x, 3)", 7.0), 1.0);
+        assert_eq!(eval("mod(x, 3)", -1.0), 2.0);
+        assert_eq!(eval("max(x, pi)", 3.0), PI);
+        assert_eq!(constant("2*pi").unwrap(), 2.0 * PI);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(error("y + 1").contains("unknown variable y"));
+        assert!(error("foo(x)").contains("unknown function foo of 1 arguments"));
+        assert!(error("sin(x, 2)").contains("unknown function sin of 2 arguments"));
+        assert!(error("atan2(x)").contains("unknown function atan2 of 1 arguments"));
+        assert!(error("x 2").contains("unexpected"));
+        assert!(error("(x))").contains("unexpected"));
+        assert!(error("x +").contains("ends early"));
+        assert!(error("(x").contains("expected ')'"));
+        assert!(error("x # 2").contains("unexpected"));
+    }
+
+    #[test]
+    fn stack_depth() {
+        // every ( holds one more value on the stack before the innermost is added
+        let nested =
+            |depth: usize| format!("{}x{}", "1+(".repeat(depth - 1), ")".repeat(depth - 1));
+        assert_eq!(eval(&nested(MAX_STACK), 1.0), MAX_STACK as f32);
+        assert!(error(&nested(MAX_STACK + 1)).contains("nested too deeply"));
+    }
+
+    #[test]
+    fn parametric_definition() {
+        let text = "
+            // a torus
+            x = (p0 + p1 * cos(v)) * cos(u)
+            y = p1 * sin(v)
+            z = (p0 + p1 * cos(v)) * sin(u)
+            urange = 0 2*pi
+            vrange = 0 pi
+            segments = 10 20
+            params = 1 0.5
+        ";
+        let Definition::Parametric(surface) = Definition::parse("torus", text).unwrap() else {
+            panic!("not parametric");
+        };
+        assert_eq!([surface.umin, surface.umax], [0.0, 2.0 * PI]);
+        assert_eq!([surface.vmin, surface.vmax], [0.0, PI]);
+        assert_eq!([surface.u_segments, surface.v_segments], [10, 20]);
+        assert_eq!(surface.params, [1.0, 0.5, 0.0, 0.0, 0.0]);
+        let [x, y, z] = surface.f.eval(0.0, 0.0, surface.params);
+        assert_eq!([x, y, z], [1.5, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn height_definition() {
+        let text = "height = p0 * x * z\nxrange = -2 2\nzrange = 0 1\naspect = 0.5\nparams = 3";
+        let Definition::Height(function) = Definition::parse("saddle", text).unwrap() else {
+            panic!("not a height function");
+        };
+        assert_eq!([function.xmin, function.xmax], [-2.0, 2.0]);
+        assert_eq!([function.zmin, function.zmax], [0.0, 1.0]);
+        assert_eq!(function.aspect, 0.5);
+        assert_eq!(function.f.eval(2.0, 0.5, function.params), [2.0, 3.0, 0.5]);
+    }
+
+    #[test]
+    fn bad_definitions() {
+        for text in [
+            "x = u\ny = v",
+            "height = u",
+            "height = x\nxrange = 1",
+            "height = x\nparams = 1 2 3 4 5 6",
+            "just some text",
+        ] {
+            assert!(Definition::parse("bad", text).is_err(), "{:?}", text);
+        }
+    }
+}
@@ -0,0 +1,267 @@
+#![allow(dead_code)]
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    block::Block,
+    terrain::TerrainGenerator,
+    world::{Chunk, ChunkPos, World, CHUNK_SIZE, WORLD_HEIGHT},
+};
+
+// flat worlds without their own layers get these
+const DEFAULT_LAYERS: &str = "bedrock,2*dirt,grass";
+
+// fills in the chunks of a world. The same setting and seed always give the
+// same chunks, whatever order they're generated in
+pub trait WorldGenerator {
+    // generates a chunk into the world. Features such as trees may reach into
+    // neighbouring chunks, chunks that are all air may be left out
+    fn generate(&self, world: &mut World, pos: ChunkPos);
+
+    // the setting this generator was made from, saved with the world so it
+    // can be made again
+    fn setting(&self) -> String;
+
+    // where players start, None to start them above the ground in the middle
+    fn spawn(&self) -> Option<[f32; 3]> {
+        None
+    }
+
+    // the ground as a density field over a box of blocks, negative inside it,
+    // see TerrainGenerator::density. None where the blocks are all there is
+    fn density(&self, _origin: [i32; 3], _size: [usize; 3]) -> Option<Vec<f32>> {
+        None
+    }
+}
+
+// a generator from its setting: "noise", "flat" or "flat:<layers>", "void" or "cube"
+pub fn from_setting(setting: &str, seed: u64) -> Result<Box<dyn WorldGenerator>> {
+    let (name, options) = match setting.split_once(':') {
+        Some((name, options)) => (name, Some(options)),
+        None => (setting, None),
+    };
+    let generator: Box<dyn WorldGenerator> = match (name, options) {
+        ("noise", None) => Box::new(TerrainGenerator::new(seed)),
+        ("flat", layers) => Box::new(FlatGenerator::parse(layers.unwrap_or(DEFAULT_LAYERS))?),
+        ("void", None) => Box::new(VoidGenerator),
+        ("cube", None) => Box::new(TestCube),
+        _ => bail!("unknown generator {}", setting),
+    };
+    Ok(generator)
+}
+
+impl WorldGenerator for TerrainGenerator {
+    fn generate(&self, world: &mut World, pos: ChunkPos) {
+        TerrainGenerator::generate(self, world, pos)
+    }
+
+    fn setting(&self) -> String {
+        "noise".to_string()
+    }
+
+    fn density(&self, origin: [i32; 3], size: [usize; 3]) -> Option<Vec<f32>> {
+        Some(TerrainGenerator::density(self, origin, size))
+    }
+}
+
+// the same layers of blocks everywhere
+pub struct FlatGenerator {
+    // from the bottom up
+    pub layers: Vec<Block>,
+}
+
+impl FlatGenerator {
+    // comma separated blocks from the bottom up, each optionally repeated
+    // like "3*stone"
+    pub fn parse(layers: &str) -> Result<Self> {
+        let mut blocks = Vec::new();
+        for layer in layers.split(',') {
+            let (count, name) = match layer.trim().split_once('*') {
+                Some((count, name)) => (
+                    count
+                        .trim()
+                        .parse()
+                        .map_err(|_| anyhow!("invalid layer count in {}", layer))?,
+                    name.trim(),
+                ),
+                None => (1, layer.trim()),
+            };
+            let block = Block::from_name(name).ok_or_else(|| anyhow!("unknown block {}", name))?;
+            // checked before adding them, so a huge count fails instead of allocating
+            let total = blocks.len().saturating_add(count);
+            if total > WORLD_HEIGHT {
+                bail!("{} layers don't fit in the world", total);
+            }
+            blocks.resize(total, block);
+        }
+        Ok(Self { layers: blocks })
+    }
+}
+
+impl WorldGenerator for FlatGenerator {
+    fn generate(&self, world: &mut World, pos: ChunkPos) {
+        let mut chunk = Chunk::new();
+        for (y, &block) in self.layers.iter().enumerate() {
+            for z in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    chunk.set(x, y, z, block);
+                }
+            }
+        }
+        chunk.compact();
+        world.insert_chunk(pos, chunk);
+    }
+
+    fn setting(&self) -> String {
+        let mut layers: Vec<String> = Vec::new();
+        let mut i = 0;
+        while i < self.layers.len() {
+            let block = self.layers[i];
+            let count = self.layers[i..].iter().take_while(|&&b| b == block).count();
+            layers.push(match count {
+                1 => block.name().to_string(),
+                _ => format!("{}*{}", count, block.name()),
+            });
+            i += count;
+        }
+        format!("flat:{}", layers.join(","))
+    }
+}
+
+// nothing at all, for building from scratch
+pub struct VoidGenerator;
+
+impl WorldGenerator for VoidGenerator {
+    fn generate(&self, _world: &mut World, _pos: ChunkPos) {}
+
+    fn setting(&self) -> String {
+        "void".to_string()
+    }
+
+    fn spawn(&self) -> Option<[f32; 3]> {
+        Some([0.0, WORLD_HEIGHT as f32 / 2.0, 0.0])
+    }
+}
+
+// the 16x16x16 grass cube with a pool and a few see-through blocks on top,
+// in chunk 0, 0
+pub struct TestCube;
+
+impl WorldGenerator for TestCube {
+    fn generate(&self, world: &mut World, pos: ChunkPos) {
+        if pos != [0, 0] {
+            return;
+        }
+        let mut chunk = Chunk::new();
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    chunk.set(x, y, z, Block::Grass);
+                }
+            }
+        }
+        for x in 3..9 {
+            for y in 14..16 {
+                for z in 3..9 {
+                    chunk.set(x, y, z, Block::Water);
+                }
+            }
+        }
+        chunk.set(12, 15, 3, Block::Glass);
+        chunk.set(12, 15, 5, Block::StainedGlass);
+        chunk.set(12, 15, 7, Block::Ice);
+        for x in 11..14 {
+            for z in 10..13 {
+                chunk.set(x, 15, z, Block::Leaves);
+            }
+        }
+        world.insert_chunk(pos, chunk);
+    }
+
+    fn setting(&self) -> String {
+        "cube".to_string()
+    }
+
+    fn spawn(&self) -> Option<[f32; 3]> {
+        Some([0.0, 5.0, 10.0])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_round_trip() {
+        for setting in [
+            "noise",
+            "void",
+            "cube",
+            "flat:bedrock,2*dirt,grass",
+            "flat:stone",
+            "flat:3*stone,dirt,3*stone",
+        ] {
+            assert_eq!(from_setting(setting, 1).unwrap().setting(), setting);
+        }
+        // spaces and the default layers come back in the canonical form
+        assert_eq!(
+            from_setting("flat", 1).unwrap().setting(),
+            format!("flat:{}", DEFAULT_LAYERS)
+        );
+        assert_eq!(
+            from_setting("flat: 2 * stone , grass", 1)
+                .unwrap()
+                .setting(),
+            "flat:2*stone,grass"
+        );
+    }
+
+    #[test]
+    fn bad_settings_are_rejected() {
+        for setting in [
+            "mountains",
+            "noise:flat",
+            "void:1",
+            "flat:",
+            "flat:cheese",
+            "flat:x*stone",
+            "flat:300*stone",
+            "flat:18446744073709551615*stone,stone",
+        ] {
+            assert!(from_setting(setting, 1).is_err(), "{}", setting);
+        }
+    }
+
+    #[test]
+    fn flat_layers() {
+        let generator = from_setting("flat:bedrock,2*dirt,grass", 1).unwrap();
+        let mut world = World::new(1);
+        generator.generate(&mut world, [-3, 2]);
+        let chunk = world.chunk([-3, 2]).unwrap();
+        for (x, z) in [(0, 0), (5, 11), (15, 15)] {
+            let column: Vec<Block> = (0..6).map(|y| chunk.get(x, y, z)).collect();
+            assert_eq!(
+                column,
+                [
+                    Block::Bedrock,
+                    Block::Dirt,
+                    Block::Dirt,
+                    Block::Grass,
+                    Block::Air,
+                    Block::Air
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn void_is_empty() {
+        let generator = from_setting("void", 1).unwrap();
+        let mut world = World::new(1);
+        for pos in [[0, 0], [4, -7]] {
+            generator.generate(&mut world, pos);
+        }
+        assert_eq!(world.chunks().count(), 0);
+        assert_eq!(world.get_block(0, 0, 0), Block::Air);
+        assert!(generator.spawn().is_some());
+    }
+}
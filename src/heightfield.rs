@@ -0,0 +1,315 @@
+#![allow(dead_code)]
+use anyhow::{anyhow, bail, Context, Result};
+use std::{path::Path, sync::Arc};
+
+use crate::{
+    axes::PlotBox,
+    sd::{self, colormap::Colormap, SurfaceFunction},
+    voxelize::HeightFunction,
+};
+
+// the first bytes of a binary float grid file
+const GRID_MAGIC: &[u8; 4] = b"HGRD";
+// extensions that --surface and --voxelize read as height fields rather than
+// surface files
+const DATA_EXTENSIONS: [&str; 6] = ["csv", "grid", "png", "pgm", "tif", "tiff"];
+
+pub fn is_height_data(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| DATA_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+// measured heights on a regular grid, such as a CSV matrix, a heightmap image
+// or a binary float grid. Row k holds the heights along x at the k-th z
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeightField {
+    pub width: usize,
+    pub length: usize,
+    pub heights: Vec<f32>,
+    // where the grid lies, by default the longer side spans -1 to 1
+    pub xmin: f32,
+    pub xmax: f32,
+    pub zmin: f32,
+    pub zmax: f32,
+}
+
+impl HeightField {
+    pub fn new(width: usize, length: usize, heights: Vec<f32>) -> Result<Self> {
+        if width < 2 || length < 2 {
+            bail!(
+                "a height field needs at least 2 x 2 samples, not {} x {}",
+                width,
+                length
+            );
+        }
+        if heights.len() != width * length {
+            bail!(
+                "{} heights don't make a {} x {} grid",
+                heights.len(),
+                width,
+                length
+            );
+        }
+        if let Some(i) = heights.iter().position(|h| !h.is_finite()) {
+            bail!(
+                "height {} at column {}, row {} isn't a number",
+                heights[i],
+                i % width,
+                i / width
+            );
+        }
+        let longest = (width.max(length) - 1) as f32;
+        let (x, z) = ((width - 1) as f32 / longest, (length - 1) as f32 / longest);
+        Ok(Self {
+            width,
+            length,
+            heights,
+            xmin: -x,
+            xmax: x,
+            zmin: -z,
+            zmax: z,
+        })
+    }
+
+    // picks the format from the extension: .csv and .txt are matrices, .grid
+    // is a binary float grid and anything else is read as an image
+    pub fn load(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        let name = path.display().to_string();
+        let field = match extension.as_deref() {
+            Some("csv") | Some("txt") => Self::parse_csv(
+                &name,
+                &std::fs::read_to_string(path)
+                    .with_context(|| format!("failed to read {}", name))?,
+            ),
+            Some("grid") => Self::parse_grid(
+                &name,
+                &std::fs::read(path).with_context(|| format!("failed to read {}", name))?,
+            ),
+            _ => Self::from_image(
+                &image::open(path).with_context(|| format!("failed to read {}", name))?,
+            ),
+        };
+        field.with_context(|| format!("invalid height field {}", name))
+    }
+
+    // one row of heights per line, separated by commas or whitespace. Lines
+    // starting with # are skipped
+    pub fn parse_csv(name: &str, text: &str) -> Result<Self> {
+        let mut heights = Vec::new();
+        let mut width = 0;
+        let mut length = 0;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let row = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|v| !v.is_empty())
+                .map(str::parse)
+                .collect::<Result<Vec<f32>, _>>()
+                .map_err(|_| anyhow!("{} line {}: can't read {:?}", name, number + 1, line))?;
+            if length == 0 {
+                width = row.len();
+            } else if row.len() != width {
+                bail!(
+                    "{} line {} has {} heights, not {}",
+                    name,
+                    number + 1,
+                    row.len(),
+                    width
+                );
+            }
+            heights.extend(row);
+            length += 1;
+        }
+        Self::new(width, length, heights)
+    }
+
+    // the brightness of each pixel from 0 to 1, keeping 16 bits for images
+    // such as 16 bit PNG and PGM heightmaps
+    pub fn from_image(image: &image::DynamicImage) -> Result<Self> {
+        let luma = image.to_luma16();
+        let heights = luma
+            .pixels()
+            .map(|p| p[0] as f32 / u16::MAX as f32)
+            .collect();
+        Self::new(luma.width() as usize, luma.height() as usize, heights)
+    }
+
+    // "HGRD", the width and length as little endian u32s and then
+    // width * length little endian f32 heights, row by row
+    pub fn parse_grid(name: &str, bytes: &[u8]) -> Result<Self> {
+        let (magic, rest) = bytes.split_at(bytes.len().min(4));
+        if magic != GRID_MAGIC || rest.len() < 8 {
+            bail!("{} isn't a height grid", name);
+        }
+        let size = |i: usize| u32::from_le_bytes(rest[i..i + 4].try_into().unwrap()) as usize;
+        let (width, length) = (size(0), size(4));
+        let data = &rest[8..];
+        if Some(data.len()) != width.checked_mul(length).and_then(|n| n.checked_mul(4)) {
+            bail!("{} should hold {} x {} heights", name, width, length);
+        }
+        let heights = data
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        Self::new(width, length, heights)
+    }
+
+    pub fn to_grid(&self) -> Vec<u8> {
+        let mut bytes = GRID_MAGIC.to_vec();
+        bytes.extend((self.width as u32).to_le_bytes());
+        bytes.extend((self.length as u32).to_le_bytes());
+        for height in &self.heights {
+            bytes.extend(height.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn range(&self) -> (f32, f32) {
+        self.heights
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(min, max), &h| {
+                (min.min(h), max.max(h))
+            })
+    }
+
+    // the height at a point between xmin..xmax and zmin..zmax, blended
+    // between the four samples around it and clamped to the edges
+    pub fn height(&self, x: f32, z: f32) -> f32 {
+        let at = |v: f32, min: f32, max: f32, count: usize| {
+            let t = ((v - min) / (max - min)).clamp(0.0, 1.0) * (count - 1) as f32;
+            let i = (t.floor() as usize).min(count - 2);
+            (i, t - i as f32)
+        };
+        let (i, fx) = at(x, self.xmin, self.xmax, self.width);
+        let (k, fz) = at(z, self.zmin, self.zmax, self.length);
+        let h = |i: usize, k: usize| self.heights[i + k * self.width];
+        let near = h(i, k) + (h(i + 1, k) - h(i, k)) * fx;
+        let far = h(i, k + 1) + (h(i + 1, k + 1) - h(i, k + 1)) * fx;
+        near + (far - near) * fz
+    }
+
+    // a height function over the field with its heights fitted into -1..1, so
+    // aspect sets how tall it stands against its width. Plot axes still show
+    // the heights as measured
+    pub fn function(&self, aspect: f32, colormap: Colormap) -> HeightFunction {
+        let (min, max) = self.range();
+        let mut field = self.clone();
+        for h in &mut field.heights {
+            *h = match max > min {
+                true => 2.0 * (*h - min) / (max - min) - 1.0,
+                false => 0.0,
+            };
+        }
+        HeightFunction {
+            f: SurfaceFunction::Grid(Arc::new(field), [min, max]),
+            xmin: self.xmin,
+            xmax: self.xmax,
+            zmin: self.zmin,
+            zmax: self.zmax,
+            aspect,
+            colormap,
+            params: [0.0; 5],
+        }
+    }
+
+    // the samples as points normalized like simple_surface_points, with the
+    // colormap range of the heights
+    pub fn points(&self, scale: f32, aspect: f32) -> (Vec<Vec<[f32; 3]>>, [f32; 2]) {
+        let step = |min: f32, max: f32, count: usize| (max - min) / (count - 1) as f32;
+        let (dx, dz) = (
+            step(self.xmin, self.xmax, self.width),
+            step(self.zmin, self.zmax, self.length),
+        );
+        let pts = (0..self.width)
+            .map(|i| {
+                (0..self.length)
+                    .map(|k| {
+                        let (x, z) = (self.xmin + i as f32 * dx, self.zmin + k as f32 * dz);
+                        [x, self.heights[i + k * self.width], z]
+                    })
+                    .collect()
+            })
+            .collect();
+        sd::normalize_surface_points(
+            pts,
+            [self.xmin, self.xmax],
+            [self.zmin, self.zmax],
+            scale,
+            aspect,
+        )
+    }
+
+    // the box points fits the samples into, for plot axes
+    pub fn plot_box(&self, scale: f32, aspect: f32) -> PlotBox {
+        let (min, max) = self.range();
+        sd::normalized_box(
+            [self.xmin, self.xmax],
+            [min, max],
+            [self.zmin, self.zmax],
+            scale,
+            aspect,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_round_trip() {
+        let field = HeightField::new(3, 2, vec![0.0, 1.5, -2.0, 4.0, 0.25, 1e6]).unwrap();
+        let bytes = field.to_grid();
+        assert_eq!(bytes.len(), 4 + 8 + 6 * 4);
+        assert_eq!(HeightField::parse_grid("field", &bytes).unwrap(), field);
+        // cut short, or with a size that overflows
+        assert!(HeightField::parse_grid("short", &bytes[..bytes.len() - 1]).is_err());
+        let mut huge = bytes.clone();
+        huge[4..12].copy_from_slice(&[0xff; 8]);
+        assert!(HeightField::parse_grid("huge", &huge).is_err());
+        assert!(HeightField::parse_grid("empty", b"").is_err());
+    }
+
+    #[test]
+    fn csv() {
+        let field = HeightField::parse_csv("field", "# heights\n1, 2, 3\n\n4 5\t6\n").unwrap();
+        assert_eq!([field.width, field.length], [3, 2]);
+        assert_eq!(field.heights, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        // the longer side spans -1 to 1
+        assert_eq!(
+            [field.xmin, field.xmax, field.zmin, field.zmax],
+            [-1.0, 1.0, -0.5, 0.5]
+        );
+        for text in [
+            "1, 2, 3\n4, 5",
+            "1, 2\n3, nan",
+            "1, 2\n3, inf",
+            "1, 2\n3, x",
+            "1, 2",
+            "",
+        ] {
+            assert!(HeightField::parse_csv("bad", text).is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn height_interpolates() {
+        let field = HeightField::parse_csv("field", "0, 2\n4, 6").unwrap();
+        assert_eq!(field.height(-1.0, -1.0), 0.0);
+        assert_eq!(field.height(1.0, 1.0), 6.0);
+        assert_eq!(field.height(0.0, -1.0), 1.0);
+        assert_eq!(field.height(-1.0, 0.0), 2.0);
+        assert_eq!(field.height(0.0, 0.0), 3.0);
+        // clamped outside the grid
+        assert_eq!(field.height(-5.0, 9.0), 4.0);
+        assert_eq!(field.range(), (0.0, 6.0));
+    }
+}
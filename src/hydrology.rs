@@ -0,0 +1,214 @@
+#![allow(dead_code)]
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use rand::Rng;
+
+use crate::terrain::{chunk_rng, Column, SEA_LEVEL};
+
+// rivers follow the lines where this noise crosses zero. The valley reaches
+// out to this value on either side, the water itself is narrower
+const RIVER_VALLEY: f64 = 0.1;
+// how deep a river is below its surface
+const RIVER_DEPTH: i32 = 3;
+// each cell of this many blocks has at most one lake
+const LAKE_CELL: i32 = 96;
+const LAKE_CHANCE: f64 = 0.35;
+const LAKE_SALT: u64 = 0x6c61_6b65;
+// points checked around a lake's shore to find the lowest, which it fills up to
+const SHORE_SAMPLES: usize = 16;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Lake {
+    pub center: [i32; 2],
+    pub radius: f32,
+    pub depth: f32,
+    // the height of the water surface
+    pub level: i32,
+}
+
+impl Lake {
+    // the lake bed at a point, None outside the lake
+    pub fn bed(&self, x: i32, z: i32) -> Option<f32> {
+        let dx = (x - self.center[0]) as f32;
+        let dz = (z - self.center[1]) as f32;
+        let d = (dx * dx + dz * dz) / (self.radius * self.radius);
+        (d < 1.0).then_some(self.level as f32 + 1.0 - self.depth * (1.0 - d))
+    }
+}
+
+// rivers and lakes, applied to the land heights of the terrain generator
+pub struct Hydrology {
+    seed: u64,
+    rivers: Fbm<Perlin>,
+}
+
+impl Hydrology {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rivers: Fbm::<Perlin>::new((seed as u32).wrapping_add(20))
+                .set_frequency(1.0 / 400.0)
+                .set_octaves(2),
+        }
+    }
+
+    // 1 in the middle of a river valley falling to 0 at its edge
+    pub fn river(&self, x: i32, z: i32) -> f32 {
+        let n = self.rivers.get([x as f64, z as f64]).abs();
+        (1.0 - n / RIVER_VALLEY).max(0.0) as f32
+    }
+
+    // the water surface of a river through a valley whose floor is at this
+    // height. It follows the floor down and only reaches sea level at the coast
+    pub fn river_surface(valley_floor: f32) -> i32 {
+        ((valley_floor - 1.0).round() as i32).max(SEA_LEVEL)
+    }
+
+    // lowers the land into a river valley, smoothly so the banks slope. The
+    // valley floor is a smoothed height of the land around, never above it
+    pub fn carve_river(&self, x: i32, z: i32, height: f32, valley_floor: f32) -> f32 {
+        let t = self.river(x, z);
+        let t = t * t * (3.0 - 2.0 * t);
+        let bed = (Self::river_surface(valley_floor) - RIVER_DEPTH) as f32;
+        // only carve down, a valley never raises the sea floor
+        height.min(height + (bed - height) * t)
+    }
+
+    // fills a column in a river valley with the river's water
+    pub fn apply_river(&self, x: i32, z: i32, valley_floor: f32, column: &mut Column) {
+        if self.river(x, z) > 0.0 {
+            column.water_level = column.water_level.max(Self::river_surface(valley_floor));
+        }
+    }
+
+    // the lake of a cell, if it has one. Land height is asked for around the
+    // shore so the lake fills its basin up to the lowest point of the rim
+    fn lake(&self, cell: [i32; 2], land_height: &dyn Fn(i32, i32) -> f32) -> Option<Lake> {
+        let mut rng = chunk_rng(self.seed, cell, LAKE_SALT);
+        if !rng.gen_bool(LAKE_CHANCE) {
+            return None;
+        }
+        let radius = rng.gen_range(7.0..16.0f32);
+        let margin = radius.ceil() as i32;
+        let center = [
+            cell[0] * LAKE_CELL + rng.gen_range(margin..LAKE_CELL - margin),
+            cell[1] * LAKE_CELL + rng.gen_range(margin..LAKE_CELL - margin),
+        ];
+        let depth = rng.gen_range(3.0..6.0f32);
+        let shore = (0..SHORE_SAMPLES)
+            .map(|i| {
+                let angle = i as f32 / SHORE_SAMPLES as f32 * std::f32::consts::TAU;
+                land_height(
+                    center[0] + (angle.cos() * radius) as i32,
+                    center[1] + (angle.sin() * radius) as i32,
+                )
+            })
+            .fold(f32::MAX, f32::min);
+        let level = shore.floor() as i32 - 1;
+        // a lake at sea level would just be a pond next to the sea
+        (level > SEA_LEVEL + 1).then_some(Lake {
+            center,
+            radius,
+            depth,
+            level,
+        })
+    }
+
+    // every lake that could reach into the box between two corners
+    pub fn lakes_near(
+        &self,
+        min: [i32; 2],
+        max: [i32; 2],
+        land_height: &dyn Fn(i32, i32) -> f32,
+    ) -> Vec<Lake> {
+        let mut lakes = Vec::new();
+        for cz in min[1].div_euclid(LAKE_CELL)..=max[1].div_euclid(LAKE_CELL) {
+            for cx in min[0].div_euclid(LAKE_CELL)..=max[0].div_euclid(LAKE_CELL) {
+                lakes.extend(self.lake([cx, cz], land_height));
+            }
+        }
+        lakes.retain(|lake| {
+            let r = lake.radius.ceil() as i32;
+            lake.center[0] + r >= min[0]
+                && lake.center[0] - r <= max[0]
+                && lake.center[1] + r >= min[1]
+                && lake.center[1] - r <= max[1]
+        });
+        lakes
+    }
+
+    // digs out any of the lakes over a column and raises its water to theirs
+    pub fn apply_lakes(lakes: &[Lake], x: i32, z: i32, column: &mut Column) {
+        for lake in lakes {
+            if let Some(bed) = lake.bed(x, z) {
+                column.height = column.height.min(bed.round() as i32);
+                column.water_level = column.water_level.max(lake.level);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the point nearest the middle of a river in a patch of the map
+    fn river_middle(hydrology: &Hydrology) -> [i32; 2] {
+        (-200..200)
+            .flat_map(|z| (-200..200).map(move |x| [x, z]))
+            .max_by(|&a, &b| {
+                hydrology
+                    .river(a[0], a[1])
+                    .total_cmp(&hydrology.river(b[0], b[1]))
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn river_beds_fall_towards_the_sea() {
+        let hydrology = Hydrology::new(5);
+        let [x, z] = river_middle(&hydrology);
+        assert!(hydrology.river(x, z) > 0.95);
+        // a valley falling from the hills to below sea level, the land well above it
+        let floors: Vec<f32> = (0..200)
+            .map(|i| SEA_LEVEL as f32 + 40.0 - i as f32 * 0.25)
+            .collect();
+        let beds: Vec<f32> = floors
+            .iter()
+            .map(|&floor| hydrology.carve_river(x, z, floor + 20.0, floor))
+            .collect();
+        for (i, pair) in beds.windows(2).enumerate() {
+            assert!(
+                pair[1] <= pair[0],
+                "bed rises at {} from {} to {}",
+                i,
+                pair[0],
+                pair[1]
+            );
+        }
+        // inland the river runs above sea level, at the coast its bed is under it
+        assert!(beds[0] > SEA_LEVEL as f32 + 30.0);
+        let coast = (SEA_LEVEL - RIVER_DEPTH) as f32;
+        assert!((beds[beds.len() - 1] - coast).abs() < 0.5);
+
+        // the water stands on the bed
+        for (&floor, &bed) in floors.iter().zip(&beds) {
+            let mut column = Column {
+                height: bed.round() as i32,
+                water_level: SEA_LEVEL,
+            };
+            hydrology.apply_river(x, z, floor, &mut column);
+            assert!(column.is_underwater());
+            assert!(column.water_level - column.height <= RIVER_DEPTH + 1);
+            assert!(column.water_level >= SEA_LEVEL);
+        }
+    }
+
+    #[test]
+    fn rivers_only_carve_down() {
+        let hydrology = Hydrology::new(5);
+        let [x, z] = river_middle(&hydrology);
+        // a sea floor below the river bed stays where it is
+        let sea_floor = (SEA_LEVEL - 20) as f32;
+        assert_eq!(hydrology.carve_river(x, z, sea_floor, sea_floor), sea_floor);
+    }
+}
@@ -0,0 +1,282 @@
+#![allow(dead_code)]
+use cgmath::{InnerSpace, Vector3};
+use std::f32::consts::PI;
+
+use crate::{
+    axes::PlotBox,
+    common::{Mesh, Vertex},
+    math_func,
+    sd::colormap::Colormap,
+};
+
+// the corners of a cell as offsets, bit i of a corner index is its offset on axis i
+const CORNERS: [[usize; 3]; 8] = [
+    [0, 0, 0],
+    [1, 0, 0],
+    [0, 1, 0],
+    [1, 1, 0],
+    [0, 0, 1],
+    [1, 0, 1],
+    [0, 1, 1],
+    [1, 1, 1],
+];
+// the 12 edges of a cell as pairs of corner indices
+const EDGES: [[usize; 2]; 12] = [
+    [0, 1],
+    [2, 3],
+    [4, 5],
+    [6, 7],
+    [0, 2],
+    [1, 3],
+    [4, 6],
+    [5, 7],
+    [0, 4],
+    [1, 5],
+    [2, 6],
+    [3, 7],
+];
+
+// meshes the surface where a grid of samples crosses zero, negative samples
+// being inside. values holds size[0] * size[1] * size[2] samples with x
+// varying fastest. Every cell the surface passes through gets one vertex at
+// the mean of the points where its edges cross zero, and every grid edge that
+// crosses zero becomes a quad joining the four cells around it. Positions are
+// in grid units and triangles wind counter-clockwise seen from outside
+pub fn surface_nets(size: [usize; 3], values: &[f32]) -> (Vec<[f32; 3]>, Vec<u32>) {
+    surface_nets_from(size, values, [0; 3])
+}
+
+// surface_nets for one of several grids that overlap their neighbours by a
+// sample on the low side. Quads for edges starting before `start` are left to
+// the neighbour, so the shared border is meshed once
+pub fn surface_nets_from(
+    size: [usize; 3],
+    values: &[f32],
+    start: [usize; 3],
+) -> (Vec<[f32; 3]>, Vec<u32>) {
+    let [sx, sy, sz] = size;
+    let value = |[x, y, z]: [usize; 3]| values[x + sx * (y + sy * z)];
+    let cells = [
+        sx.saturating_sub(1),
+        sy.saturating_sub(1),
+        sz.saturating_sub(1),
+    ];
+    let cell_index = |[x, y, z]: [usize; 3]| x + cells[0] * (y + cells[1] * z);
+
+    let mut positions = Vec::new();
+    let mut cell_vertices = vec![u32::MAX; cells[0] * cells[1] * cells[2]];
+    for z in 0..cells[2] {
+        for y in 0..cells[1] {
+            for x in 0..cells[0] {
+                let corner = |i: usize| {
+                    let [dx, dy, dz] = CORNERS[i];
+                    [x + dx, y + dy, z + dz]
+                };
+                let samples: [f32; 8] = std::array::from_fn(|i| value(corner(i)));
+                let mut sum = [0.0; 3];
+                let mut crossings = 0;
+                for [a, b] in EDGES {
+                    let (va, vb) = (samples[a], samples[b]);
+                    if (va < 0.0) == (vb < 0.0) {
+                        continue;
+                    }
+                    let t = va / (va - vb);
+                    for (s, (pa, pb)) in sum.iter_mut().zip(corner(a).into_iter().zip(corner(b))) {
+                        *s += pa as f32 + t * (pb as f32 - pa as f32);
+                    }
+                    crossings += 1;
+                }
+                if crossings > 0 {
+                    cell_vertices[cell_index([x, y, z])] = positions.len() as u32;
+                    positions.push(sum.map(|s| s / crossings as f32));
+                }
+            }
+        }
+    }
+
+    let mut indices = Vec::new();
+    for z in 0..sz {
+        for y in 0..sy {
+            for x in 0..sx {
+                let p = [x, y, z];
+                for axis in 0..3 {
+                    // the other two axes, in the order that makes the quad
+                    // wind counter-clockwise around this one
+                    let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+                    if p[axis] < start[axis] || p[axis] + 1 >= size[axis] || p[a] == 0 || p[b] == 0
+                    {
+                        continue;
+                    }
+                    if p[a] >= cells[a] || p[b] >= cells[b] {
+                        continue;
+                    }
+                    let mut q = p;
+                    q[axis] += 1;
+                    let (inside, next) = (value(p) < 0.0, value(q) < 0.0);
+                    if inside == next {
+                        continue;
+                    }
+                    let cell = |da: usize, db: usize| {
+                        let mut c = p;
+                        c[a] -= 1 - da;
+                        c[b] -= 1 - db;
+                        cell_vertices[cell_index(c)]
+                    };
+                    let quad = [cell(0, 0), cell(1, 0), cell(1, 1), cell(0, 1)];
+                    let quad = match inside {
+                        true => quad,
+                        false => [quad[0], quad[3], quad[2], quad[1]],
+                    };
+                    indices.extend([quad[0], quad[1], quad[2], quad[2], quad[3], quad[0]]);
+                }
+            }
+        }
+    }
+    (positions, indices)
+}
+
+// the direction the samples grow fastest at a point in grid units: central
+// differences at the corners of its cell, one sided on the border of the
+// grid, blended like trilinear interpolation
+pub fn gradient_at(size: [usize; 3], values: &[f32], p: [f32; 3]) -> [f32; 3] {
+    let value = |[x, y, z]: [usize; 3]| values[x + size[0] * (y + size[1] * z)];
+    let cell: [usize; 3] =
+        std::array::from_fn(|axis| (p[axis].max(0.0) as usize).min(size[axis].saturating_sub(2)));
+    let mut gradient = [0.0; 3];
+    for offset in CORNERS {
+        let corner: [usize; 3] = std::array::from_fn(|axis| cell[axis] + offset[axis]);
+        let weight: f32 = (0..3)
+            .map(|axis| {
+                let t = (p[axis] - cell[axis] as f32).clamp(0.0, 1.0);
+                match offset[axis] {
+                    1 => t,
+                    _ => 1.0 - t,
+                }
+            })
+            .product();
+        for (axis, g) in gradient.iter_mut().enumerate() {
+            let (mut low, mut high) = (corner, corner);
+            low[axis] = low[axis].saturating_sub(1);
+            high[axis] = (high[axis] + 1).min(size[axis] - 1);
+            let span = (high[axis] - low[axis]).max(1) as f32;
+            *g += weight * (value(high) - value(low)) / span;
+        }
+    }
+    gradient
+}
+
+// a surface f(x, y, z, params) = 0 inside the box from min to max, with f
+// negative inside the shape. Drawn like a ParametricSurface
+pub struct ImplicitSurface {
+    pub f: fn(f32, f32, f32, [f32; 5]) -> f32,
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    // cells along each axis of the box
+    pub segments: [usize; 3],
+    pub scale: f32,
+    pub use_colormap: bool,
+    pub colormap: Colormap,
+    pub colormap_direction: &'static str,
+    pub color: [f32; 3],
+    pub params: [f32; 5],
+}
+
+impl Default for ImplicitSurface {
+    fn default() -> Self {
+        ImplicitSurface {
+            f: math_func::gyroid,
+            min: [-PI; 3],
+            max: [PI; 3],
+            segments: [48; 3],
+            scale: 0.5,
+            use_colormap: true,
+            colormap: Colormap::default(),
+            colormap_direction: "y",
+            color: [1.0, 0.0, 0.0],
+            params: [0.3, 0.0, 0.0, 0.0, 0.0],
+        }
+    }
+}
+
+impl ImplicitSurface {
+    // samples f on the grid and meshes it with surface nets. Normals point
+    // along the gradient of f, uvs are x and z scaled to [0, 1] over the box
+    pub fn build(&self) -> Mesh {
+        let size = self.segments.map(|n| n.max(1) + 1);
+        let step: [f32; 3] =
+            std::array::from_fn(|axis| (self.max[axis] - self.min[axis]) / (size[axis] - 1) as f32);
+        let mut values = Vec::with_capacity(size[0] * size[1] * size[2]);
+        for z in 0..size[2] {
+            for y in 0..size[1] {
+                for x in 0..size[0] {
+                    let p = [x, y, z].map(|i| i as f32);
+                    let q: [f32; 3] =
+                        std::array::from_fn(|axis| self.min[axis] + p[axis] * step[axis]);
+                    values.push((self.f)(q[0], q[1], q[2], self.params));
+                }
+            }
+        }
+        let (positions, indices) = surface_nets(size, &values);
+
+        let points: Vec<[f32; 3]> = positions
+            .iter()
+            .map(|p| std::array::from_fn(|axis| self.min[axis] + p[axis] * step[axis]))
+            .collect();
+        let axis = match self.colormap_direction {
+            "x" => 0,
+            "z" => 2,
+            _ => 1,
+        };
+        let (min, max) = points.iter().fold((f32::MAX, f32::MIN), |(min, max), p| {
+            (min.min(p[axis]), max.max(p[axis]))
+        });
+        let vertices = points
+            .iter()
+            .map(|&q| {
+                let normal = self.gradient(q, step);
+                let color = match self.use_colormap && max > min {
+                    true => self.colormap.interp(min, max, q[axis]),
+                    false => self.color,
+                };
+                let uv = [0, 2]
+                    .map(|axis| (q[axis] - self.min[axis]) / (self.max[axis] - self.min[axis]));
+                Vertex {
+                    position: [q[0] * self.scale, q[1] * self.scale, q[2] * self.scale, 1.0],
+                    normal: [normal.x, normal.y, normal.z, 1.0],
+                    color: [color[0], color[1], color[2], 1.0],
+                    tex_coords: uv,
+                }
+            })
+            .collect();
+        Mesh { vertices, indices }
+    }
+
+    // the box f is sampled in, scaled like the mesh
+    pub fn plot_box(&self) -> PlotBox {
+        PlotBox {
+            min: self.min.map(|v| v * self.scale),
+            max: self.max.map(|v| v * self.scale),
+            data_min: self.min,
+            data_max: self.max,
+        }
+    }
+
+    // the normalised gradient of f by central differences a fraction of a
+    // cell wide, straight up where f is flat
+    fn gradient(&self, q: [f32; 3], step: [f32; 3]) -> Vector3<f32> {
+        let f = |q: [f32; 3]| (self.f)(q[0], q[1], q[2], self.params);
+        let mut gradient = [0.0; 3];
+        for axis in 0..3 {
+            let h = step[axis] * 0.1;
+            let (mut a, mut b) = (q, q);
+            a[axis] += h;
+            b[axis] -= h;
+            gradient[axis] = (f(a) - f(b)) / (2.0 * h);
+        }
+        let gradient = Vector3::from(gradient);
+        match gradient.magnitude2() > 0.0 {
+            true => gradient.normalize(),
+            false => Vector3::unit_y(),
+        }
+    }
+}
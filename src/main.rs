@@ -1,118 +1,619 @@
+mod anvil;
+mod atlas;
+mod axes;
+mod biome;
+mod block;
+mod caves;
 mod common;
+mod decoration;
+mod export;
+mod expr;
+mod generator;
+mod heightfield;
+mod hydrology;
+mod implicit;
 mod math_func;
+mod nbt;
+mod ores;
+mod schematic;
 #[path = "surface_data.rs"]
 mod sd;
+mod smooth;
+mod storage;
+mod structures;
+mod terrain;
+mod text;
+mod voxelize;
+mod world;
 use std::f32::consts::PI;
 
+use anyhow::Context;
+use block::Block;
 use common::{Mesh, Vertex};
+use generator::WorldGenerator;
+use sd::colormap::Colormap;
+use storage::WorldStorage;
 use vertex_data::{create_cube, cube_colors, cube_normals, cube_positions};
+use world::World;
 
-// let mut function_selection = 0;
-// let args: Vec<String> = std::env::args().collect();
-// if args.len() > 1 {
-//     function_selection = args[1].parse().unwrap();
-// }
-
-// let ps_struct: sd::ParametricSurface;
-
-// if function_selection == 1 {
-//     ps_struct = sd::ParametricSurface {
-//         f: math_func::klein_bottle,
-//         umin: 0.0,
-//         umax: PI,
-//         vmin: 0.0,
-//         vmax: 2.0 * PI,
-//         u_segments: 120,
-//         v_segments: 40,
-//         scale: 1.0,
-//         ..Default::default()
-//     };
-// } else if function_selection == 2 {
-//     ps_struct = sd::ParametricSurface {
-//         f: math_func::wellenkugel,
-//         umin: 0.0,
-//         umax: 14.5,
-//         vmin: 0.0,
-//         vmax: 5.0,
-//         u_segments: 100,
-//         v_segments: 50,
-//         scale: 0.17,
-//         colormap_name: "cool",
-//         ..Default::default()
-//     };
-// } else {
-//     ps_struct = sd::ParametricSurface {
-//         ..Default::default()
-//     };
-// }
 mod camera;
 mod texture;
 mod vertex_data;
 
+// chunks generated around the origin for a new world
+const TERRAIN_RADIUS: i32 = 4;
+// regions searched around the origin by --locate
+const LOCATE_RADIUS: i32 = 16;
+// how many blocks a unit of a parametric surface spans in the world
+const SURFACE_SIZE: f32 = 6.0;
+// how tall height field data given straight to --surface or --voxelize stands
+// against its width
+const DATA_ASPECT: f32 = 0.25;
+
+// the parametric surfaces --surface can show
+fn parametric_surface(name: &str) -> Option<sd::ParametricSurface> {
+    let surface = match name {
+        "torus" => sd::ParametricSurface::default(),
+        "klein" => sd::ParametricSurface {
+            f: sd::SurfaceFunction::Native(math_func::klein_bottle),
+            normal: None,
+            umin: 0.0,
+            umax: PI,
+            vmin: 0.0,
+            vmax: 2.0 * PI,
+            u_segments: 120,
+            v_segments: 40,
+            scale: 1.0,
+            ..Default::default()
+        },
+        "wellenkugel" => sd::ParametricSurface {
+            f: sd::SurfaceFunction::Native(math_func::wellenkugel),
+            normal: None,
+            umin: 0.0,
+            umax: 14.5,
+            vmin: 0.0,
+            vmax: 5.0,
+            u_segments: 100,
+            v_segments: 50,
+            scale: 0.17,
+            colormap: Colormap::builtin("cool"),
+            ..Default::default()
+        },
+        _ => return None,
+    };
+    Some(surface)
+}
+
+// the implicit surfaces --surface can show
+fn implicit_surface(name: &str) -> Option<implicit::ImplicitSurface> {
+    let surface = match name {
+        "gyroid" => implicit::ImplicitSurface::default(),
+        "metaballs" => implicit::ImplicitSurface {
+            f: math_func::metaballs,
+            min: [-2.0; 3],
+            max: [2.0; 3],
+            segments: [64; 3],
+            params: [0.5, 1.1, 0.0, 0.0, 0.0],
+            colormap: Colormap::builtin("cool"),
+            ..Default::default()
+        },
+        "torus-sdf" => implicit::ImplicitSurface {
+            f: math_func::torus_sdf,
+            min: [-1.5, -0.5, -1.5],
+            max: [1.5, 0.5, 1.5],
+            segments: [60, 20, 60],
+            scale: 1.5,
+            params: [1.0, 0.3, 0.0, 0.0, 0.0],
+            ..Default::default()
+        },
+        "carved-cube" => implicit::ImplicitSurface {
+            f: math_func::carved_cube_sdf,
+            min: [-1.2; 3],
+            max: [1.2; 3],
+            segments: [48; 3],
+            scale: 1.0,
+            params: [1.0, 1.25, 0.0, 0.0, 0.0],
+            colormap: Colormap::builtin("hsv"),
+            ..Default::default()
+        },
+        _ => return None,
+    };
+    Some(surface)
+}
+
+// the height functions --voxelize can build besides the parametric surfaces
+fn height_function(name: &str) -> Option<voxelize::HeightFunction> {
+    let function = match name {
+        "peaks" => voxelize::HeightFunction {
+            f: sd::SurfaceFunction::Native(math_func::peaks),
+            xmin: -3.0,
+            xmax: 3.0,
+            zmin: -3.0,
+            zmax: 3.0,
+            aspect: 0.5,
+            colormap: Colormap::builtin("jet"),
+            params: [0.0; 5],
+        },
+        "sinc" => voxelize::HeightFunction {
+            f: sd::SurfaceFunction::Native(math_func::sinc),
+            xmin: -8.0,
+            xmax: 8.0,
+            zmin: -8.0,
+            zmax: 8.0,
+            aspect: 4.0,
+            colormap: Colormap::builtin("jet"),
+            params: [0.0; 5],
+        },
+        _ => return None,
+    };
+    Some(function)
+}
+
+// a shape that isn't built in: a surface file, the lines of one separated by
+// semicolons, like "height = sin(x) * cos(z); xrange = -pi pi", or a height
+// field data file
+fn user_definition(arg: &str) -> anyhow::Result<expr::Definition> {
+    let path = std::path::Path::new(arg);
+    if arg.contains('=') {
+        expr::Definition::parse("command line", &arg.replace(';', "\n"))
+    } else if heightfield::is_height_data(path) {
+        let field = heightfield::HeightField::load(path)?;
+        Ok(expr::Definition::Height(
+            field.function(DATA_ASPECT, Colormap::default()),
+        ))
+    } else {
+        expr::Definition::load(path)
+    }
+}
+
+// `--surface <name> [<x,y,z>]` meshes, in front of the spawn point unless a
+// position is given, with plot axes around each one for `--axes`
+fn surface_meshes(args: &[String], spawn: [f32; 3]) -> anyhow::Result<common::Surfaces> {
+    let mut surfaces = common::Surfaces::default();
+    let colormap = colormap_arg(args)?;
+    let show_axes = args.iter().any(|arg| arg == "--axes");
+    for (i, _) in args
+        .iter()
+        .enumerate()
+        .filter(|(_, arg)| *arg == "--surface")
+    {
+        let name = args
+            .get(i + 1)
+            .ok_or_else(|| anyhow::anyhow!("--surface needs a surface name"))?;
+        let (mut mesh, used, mut plot) = if let Some(mut surface) = implicit_surface(name) {
+            surface.scale *= SURFACE_SIZE;
+            surface.colormap = colormap.clone().unwrap_or(surface.colormap);
+            (
+                surface.build(),
+                surface.use_colormap.then_some(surface.colormap.clone()),
+                surface.plot_box(),
+            )
+        } else {
+            let mut surface = match parametric_surface(name) {
+                Some(surface) => surface,
+                None => user_definition(name)
+                    .with_context(|| {
+                        format!(
+                            "{} isn't torus, klein, wellenkugel, gyroid, metaballs, torus-sdf, \
+                             carved-cube, a surface definition or height data",
+                            name
+                        )
+                    })?
+                    .surface(),
+            };
+            surface.scale *= SURFACE_SIZE;
+            surface.colormap = colormap.clone().unwrap_or(surface.colormap);
+            (
+                surface.build(),
+                surface.use_colormap.then_some(surface.colormap.clone()),
+                surface.plot_box(),
+            )
+        };
+        let [x, y, z] = spawn;
+        let position = match args.get(i + 2).and_then(|a| parse_position(a)) {
+            Some([px, py, pz]) => [px as f32, py as f32, pz as f32],
+            None => [x, y + SURFACE_SIZE, z - 2.0 * SURFACE_SIZE],
+        };
+        mesh.translate(position);
+        surfaces.add(&mesh, used.as_ref());
+        if show_axes {
+            plot.translate(position);
+            surfaces.add_axes(&plot);
+        }
+    }
+    Ok(surfaces)
+}
+
+// `--colormap <name|file.csv>` colours every shape with one colormap
+fn colormap_arg(args: &[String]) -> anyhow::Result<Option<Colormap>> {
+    arg_value(args, "--colormap")
+        .map(|setting| Colormap::from_setting(setting))
+        .transpose()
+}
+
+// the terrain generator for a seed, with structure templates from
+// `--structures <dir>` added to the built in ones
+fn terrain_generator(args: &[String], seed: u64) -> anyhow::Result<terrain::TerrainGenerator> {
+    let mut generator = terrain::TerrainGenerator::new(seed);
+    if let Some(dir) = arg_value(args, "--structures") {
+        let count = generator
+            .structures
+            .load_templates(std::path::Path::new(dir))?;
+        println!("Loaded {} structure templates from {}", count, dir);
+    }
+    Ok(generator)
+}
+
+fn world_generator(
+    args: &[String],
+    setting: &str,
+    seed: u64,
+) -> anyhow::Result<Box<dyn WorldGenerator>> {
+    if setting == "noise" {
+        return Ok(Box::new(terrain_generator(args, seed)?));
+    }
+    generator::from_setting(setting, seed)
+}
+
+// a new world with the chunks around the origin generated
+fn generated_world(generator: &dyn WorldGenerator, seed: u64) -> World {
+    let mut world = World::new(seed);
+    world.generator = Some(generator.setting());
+    for cz in -TERRAIN_RADIUS..=TERRAIN_RADIUS {
+        for cx in -TERRAIN_RADIUS..=TERRAIN_RADIUS {
+            generator.generate(&mut world, [cx, cz]);
+        }
+    }
+    world.spawn = generator.spawn().unwrap_or_else(|| spawn_point(&world));
+    world
+}
+
+// loads a saved world, or starts it from fresh if it was never saved. Chunks
+// around the player that were never saved are generated if the world
+// remembers its generator
+fn open_world(args: &[String], dir: &str, fresh: World) -> anyhow::Result<(World, WorldStorage)> {
+    let storage = WorldStorage::open(dir)?;
+    let world = match storage.load_level()? {
+        Some(level) => {
+            let mut world = World::new(level.seed);
+            world.spawn = level.spawn;
+            world.generator = level.generator.clone();
+            storage.load_pending(&mut world)?;
+            storage.load_around(&mut world, level.player_position, common::LOAD_RADIUS)?;
+            if let Some(setting) = &level.generator {
+                let generator = world_generator(args, setting, level.seed)?;
+                let [x, _, z] = level.player_position;
+                let [cx, cz] = world::chunk_pos(x.floor() as i32, z.floor() as i32);
+                for pz in cz - common::LOAD_RADIUS..=cz + common::LOAD_RADIUS {
+                    for px in cx - common::LOAD_RADIUS..=cx + common::LOAD_RADIUS {
+                        if world.chunk([px, pz]).is_none() {
+                            generator.generate(&mut world, [px, pz]);
+                        }
+                    }
+                }
+            }
+            world
+        }
+        None => fresh,
+    };
+    Ok((world, storage))
+}
+
+// a few blocks above the loaded chunk closest to the middle of all loaded chunks
+fn spawn_point(world: &World) -> [f32; 3] {
+    let chunks: Vec<_> = world.chunks().map(|(&pos, _)| pos).collect();
+    let count = chunks.len() as f32;
+    let mean = |axis: usize| chunks.iter().map(|pos| pos[axis] as f32).sum::<f32>() / count;
+    let (mx, mz) = (mean(0), mean(1));
+    let Some([cx, cz]) = chunks.into_iter().min_by(|a, b| {
+        let distance = |pos: &[i32; 2]| (pos[0] as f32 - mx).powi(2) + (pos[1] as f32 - mz).powi(2);
+        distance(a).total_cmp(&distance(b))
+    }) else {
+        return world.spawn;
+    };
+    let size = world::CHUNK_SIZE as i32;
+    let (x, z) = (cx * size + size / 2, cz * size + size / 2);
+    let y = world
+        .height_at(x, z)
+        .unwrap_or(world::WORLD_HEIGHT as i32 / 2);
+    [x as f32, y as f32 + 3.0, z as f32]
+}
+
+// "x,y,z" block coordinates
+fn parse_position(arg: &str) -> Option<[i32; 3]> {
+    let values: Vec<i32> = arg
+        .split(',')
+        .map(|v| v.trim().parse())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    values.try_into().ok()
+}
+
+// handles --copy and --paste, which edit the world before it's shown or saved
+fn edit_world(args: &[String], world: &mut World) -> anyhow::Result<()> {
+    if let Some(i) = args.iter().position(|arg| arg == "--copy") {
+        let usage = || anyhow::anyhow!("usage: --copy <x,y,z> <x,y,z> <file.schem>");
+        let a = args.get(i + 1).and_then(|a| parse_position(a));
+        let b = args.get(i + 2).and_then(|b| parse_position(b));
+        let (Some(a), Some(b), Some(path)) = (a, b, args.get(i + 3)) else {
+            return Err(usage());
+        };
+        schematic::Schematic::copy(world, a, b).save(std::path::Path::new(path))?;
+        println!("Copied {:?} to {:?} into {}", a, b, path);
+    }
+    if let Some(i) = args.iter().position(|arg| arg == "--paste") {
+        let usage = || {
+            anyhow::anyhow!(
+                "usage: --paste <file.schem> <x,y,z> [--rotate <0|90|180|270>] [--mirror <x|z>]"
+            )
+        };
+        let origin = args.get(i + 2).and_then(|a| parse_position(a));
+        let (Some(path), Some(origin)) = (args.get(i + 1), origin) else {
+            return Err(usage());
+        };
+        let rotation = match arg_value(args, "--rotate") {
+            Some(degrees) => degrees
+                .parse()
+                .ok()
+                .and_then(schematic::Rotation::from_degrees)
+                .ok_or_else(usage)?,
+            None => schematic::Rotation::None,
+        };
+        let mirror = match arg_value(args, "--mirror").map(String::as_str) {
+            Some("x") => schematic::Mirror::X,
+            Some("z") => schematic::Mirror::Z,
+            None => schematic::Mirror::None,
+            Some(_) => return Err(usage()),
+        };
+        let schematic = schematic::Schematic::load(
+            std::path::Path::new(path),
+            &anvil::BlockMapping::default(),
+        )?;
+        schematic.paste(
+            world,
+            origin,
+            schematic::PasteOptions {
+                rotation,
+                mirror,
+                skip_air: false,
+            },
+        );
+        println!("Pasted {} at {:?}", path, origin);
+    }
+    if let Some(i) = args.iter().position(|arg| arg == "--voxelize") {
+        let usage = || {
+            anyhow::anyhow!(
+                "usage: --voxelize <torus|klein|wellenkugel|peaks|sinc|surface file|height data> <x,y,z> \
+                 [--voxel-scale <blocks>] [--solid] [--palette <block,...>] \
+                 [--colormap <name|file.csv>]"
+            )
+        };
+        let origin = args.get(i + 2).and_then(|a| parse_position(a));
+        let (Some(name), Some(origin)) = (args.get(i + 1), origin) else {
+            return Err(usage());
+        };
+        let scale = match arg_value(args, "--voxel-scale") {
+            Some(scale) => match scale.parse::<f32>() {
+                Ok(scale) if scale.is_finite() && scale > 0.0 => scale,
+                _ => anyhow::bail!("--voxel-scale must be a positive number, not {}", scale),
+            },
+            None => SURFACE_SIZE,
+        };
+        let fill = match args.iter().any(|arg| arg == "--solid") {
+            true => voxelize::Fill::Solid,
+            false => voxelize::Fill::Shell,
+        };
+        let mut voxelizer = voxelize::Voxelizer::new(origin, scale, fill);
+        if let Some(palette) = arg_value(args, "--palette") {
+            voxelizer.palette = palette
+                .split(',')
+                .map(|name| {
+                    Block::from_name(name.trim())
+                        .ok_or_else(|| anyhow::anyhow!("unknown block {}", name))
+                })
+                .collect::<anyhow::Result<_>>()?;
+        }
+        let definition = if let Some(surface) = parametric_surface(name) {
+            expr::Definition::Parametric(surface)
+        } else if let Some(function) = height_function(name) {
+            expr::Definition::Height(function)
+        } else {
+            user_definition(name)?
+        };
+        let colormap = colormap_arg(args)?;
+        let count = match definition {
+            expr::Definition::Parametric(mut surface) => {
+                surface.colormap = colormap.unwrap_or(surface.colormap);
+                voxelizer.parametric(world, &surface)
+            }
+            expr::Definition::Height(mut function) => {
+                function.colormap = colormap.unwrap_or(function.colormap);
+                voxelizer.height(world, &function)?
+            }
+        };
+        println!("Voxelized {} into {} blocks at {:?}", name, count, origin);
+    }
+    Ok(())
+}
+
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a String> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|i| args.get(i + 1))
+}
+
 fn main() {
-    // let (pos_data1, normal_data1, color_data1, index_data1) =
-    //     sd::ParametricSurface::new(sd::ParametricSurface {
-    //         f: math_func::klein_bottle,
-    //         umin: 0.0,
-    //         umax: PI,
-    //         vmin: 0.0,
-    //         vmax: 2.0 * PI,
-    //         u_segments: 120,
-    //         v_segments: 40,
-    //         scale: 1.0,
-    //         ..Default::default()
-    //     });
     let light_data = common::light([1.0, 1.0, 1.0], 0.1, 0.8, 0.4, 30.0, 1);
-    // change run to handle multiple vertex data
-    // let a: Vec<(
-    //     Option<Vec<[f32; 3]>>,
-    //     Option<Vec<[f32; 3]>>,
-    //     Option<Vec<[f32; 3]>>,
-    //     Option<Vec<u32>>,
-    // )> = vec![
-    let mut meshes: Vec<Vec<Vec<Mesh>>> = vec![];
-    // meshes.push(create_sphere(1.0, 100, 50));
-    // meshes.push(sd::ParametricSurface::new(sd::ParametricSurface {
-    //     f: math_func::klein_bottle,
-    //     umin: 0.0,
-    //     umax: PI,
-    //     vmin: 0.0,
-    //     vmax: 2.0 * PI,
-    //     u_segments: 120,
-    //     v_segments: 40,
-    //     scale: 1.0,
-    //     ..Default::default()
-    // }));
-    // meshes.push(create_cube([0.0, 0.0, 0.0], 10.0));
-    let gap = 1.0;
-    // y
-    for x in 0..16 {
-        meshes.push(vec![]);
-        // z
-        for y in 0..16 {
-            meshes[x].push(vec![]);
-            // x
-            for z in 0..16 {
-                meshes[x][y].push(create_cube(
-                    [x as f32 * gap, y as f32 * gap, z as f32 * gap],
-                    1.0,
-                ));
+    // `--world <dir>` loads and autosaves a world instead of the throwaway test world
+    let args: Vec<String> = std::env::args().collect();
+    // `--import <file.mca or region dir>` loads Minecraft chunks, unknown blocks
+    // become `--fallback <block>`
+    let import_path = arg_value(&args, "--import");
+    // `--generator <noise|flat[:layers]|void|cube> [--seed <n>]` picks what makes
+    // new worlds, `--terrain` is short for noise and the test cube is the default
+    let setting = match arg_value(&args, "--generator") {
+        Some(setting) => setting.as_str(),
+        None if args.iter().any(|arg| arg == "--terrain") => "noise",
+        None => "cube",
+    };
+    let seed = match arg_value(&args, "--seed").map(|seed| seed.parse()) {
+        Some(Ok(seed)) => seed,
+        Some(Err(_)) => {
+            eprintln!("--seed needs a number");
+            std::process::exit(1);
+        }
+        None => 0,
+    };
+    // `--locate <village|dungeon|ruin> [--seed <n>]` prints the nearest
+    // structure of a kind to the origin and exits
+    if let Some(i) = args.iter().position(|arg| arg == "--locate") {
+        let generator = match terrain_generator(&args, seed) {
+            Ok(generator) => generator,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                std::process::exit(1);
+            }
+        };
+        let Some(name) = args.get(i + 1) else {
+            eprintln!("--locate needs a structure kind");
+            std::process::exit(1);
+        };
+        if !generator
+            .structures
+            .kinds
+            .iter()
+            .any(|kind| &kind.name == name)
+        {
+            eprintln!("Unknown structure kind {}", name);
+            std::process::exit(1);
+        }
+        match generator
+            .structures
+            .locate(&generator, name, [0, 0], LOCATE_RADIUS)
+        {
+            Some([x, y, z]) => println!("Nearest {} at {}, {}, {}", name, x, y, z),
+            None => println!("No {} within {} regions", name, LOCATE_RADIUS),
+        }
+        return;
+    }
+    let fresh = if import_path.is_some() {
+        World::new(seed)
+    } else {
+        match world_generator(&args, setting, seed) {
+            Ok(generator) => generated_world(generator.as_ref(), seed),
+            Err(e) => {
+                eprintln!("{:?}", e);
+                std::process::exit(1);
+            }
+        }
+    };
+    let (mut world, storage) = match arg_value(&args, "--world") {
+        Some(dir) => match open_world(&args, dir, fresh) {
+            Ok((world, storage)) => (world, Some(storage)),
+            Err(e) => {
+                eprintln!("Failed to open world {}: {:?}", dir, e);
+                std::process::exit(1);
+            }
+        },
+        None => (fresh, None),
+    };
+    if let Some(path) = import_path {
+        let mut mapping = anvil::BlockMapping::default();
+        if let Some(name) = arg_value(&args, "--fallback") {
+            match Block::from_name(name) {
+                Some(block) => mapping.fallback = block,
+                None => {
+                    eprintln!("Unknown fallback block {}", name);
+                    std::process::exit(1);
+                }
             }
         }
+        match anvil::import(std::path::Path::new(path), &mut world, &mapping) {
+            Ok(count) => println!("Imported {} chunks from {}", count, path),
+            Err(e) => {
+                eprintln!("Failed to import {}: {:?}", path, e);
+                std::process::exit(1);
+            }
+        }
+        world.spawn = spawn_point(&world);
+    }
+    // `--copy <x,y,z> <x,y,z> out.schem` and `--paste in.schem <x,y,z>` move
+    // building templates between worlds, `--voxelize <shape> <x,y,z>` builds a
+    // math shape out of blocks
+    if let Err(e) = edit_world(&args, &mut world) {
+        eprintln!("{:?}", e);
+        std::process::exit(1);
+    }
+
+    // `--surface <torus|klein|wellenkugel|gyroid|metaballs|torus-sdf|carved-cube>
+    // [<x,y,z>]`, repeatable, shows parametric and implicit surfaces alongside
+    // the blocks. A surface file or "x = ...; y = ...; z = ..." shows a surface
+    // defined at runtime. `--colormap <name|file.csv>` recolours them all, with a
+    // colorbar on screen for each colormap in use. `--axes` draws plot axes with
+    // gridlines and tick values around each surface
+    let surfaces = match surface_meshes(&args, world.spawn) {
+        Ok(surfaces) => surfaces,
+        Err(e) => {
+            eprintln!("{:?}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // `--smooth` draws the terrain as one smooth surface instead of blocks
+    let style = match args.iter().any(|arg| arg == "--smooth") {
+        true => common::TerrainStyle::Smooth,
+        false => common::TerrainStyle::Blocks,
+    };
+
+    // `--export out.glb|out.obj [<x,y,z> <x,y,z>]` writes the world or a box of it
+    // as a model and exits
+    if let Some(i) = args.iter().position(|arg| arg == "--export") {
+        let Some(path) = args.get(i + 1) else {
+            eprintln!("--export needs an output path");
+            std::process::exit(1);
+        };
+        let a = args.get(i + 2).and_then(|a| parse_position(a));
+        let b = args.get(i + 3).and_then(|b| parse_position(b));
+        let selection = match (a, b) {
+            (Some(a), Some(b)) => world.extract(a, b),
+            _ => world,
+        };
+        if let Err(e) = export::export(&selection, std::path::Path::new(path)) {
+            eprintln!("Failed to export {}: {:?}", path, e);
+            std::process::exit(1);
+        }
+        println!("Exported {}", path);
+        return;
+    }
+
+    // `--screenshot out.png [width height]` renders one frame offscreen and exits
+    if let Some(i) = args.iter().position(|arg| arg == "--screenshot") {
+        let Some(path) = args.get(i + 1) else {
+            eprintln!("--screenshot needs an output path");
+            std::process::exit(1);
+        };
+        let width = args.get(i + 2).and_then(|w| w.parse().ok()).unwrap_or(800);
+        let height = args.get(i + 3).and_then(|h| h.parse().ok()).unwrap_or(600);
+        // above and behind the spawn point, looking down at it
+        let [x, y, z] = world.spawn;
+        let camera = camera::Camera::new(
+            (x - 10.0, y + 19.0, z + 18.0),
+            cgmath::Deg(-48.0),
+            cgmath::Deg(-30.0),
+        );
+        if let Err(e) = common::screenshot(
+            world,
+            &surfaces,
+            style,
+            light_data,
+            camera,
+            [width, height],
+            std::path::Path::new(path),
+        ) {
+            eprintln!("Failed to render screenshot: {:?}", e);
+            std::process::exit(1);
+        }
+        return;
     }
-    // for x in 0..10 {
-    //     meshes[x].push(vec![]);
-    //     for y in 0..10 {
-    //         meshes[x][y].push(vec![]);
-    //         for z in 0..10 {
-    //             meshes[x][y][z] =
-    //                 create_cube([x as f32 * gap, y as f32 * gap, z as f32 * gap], 1.0);
-    //             // meshes.push(create_cube(
-    //             //     [x as f32 * gap, y as f32 * gap, z as f32 * gap],
-    //             //     1.0,
-    //             // ));
-    //         }
-    //     }
-    // }
-    common::run(&meshes, light_data);
+    common::run(world, &surfaces, style, storage, light_data);
 }
@@ -29,13 +29,17 @@ pub fn torus(u:f32, v:f32, params:[f32;5]) -> [f32; 3] {
     [x, y, z]
 }
 
-pub fn peaks (x:f32, z:f32) -> [f32; 3] {
+pub fn torus_normal(u:f32, v:f32, _params:[f32;5]) -> [f32; 3] {
+    [v.cos()*u.cos(), v.sin(), v.cos()*u.sin()]
+}
+
+pub fn peaks (x:f32, z:f32, _params:[f32;5]) -> [f32; 3] {
     let y = 3.0*(1.0-x)*(1.0-x)*(-(x*x)-(z+1.0)*(z+1.0)).exp()-
         10.0*(x/5.0-x*x*x-z*z*z*z*z)*(-x*x-z*z).exp() - 1.0/3.0*(-(x+1.0)*(x+1.0)-z*z).exp();
     [x, y, z]
 }
 
-pub fn sinc (x:f32, z:f32) -> [f32; 3] {
+pub fn sinc (x:f32, z:f32, _params:[f32;5]) -> [f32; 3] {
     let r = (x*x + z*z).sqrt();
     let y = if r == 0.0 { 1.0 } else { r.sin()/r };
     [x, y, z]
@@ -53,4 +57,36 @@ pub fn sphere_position(r:f32, theta:Deg<f32>, phi:Deg<f32>) ->[f32; 3]{
     let y = r*theta.cos();
     let z = -r*theta.sin()*phi.sin();
     [x, y, z]
-}
\ No newline at end of file
+}
+// implicit surfaces are where these are zero, negative inside
+
+pub fn gyroid(x:f32, y:f32, z:f32, params:[f32;5]) -> f32 {
+    // params[0] thickens the sheet, 0 gives the bare minimal surface
+    let g = x.sin()*y.cos() + y.sin()*z.cos() + z.sin()*x.cos();
+    g.abs() - params[0]
+}
+
+pub fn metaballs(x:f32, y:f32, z:f32, params:[f32;5]) -> f32 {
+    // three balls of radius params[0], the middle one params[1] from the others
+    let centers = [[-params[1], 0.0, 0.0], [0.0, 0.3*params[1], 0.0], [params[1], 0.0, 0.2*params[1]]];
+    let mut field = 0.0;
+    for c in centers {
+        let d2 = (x-c[0])*(x-c[0]) + (y-c[1])*(y-c[1]) + (z-c[2])*(z-c[2]);
+        field += params[0]*params[0]/d2.max(1e-6);
+    }
+    1.0 - field
+}
+
+pub fn torus_sdf(x:f32, y:f32, z:f32, params:[f32;5]) -> f32 {
+    let q = (x*x + z*z).sqrt() - params[0];
+    (q*q + y*y).sqrt() - params[1]
+}
+
+pub fn carved_cube_sdf(x:f32, y:f32, z:f32, params:[f32;5]) -> f32 {
+    // a cube of half size params[0] with a ball of radius params[1] cut out of it
+    let q = [x.abs()-params[0], y.abs()-params[0], z.abs()-params[0]];
+    let outside = (q[0].max(0.0).powi(2) + q[1].max(0.0).powi(2) + q[2].max(0.0).powi(2)).sqrt();
+    let cube = outside + q[0].max(q[1]).max(q[2]).min(0.0);
+    let ball = (x*x + y*y + z*z).sqrt() - params[1];
+    cube.max(-ball)
+}
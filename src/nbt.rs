@@ -0,0 +1,339 @@
+#![allow(dead_code)]
+use anyhow::{anyhow, bail, Result};
+use std::collections::BTreeMap;
+
+// Minecraft's named binary tag format, big endian. Used by Anvil region files
+// and schematics
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(BTreeMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+const END: u8 = 0;
+
+impl Tag {
+    fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    // child of a compound
+    pub fn get(&self, key: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    // any integer tag widened to i64
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(v) => Some(v as i64),
+            Tag::Short(v) => Some(v as i64),
+            Tag::Int(v) => Some(v as i64),
+            Tag::Long(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&BTreeMap<String, Tag>> {
+        match self {
+            Tag::Compound(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    pub fn as_long_array(&self) -> Option<&[i64]> {
+        match self {
+            Tag::LongArray(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_byte_array(&self) -> Option<&[i8]> {
+        match self {
+            Tag::ByteArray(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.offset..self.offset + len)
+            .ok_or_else(|| anyhow!("unexpected end of NBT data"))?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.array()?))
+    }
+
+    // arrays and lists can't have negative lengths
+    fn len(&mut self) -> Result<usize> {
+        let len = self.i32()?;
+        usize::try_from(len).map_err(|_| anyhow!("negative NBT length {}", len))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = u16::from_be_bytes(self.array()?) as usize;
+        // Java's modified UTF-8 only differs for NUL and surrogate pairs
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn payload(&mut self, id: u8, depth: usize) -> Result<Tag> {
+        if depth > 512 {
+            bail!("NBT nested too deeply");
+        }
+        Ok(match id {
+            1 => Tag::Byte(self.u8()? as i8),
+            2 => Tag::Short(i16::from_be_bytes(self.array()?)),
+            3 => Tag::Int(self.i32()?),
+            4 => Tag::Long(i64::from_be_bytes(self.array()?)),
+            5 => Tag::Float(f32::from_be_bytes(self.array()?)),
+            6 => Tag::Double(f64::from_be_bytes(self.array()?)),
+            7 => {
+                let len = self.len()?;
+                Tag::ByteArray(self.take(len)?.iter().map(|&b| b as i8).collect())
+            }
+            8 => Tag::String(self.string()?),
+            9 => {
+                let element = self.u8()?;
+                let len = self.len()?;
+                let mut list = Vec::new();
+                for _ in 0..len {
+                    list.push(self.payload(element, depth + 1)?);
+                }
+                Tag::List(list)
+            }
+            10 => {
+                let mut map = BTreeMap::new();
+                loop {
+                    let id = self.u8()?;
+                    if id == END {
+                        break;
+                    }
+                    let name = self.string()?;
+                    map.insert(name, self.payload(id, depth + 1)?);
+                }
+                Tag::Compound(map)
+            }
+            11 => {
+                let len = self.len()?;
+                let bytes = self.take(len.saturating_mul(4))?;
+                Tag::IntArray(
+                    bytes
+                        .chunks_exact(4)
+                        .map(|b| i32::from_be_bytes(b.try_into().unwrap()))
+                        .collect(),
+                )
+            }
+            12 => {
+                let len = self.len()?;
+                let bytes = self.take(len.saturating_mul(8))?;
+                Tag::LongArray(
+                    bytes
+                        .chunks_exact(8)
+                        .map(|b| i64::from_be_bytes(b.try_into().unwrap()))
+                        .collect(),
+                )
+            }
+            _ => bail!("unknown NBT tag type {}", id),
+        })
+    }
+}
+
+// parses uncompressed NBT, returning the root tag's name and value
+pub fn read(data: &[u8]) -> Result<(String, Tag)> {
+    let mut reader = Reader { data, offset: 0 };
+    let id = reader.u8()?;
+    if id != 10 {
+        bail!("NBT root is not a compound");
+    }
+    let name = reader.string()?;
+    let root = reader.payload(id, 0)?;
+    Ok((name, root))
+}
+
+fn write_string(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn write_payload(out: &mut Vec<u8>, tag: &Tag) {
+    match tag {
+        Tag::Byte(v) => out.push(*v as u8),
+        Tag::Short(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::Int(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::Long(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::Float(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::Double(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::ByteArray(values) => {
+            out.extend_from_slice(&(values.len() as i32).to_be_bytes());
+            out.extend(values.iter().map(|&b| b as u8));
+        }
+        Tag::String(s) => write_string(out, s),
+        Tag::List(list) => {
+            // every element has the type of the first, empty lists are typed End
+            out.push(list.first().map_or(END, Tag::id));
+            out.extend_from_slice(&(list.len() as i32).to_be_bytes());
+            for element in list {
+                write_payload(out, element);
+            }
+        }
+        Tag::Compound(map) => {
+            for (name, value) in map {
+                out.push(value.id());
+                write_string(out, name);
+                write_payload(out, value);
+            }
+            out.push(END);
+        }
+        Tag::IntArray(values) => {
+            out.extend_from_slice(&(values.len() as i32).to_be_bytes());
+            for v in values {
+                out.extend_from_slice(&v.to_be_bytes());
+            }
+        }
+        Tag::LongArray(values) => {
+            out.extend_from_slice(&(values.len() as i32).to_be_bytes());
+            for v in values {
+                out.extend_from_slice(&v.to_be_bytes());
+            }
+        }
+    }
+}
+
+// serializes a named root compound, uncompressed
+pub fn write(name: &str, root: &Tag) -> Vec<u8> {
+    let mut out = vec![root.id()];
+    write_string(&mut out, name);
+    write_payload(&mut out, root);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compound(entries: Vec<(&str, Tag)>) -> Tag {
+        Tag::Compound(
+            entries
+                .into_iter()
+                .map(|(name, tag)| (name.to_string(), tag))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn round_trip_every_tag() {
+        let root = compound(vec![
+            ("byte", Tag::Byte(-3)),
+            ("short", Tag::Short(-1234)),
+            ("int", Tag::Int(123456789)),
+            ("long", Tag::Long(-1 << 40)),
+            ("float", Tag::Float(1.5)),
+            ("double", Tag::Double(-0.25)),
+            ("bytes", Tag::ByteArray(vec![1, -2, 3])),
+            ("string", Tag::String("minecraft:stone".to_string())),
+            ("list", Tag::List(vec![Tag::Int(1), Tag::Int(2)])),
+            ("empty list", Tag::List(vec![])),
+            (
+                "compounds",
+                Tag::List(vec![compound(vec![("Name", Tag::String("a".into()))])]),
+            ),
+            ("nested", compound(vec![("inner", compound(vec![]))])),
+            ("ints", Tag::IntArray(vec![-1, 0, i32::MAX])),
+            ("longs", Tag::LongArray(vec![i64::MIN, 0, i64::MAX])),
+        ]);
+        let bytes = write("root name", &root);
+        let (name, tag) = read(&bytes).unwrap();
+        assert_eq!(name, "root name");
+        assert_eq!(tag, root);
+        // writing what was read gives the same bytes
+        assert_eq!(write(&name, &tag), bytes);
+    }
+
+    #[test]
+    fn accessors() {
+        let root = compound(vec![
+            ("Y", Tag::Byte(-4)),
+            ("Name", Tag::String("minecraft:dirt".into())),
+            ("data", Tag::LongArray(vec![7])),
+        ]);
+        assert_eq!(root.get("Y").and_then(Tag::as_i64), Some(-4));
+        assert_eq!(
+            root.get("Name").and_then(Tag::as_str),
+            Some("minecraft:dirt")
+        );
+        assert_eq!(
+            root.get("data").and_then(Tag::as_long_array),
+            Some(&[7][..])
+        );
+        assert!(root.get("missing").is_none());
+        assert!(Tag::Int(1).get("Y").is_none());
+    }
+
+    #[test]
+    fn invalid_data_is_an_error() {
+        // the root has to be a compound
+        assert!(read(&write("", &Tag::Int(1))).is_err());
+        let bytes = write("", &compound(vec![("long", Tag::Long(5))]));
+        for len in 0..bytes.len() {
+            assert!(read(&bytes[..len]).is_err(), "truncated to {} bytes", len);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+#![allow(dead_code)]
+use rand::Rng;
+
+use crate::{
+    block::Block,
+    terrain::chunk_rng,
+    world::{Chunk, ChunkPos, CHUNK_SIZE, WORLD_HEIGHT},
+};
+
+const ORE_SALT: u64 = 0x6f72_6573;
+
+// one kind of ore and where and how often its veins appear
+#[derive(Debug, Clone)]
+pub struct Ore {
+    pub block: Block,
+    // veins start between these heights, both inclusive
+    pub min_height: i32,
+    pub max_height: i32,
+    // the most blocks in one vein, fewer if it runs into caves or other ores
+    pub vein_size: usize,
+    pub veins_per_chunk: u32,
+}
+
+impl Ore {
+    // roughly Minecraft's distribution, scaled to our world height
+    pub fn defaults() -> Vec<Ore> {
+        let ore = |block, min_height, max_height, vein_size, veins_per_chunk| Ore {
+            block,
+            min_height,
+            max_height,
+            vein_size,
+            veins_per_chunk,
+        };
+        vec![
+            ore(Block::CoalOre, 5, 128, 12, 16),
+            ore(Block::IronOre, 5, 64, 8, 10),
+            ore(Block::GoldOre, 5, 32, 8, 2),
+            ore(Block::DiamondOre, 5, 16, 6, 1),
+        ]
+    }
+}
+
+// replaces stone with ore veins. Veins stay inside the chunk so each chunk's
+// ores only depend on the seed and its position
+pub fn place_ores(chunk: &mut Chunk, seed: u64, pos: ChunkPos, ores: &[Ore]) {
+    let size = CHUNK_SIZE as i32;
+    for (i, ore) in ores.iter().enumerate() {
+        if ore.min_height > ore.max_height {
+            continue;
+        }
+        // every ore has its own generator so changing one leaves the others alone
+        let mut rng = chunk_rng(seed, pos, ORE_SALT + i as u64);
+        for _ in 0..ore.veins_per_chunk {
+            let mut position = [
+                rng.gen_range(0..size),
+                rng.gen_range(ore.min_height..=ore.max_height),
+                rng.gen_range(0..size),
+            ];
+            // a random walk, staying in the chunk and the ore's height range
+            for _ in 0..ore.vein_size {
+                let [x, y, z] = position;
+                if (0..WORLD_HEIGHT as i32).contains(&y)
+                    && chunk.get(x as usize, y as usize, z as usize) == Block::Stone
+                {
+                    chunk.set(x as usize, y as usize, z as usize, ore.block);
+                }
+                let axis = rng.gen_range(0..3);
+                let step = if rng.gen_bool(0.5) { 1 } else { -1 };
+                let (min, max) = match axis {
+                    1 => (ore.min_height, ore.max_height),
+                    _ => (0, size - 1),
+                };
+                position[axis] = (position[axis] + step).clamp(min, max);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        terrain::TerrainGenerator,
+        world::{Section, SECTION_COUNT},
+    };
+
+    const SEED: u64 = 42;
+
+    fn stone_chunk() -> Chunk {
+        let mut chunk = Chunk::new();
+        for i in 0..SECTION_COUNT {
+            chunk.set_section(i, Some(Section::filled(Block::Stone)));
+        }
+        chunk
+    }
+
+    // how many blocks of each ore a chunk has, and the heights they're at
+    fn survey(chunk: &Chunk, ores: &[Ore]) -> Vec<(usize, Vec<i32>)> {
+        let mut found = vec![(0, Vec::new()); ores.len()];
+        for y in 0..WORLD_HEIGHT {
+            for z in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    let block = chunk.get(x, y, z);
+                    if let Some(i) = ores.iter().position(|ore| ore.block == block) {
+                        found[i].0 += 1;
+                        found[i].1.push(y as i32);
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    fn positions() -> impl Iterator<Item = ChunkPos> {
+        (-4..4).flat_map(|z| (-4..4).map(move |x| [x, z]))
+    }
+
+    #[test]
+    fn ores_in_stone_stay_in_their_ranges() {
+        let ores = Ore::defaults();
+        let mut totals = vec![0; ores.len()];
+        for pos in positions() {
+            let mut chunk = stone_chunk();
+            place_ores(&mut chunk, SEED, pos, &ores);
+            for (i, (ore, (count, heights))) in ores.iter().zip(survey(&chunk, &ores)).enumerate() {
+                let most = ore.veins_per_chunk as usize * ore.vein_size;
+                assert!(
+                    count <= most,
+                    "{:?} in {:?}: {} blocks",
+                    ore.block,
+                    pos,
+                    count
+                );
+                assert!(count > 0, "no {:?} in {:?}", ore.block, pos);
+                for y in heights {
+                    assert!(
+                        (ore.min_height..=ore.max_height).contains(&y),
+                        "{:?} at y {} in {:?}",
+                        ore.block,
+                        y,
+                        pos
+                    );
+                }
+                totals[i] += count;
+            }
+        }
+        // a vein's random walk steps back onto blocks it already turned, and
+        // veins overlap, but on average most of each vein is new ore
+        let chunks = positions().count();
+        for (ore, total) in ores.iter().zip(totals) {
+            let mean = total as f32 / chunks as f32;
+            let most = (ore.veins_per_chunk as usize * ore.vein_size) as f32;
+            assert!(
+                (0.35 * most..=0.9 * most).contains(&mean),
+                "{:?}: {} blocks per chunk on average, expected up to {}",
+                ore.block,
+                mean,
+                most
+            );
+        }
+    }
+
+    #[test]
+    fn ores_in_generated_chunks_stay_in_their_ranges() {
+        let generator = TerrainGenerator::new(SEED);
+        let ores = Ore::defaults();
+        let mut coal = 0;
+        for pos in positions().step_by(3) {
+            let chunk = generator.generate_chunk(pos);
+            for (ore, (count, heights)) in ores.iter().zip(survey(&chunk, &ores)) {
+                assert!(count <= ore.veins_per_chunk as usize * ore.vein_size);
+                assert!(heights
+                    .iter()
+                    .all(|y| (ore.min_height..=ore.max_height).contains(y)));
+                if ore.block == Block::CoalOre {
+                    coal += count;
+                }
+            }
+        }
+        assert!(coal > 0);
+    }
+
+    #[test]
+    fn ores_depend_on_seed_and_position_only() {
+        let ores = Ore::defaults();
+        let place = |seed, pos| {
+            let mut chunk = stone_chunk();
+            place_ores(&mut chunk, seed, pos, &ores);
+            survey(&chunk, &ores)
+        };
+        assert_eq!(place(SEED, [3, -2]), place(SEED, [3, -2]));
+        assert_ne!(place(SEED, [3, -2]), place(SEED + 1, [3, -2]));
+        assert_ne!(place(SEED, [3, -2]), place(SEED, [-2, 3]));
+    }
+
+    #[test]
+    fn empty_height_range_places_nothing() {
+        let ore = Ore {
+            block: Block::GoldOre,
+            min_height: 40,
+            max_height: 20,
+            vein_size: 8,
+            veins_per_chunk: 4,
+        };
+        let mut chunk = stone_chunk();
+        place_ores(&mut chunk, SEED, [0, 0], std::slice::from_ref(&ore));
+        assert_eq!(survey(&chunk, &[ore])[0].0, 0);
+    }
+}
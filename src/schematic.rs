@@ -0,0 +1,440 @@
+#![allow(dead_code)]
+use anyhow::{anyhow, bail, Context, Result};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{Read, Write},
+    path::Path,
+};
+
+use crate::{
+    anvil::BlockMapping,
+    block::Block,
+    nbt::{self, Tag},
+    world::World,
+};
+
+// Sponge schematics: a gzipped NBT compound with the size, a palette of block
+// state names and one varint palette index per block in y, z, x order.
+// Versions 2 and 3 are read, version 2 is written
+const SPONGE_VERSION: i32 = 2;
+// Minecraft 1.20.1, the block names below exist in it
+const DATA_VERSION: i32 = 3465;
+
+// the name other tools know our blocks by
+fn minecraft_name(block: Block) -> &'static str {
+    match block {
+        Block::Air => "minecraft:air",
+        Block::Grass => "minecraft:grass_block",
+        Block::Dirt => "minecraft:dirt",
+        Block::Stone => "minecraft:stone",
+        Block::Sand => "minecraft:sand",
+        Block::Log => "minecraft:oak_log",
+        Block::Leaves => "minecraft:oak_leaves",
+        Block::Glass => "minecraft:glass",
+        Block::StainedGlass => "minecraft:red_stained_glass",
+        Block::Water => "minecraft:water",
+        Block::Ice => "minecraft:ice",
+        Block::Bedrock => "minecraft:bedrock",
+        Block::CoalOre => "minecraft:coal_ore",
+        Block::IronOre => "minecraft:iron_ore",
+        Block::GoldOre => "minecraft:gold_ore",
+        Block::DiamondOre => "minecraft:diamond_ore",
+        Block::TallGrass => "minecraft:grass",
+        Block::Flower => "minecraft:poppy",
+        Block::Cactus => "minecraft:cactus",
+        Block::Planks => "minecraft:oak_planks",
+        Block::Cobblestone => "minecraft:cobblestone",
+        Block::Path => "minecraft:dirt_path",
+    }
+}
+
+// clockwise around +y, looking down
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+impl Rotation {
+    pub fn from_degrees(degrees: i32) -> Option<Self> {
+        match degrees.rem_euclid(360) {
+            0 => Some(Rotation::None),
+            90 => Some(Rotation::Clockwise90),
+            180 => Some(Rotation::Clockwise180),
+            270 => Some(Rotation::Clockwise270),
+            _ => None,
+        }
+    }
+
+    // where a column of a box of this width (x) and length (z) ends up after rotating
+    pub fn rotate(self, [x, z]: [i32; 2], [width, length]: [i32; 2]) -> [i32; 2] {
+        match self {
+            Rotation::None => [x, z],
+            Rotation::Clockwise90 => [length - 1 - z, x],
+            Rotation::Clockwise180 => [width - 1 - x, length - 1 - z],
+            Rotation::Clockwise270 => [z, width - 1 - x],
+        }
+    }
+}
+
+// flips the x or z axis, applied before rotating
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Mirror {
+    #[default]
+    None,
+    X,
+    Z,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PasteOptions {
+    pub rotation: Rotation,
+    pub mirror: Mirror,
+    // keeps what's already in the world where the schematic has air
+    pub skip_air: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Schematic {
+    // width (x), height (y) and length (z)
+    pub size: [usize; 3],
+    // where the schematic sat relative to whoever copied it, kept for other tools
+    pub offset: [i32; 3],
+    blocks: Vec<Block>,
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn read_varints(data: &[i8], count: usize) -> Result<Vec<usize>> {
+    // every varint takes at least a byte, checked before the count read from the
+    // file decides how much to allocate
+    if count > data.len() {
+        bail!(
+            "block data ends early, {} blocks in {} bytes",
+            count,
+            data.len()
+        );
+    }
+    let mut values = Vec::with_capacity(count);
+    let mut bytes = data.iter().map(|&b| b as u8);
+    for _ in 0..count {
+        let mut value = 0usize;
+        let mut shift = 0;
+        loop {
+            let byte = bytes
+                .next()
+                .ok_or_else(|| anyhow!("block data ends early"))?;
+            value |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+            if shift > 28 {
+                bail!("varint too long in block data");
+            }
+        }
+        values.push(value);
+    }
+    Ok(values)
+}
+
+impl Schematic {
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (y * self.size[2] + z) * self.size[0] + x
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> Block {
+        self.blocks[self.index(x, y, z)]
+    }
+
+    // copies the box between two corners, both inclusive and in any order
+    pub fn copy(world: &World, a: [i32; 3], b: [i32; 3]) -> Self {
+        let min = [a[0].min(b[0]), a[1].min(b[1]), a[2].min(b[2])];
+        let max = [a[0].max(b[0]), a[1].max(b[1]), a[2].max(b[2])];
+        let size = [0, 1, 2].map(|i| (max[i] - min[i] + 1) as usize);
+        let mut blocks = Vec::with_capacity(size[0] * size[1] * size[2]);
+        for y in min[1]..=max[1] {
+            for z in min[2]..=max[2] {
+                for x in min[0]..=max[0] {
+                    blocks.push(world.get_block(x, y, z));
+                }
+            }
+        }
+        Self {
+            size,
+            offset: [0, 0, 0],
+            blocks,
+        }
+    }
+
+    // size on the ground after rotating
+    pub fn rotated_size(&self, rotation: Rotation) -> [usize; 3] {
+        let [width, height, length] = self.size;
+        match rotation {
+            Rotation::Clockwise90 | Rotation::Clockwise270 => [length, height, width],
+            _ => self.size,
+        }
+    }
+
+    // writes the schematic into the world with its minimum corner at origin
+    pub fn paste(&self, world: &mut World, origin: [i32; 3], options: PasteOptions) {
+        let [width, height, length] = self.size;
+        for y in 0..height {
+            for z in 0..length {
+                for x in 0..width {
+                    let block = self.get(x, y, z);
+                    if options.skip_air && block == Block::Air {
+                        continue;
+                    }
+                    let (mx, mz) = match options.mirror {
+                        Mirror::None => (x, z),
+                        Mirror::X => (width - 1 - x, z),
+                        Mirror::Z => (x, length - 1 - z),
+                    };
+                    let [rx, rz] = options
+                        .rotation
+                        .rotate([mx as i32, mz as i32], [width as i32, length as i32]);
+                    world.set_block(origin[0] + rx, origin[1] + y as i32, origin[2] + rz, block);
+                }
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        // sizes are stored as unsigned shorts
+        for (name, size) in ["width", "height", "length"].iter().zip(self.size) {
+            if size > u16::MAX as usize {
+                bail!(
+                    "a schematic can be at most {} blocks in each direction, this one's {} is {}",
+                    u16::MAX,
+                    name,
+                    size
+                );
+            }
+        }
+        let mut palette: Vec<Block> = Vec::new();
+        let mut data = Vec::new();
+        for &block in &self.blocks {
+            let index = match palette.iter().position(|&b| b == block) {
+                Some(index) => index,
+                None => {
+                    palette.push(block);
+                    palette.len() - 1
+                }
+            };
+            write_varint(&mut data, index as u32);
+        }
+
+        let mut root = BTreeMap::new();
+        let mut set = |key: &str, tag| {
+            root.insert(key.to_string(), tag);
+        };
+        set("Version", Tag::Int(SPONGE_VERSION));
+        set("DataVersion", Tag::Int(DATA_VERSION));
+        // unsigned shorts stored in signed tags, checked to fit above
+        set("Width", Tag::Short(self.size[0] as u16 as i16));
+        set("Height", Tag::Short(self.size[1] as u16 as i16));
+        set("Length", Tag::Short(self.size[2] as u16 as i16));
+        set("Offset", Tag::IntArray(self.offset.to_vec()));
+        set("PaletteMax", Tag::Int(palette.len() as i32));
+        set(
+            "Palette",
+            Tag::Compound(
+                palette
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &block)| (minecraft_name(block).to_string(), Tag::Int(i as i32)))
+                    .collect(),
+            ),
+        );
+        set(
+            "BlockData",
+            Tag::ByteArray(data.into_iter().map(|b| b as i8).collect()),
+        );
+        set("BlockEntities", Tag::List(Vec::new()));
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&nbt::write("Schematic", &Tag::Compound(root)))?;
+        fs::write(path, encoder.finish()?)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    // block names are mapped like Anvil imports, block states are ignored
+    pub fn load(path: &Path, mapping: &BlockMapping) -> Result<Self> {
+        let compressed =
+            fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let mut data = Vec::new();
+        GzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut data)
+            .with_context(|| format!("{} is not gzipped", path.display()))?;
+        let (_, root) = nbt::read(&data)?;
+
+        // version 3 nests everything in a Schematic compound and the blocks in a Blocks one
+        let schematic = root.get("Schematic").unwrap_or(&root);
+        let version = schematic
+            .get("Version")
+            .and_then(Tag::as_i64)
+            .ok_or_else(|| anyhow!("not a Sponge schematic"))?;
+        let (palette, block_data) = match version {
+            1 | 2 => (schematic.get("Palette"), schematic.get("BlockData")),
+            3 => {
+                let blocks = schematic.get("Blocks");
+                (
+                    blocks.and_then(|b| b.get("Palette")),
+                    blocks.and_then(|b| b.get("Data")),
+                )
+            }
+            _ => bail!("unsupported schematic version {}", version),
+        };
+
+        let dimension = |key| {
+            schematic
+                .get(key)
+                .and_then(Tag::as_i64)
+                // stored as signed shorts but meant to be unsigned
+                .map(|v| (v & 0xffff) as usize)
+                .ok_or_else(|| anyhow!("schematic without a {}", key))
+        };
+        let size = [
+            dimension("Width")?,
+            dimension("Height")?,
+            dimension("Length")?,
+        ];
+        let offset = match schematic.get("Offset") {
+            Some(Tag::IntArray(values)) if values.len() == 3 => [values[0], values[1], values[2]],
+            _ => [0, 0, 0],
+        };
+
+        let palette = palette
+            .and_then(Tag::as_compound)
+            .ok_or_else(|| anyhow!("schematic without a palette"))?;
+        let mut blocks_by_index = HashMap::new();
+        for (state, index) in palette {
+            let index = index
+                .as_i64()
+                .ok_or_else(|| anyhow!("invalid palette index for {}", state))?;
+            // properties such as [axis=y] don't matter to us
+            let name = state.split('[').next().unwrap_or(state);
+            blocks_by_index.insert(index as usize, mapping.block(name));
+        }
+
+        let block_data = block_data
+            .and_then(Tag::as_byte_array)
+            .ok_or_else(|| anyhow!("schematic without block data"))?;
+        let blocks = read_varints(block_data, size[0] * size[1] * size[2])?
+            .into_iter()
+            .map(|index| {
+                blocks_by_index
+                    .get(&index)
+                    .copied()
+                    .ok_or_else(|| anyhow!("block data refers to missing palette entry {}", index))
+            })
+            .collect::<Result<Vec<Block>>>()?;
+
+        Ok(Self {
+            size,
+            offset,
+            blocks,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an asymmetric L of different blocks, so every rotation and mirror differs
+    fn sample() -> Schematic {
+        let mut world = World::new(0);
+        for x in 0..4 {
+            world.set_block(x, 10, 0, Block::Stone);
+        }
+        world.set_block(0, 10, 1, Block::Log);
+        world.set_block(0, 10, 2, Block::Planks);
+        world.set_block(3, 11, 0, Block::Glass);
+        world.set_block(1, 12, 2, Block::Dirt);
+        Schematic::copy(&world, [0, 10, 0], [3, 12, 2])
+    }
+
+    // pastes and copies the result back
+    fn transformed(schematic: &Schematic, options: PasteOptions) -> Schematic {
+        let mut world = World::new(0);
+        schematic.paste(&mut world, [0, 10, 0], options);
+        let [width, height, length] = schematic.rotated_size(options.rotation);
+        Schematic::copy(
+            &world,
+            [0, 10, 0],
+            [width as i32 - 1, 10 + height as i32 - 1, length as i32 - 1],
+        )
+    }
+
+    fn assert_same(a: &Schematic, b: &Schematic) {
+        assert_eq!(a.size, b.size);
+        assert_eq!(a.blocks, b.blocks);
+    }
+
+    #[test]
+    fn four_quarter_turns_are_the_identity() {
+        let original = sample();
+        let options = PasteOptions {
+            rotation: Rotation::Clockwise90,
+            ..Default::default()
+        };
+        let mut schematic = original.clone();
+        for turn in 1..=4 {
+            schematic = transformed(&schematic, options);
+            if turn < 4 {
+                assert_ne!(schematic.blocks, original.blocks, "after {} turns", turn);
+            }
+        }
+        assert_same(&schematic, &original);
+    }
+
+    #[test]
+    fn mirroring_twice_is_the_identity() {
+        let original = sample();
+        for mirror in [Mirror::X, Mirror::Z] {
+            let options = PasteOptions {
+                mirror,
+                ..Default::default()
+            };
+            let once = transformed(&original, options);
+            assert_ne!(once.blocks, original.blocks, "{:?}", mirror);
+            assert_same(&transformed(&once, options), &original);
+        }
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let path = std::env::temp_dir().join(format!("schematic-{}.schem", std::process::id()));
+        let mut original = sample();
+        original.offset = [1, -2, 3];
+        original.save(&path).unwrap();
+        let loaded = Schematic::load(&path, &BlockMapping::default()).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_same(&loaded, &original);
+        assert_eq!(loaded.offset, original.offset);
+    }
+
+    #[test]
+    fn block_count_past_the_data_is_rejected() {
+        assert_eq!(read_varints(&[1, 0, 2], 3).unwrap(), vec![1, 0, 2]);
+        assert!(read_varints(&[1, 0, 2], usize::MAX).is_err());
+        assert!(read_varints(&[-128, 1], 2).is_err());
+    }
+}
@@ -1,6 +1,12 @@
 // Vertex shader
 struct CameraUniform {
     view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    // xyz: direction towards the sun, w: time of day in [0, 1)
+    sun_direction: vec4<f32>,
+    // x: seconds since start
+    time: vec4<f32>,
 };
 @group(1) @binding(0) // 1.
 var<uniform> camera: CameraUniform;
@@ -82,13 +88,189 @@ fn fs_main(in: Output) -> @location(0) vec4<f32> {
 
     let ambient: f32 = light_uniforms.ambient_intensity;
     let final_color: vec3<f32> = in.v_color.xyz * (ambient + diffuse) + light_uniforms.specular_color.xyz * specular;
-    return textureSample(t_diffuse, s_diffuse, in.v_tex_cords);
+    return textureSample(t_diffuse, s_diffuse, in.v_tex_cords) * in.v_color;
     // return vec4<f32>(final_color, 1.0);
 }
 
+// leaves and glass: fully transparent texels are cut out instead of blended
+@fragment
+fn fs_cutout(in: Output) -> @location(0) vec4<f32> {
+    let color = textureSample(t_diffuse, s_diffuse, in.v_tex_cords) * in.v_color;
+    if color.a < 0.5 {
+        discard;
+    }
+    return vec4<f32>(color.rgb, 1.0);
+}
+
+// parametric surfaces: Blinn-Phong lit by the sun, lit the same from both
+// sides so open surfaces don't go dark from behind
+@fragment
+fn fs_surface(in: Output) -> @location(0) vec4<f32> {
+    var N: vec3<f32> = normalize(in.v_normal.xyz);
+    let V: vec3<f32> = normalize(camera.view_position.xyz - in.v_position.xyz);
+    if dot(N, V) < 0.0 {
+        N = -N;
+    }
+    let L: vec3<f32> = normalize(camera.sun_direction.xyz);
+    let H: vec3<f32> = normalize(L + V);
+    let day = daylight();
+
+    let diffuse: f32 = light_uniforms.diffuse_intensity * max(dot(N, L), 0.0) * day;
+    let specular: f32 = light_uniforms.specular_intensity * pow(max(dot(N, H), 0.0), light_uniforms.specular_shininess) * day;
+    let ambient: f32 = light_uniforms.ambient_intensity + 0.25 * day;
+    let color: vec3<f32> = in.v_color.xyz * (ambient + diffuse) + light_uniforms.specular_color.xyz * specular;
+    return vec4<f32>(color, 1.0);
+}
+
+// screen space quads such as legends, their positions are already in clip space
+@vertex
+fn vs_overlay(in: Input) -> Output {
+    var output: Output;
+    output.position = in.pos;
+    output.v_position = in.pos;
+    output.v_normal = in.normal;
+    output.v_color = in.color;
+    output.v_tex_cords = in.tex_cords;
+    return output;
+}
+
+@fragment
+fn fs_overlay(in: Output) -> @location(0) vec4<f32> {
+    return textureSample(t_diffuse, s_diffuse, in.v_tex_cords) * in.v_color;
+}
+
+// lines such as plot axes, unlit
+@fragment
+fn fs_line(in: Output) -> @location(0) vec4<f32> {
+    return in.v_color;
+}
+
 
 
 // @fragment
 // fn fs_main(in: Output) -> @location(0) vec4<f32> {
 //     return textureSample(t_diffuse, s_diffuse, in.v_tex_cords);
-// }
\ No newline at end of file
+// }
+
+// sky shader
+
+struct SkyOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+@vertex
+fn vs_sky(@builtin(vertex_index) index: u32) -> SkyOutput {
+    // one triangle large enough to cover the whole viewport
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let ndc = uv * 2.0 - 1.0;
+    var output: SkyOutput;
+    output.position = vec4<f32>(ndc, 1.0, 1.0);
+    output.ndc = ndc;
+    return output;
+}
+
+fn hash3(p: vec3<f32>) -> f32 {
+    return fract(sin(dot(p, vec3<f32>(12.9898, 78.233, 37.719))) * 43758.5453);
+}
+
+// 0.0 at night, 1.0 during the day
+fn daylight() -> f32 {
+    return smoothstep(-0.2, 0.2, camera.sun_direction.y);
+}
+
+fn sky_color(dir: vec3<f32>) -> vec3<f32> {
+    let sun = normalize(camera.sun_direction.xyz);
+    let day = daylight();
+
+    let zenith = mix(vec3<f32>(0.01, 0.01, 0.05), vec3<f32>(0.25, 0.5, 0.95), day);
+    let horizon = mix(vec3<f32>(0.05, 0.06, 0.12), vec3<f32>(0.7, 0.82, 0.95), day);
+    if dir.y < 0.0 {
+        return mix(horizon, horizon * 0.4, min(-dir.y * 4.0, 1.0));
+    }
+    var color = mix(horizon, zenith, sqrt(dir.y));
+
+    // orange glow around the sun while it is close to the horizon
+    let glow = pow(max(dot(dir, sun), 0.0), 8.0) * (1.0 - smoothstep(0.0, 0.4, abs(sun.y)));
+    color = color + vec3<f32>(1.0, 0.45, 0.15) * glow * (1.0 - dir.y);
+    return color;
+}
+
+fn stars(dir: vec3<f32>) -> f32 {
+    // turn the star field with the sun so it drifts across the night sky
+    let angle = -camera.sun_direction.w * 6.2831853;
+    let c = cos(angle);
+    let s = sin(angle);
+    let d = vec3<f32>(c * dir.x - s * dir.y, s * dir.x + c * dir.y, dir.z);
+    let cell = floor(d * 300.0);
+    let star = step(0.9985, hash3(cell));
+    return star * (0.5 + 0.5 * hash3(cell + 1.0));
+}
+
+@fragment
+fn fs_sky(in: SkyOutput) -> @location(0) vec4<f32> {
+    let far = camera.inv_view_proj * vec4<f32>(in.ndc, 1.0, 1.0);
+    let dir = normalize(far.xyz / far.w - camera.view_position.xyz);
+    let sun = normalize(camera.sun_direction.xyz);
+    let day = daylight();
+
+    var color = sky_color(dir);
+    color = color + vec3<f32>(stars(dir)) * (1.0 - day) * step(0.0, dir.y);
+
+    let sun_disc = smoothstep(0.9995, 0.9998, dot(dir, sun));
+    color = mix(color, vec3<f32>(1.0, 0.95, 0.8), sun_disc);
+    let moon_disc = smoothstep(0.999, 0.9993, dot(dir, -sun));
+    color = mix(color, vec3<f32>(0.85, 0.87, 0.9), moon_disc * (1.0 - 0.7 * day));
+
+    return vec4<f32>(color, 1.0);
+}
+
+// water shader
+
+fn wave_height(p: vec2<f32>, t: f32) -> f32 {
+    return 0.03 * sin(p.x * 1.7 + t * 1.3) + 0.03 * cos(p.y * 2.3 + t * 1.1)
+        + 0.015 * sin((p.x + p.y) * 3.1 + t * 2.1);
+}
+
+fn wave_normal(p: vec2<f32>, t: f32) -> vec3<f32> {
+    let e = 0.05;
+    let dx = (wave_height(p + vec2<f32>(e, 0.0), t) - wave_height(p - vec2<f32>(e, 0.0), t)) / (2.0 * e);
+    let dz = (wave_height(p + vec2<f32>(0.0, e), t) - wave_height(p - vec2<f32>(0.0, e), t)) / (2.0 * e);
+    return normalize(vec3<f32>(-dx, 1.0, -dz));
+}
+
+@vertex
+fn vs_water(in: Input) -> Output {
+    var output: Output;
+    var m_position: vec4<f32> = uniforms.model_mat * in.pos;
+    // vertices on a water surface sit WATER_SURFACE_DROP (0.125) below a block boundary;
+    // moving them as a function of x and z keeps the sides attached to the surface
+    if abs(fract(m_position.y + 0.5) - 0.875) < 0.01 {
+        m_position.y = m_position.y + wave_height(m_position.xz, camera.time.x) - 0.045;
+    }
+    output.v_position = m_position;
+    output.v_normal = uniforms.normal_mat * in.normal;
+    output.v_color = in.color;
+    output.v_tex_cords = in.tex_cords;
+    output.position = camera.view_proj * m_position;
+    return output;
+}
+
+@fragment
+fn fs_water(in: Output) -> @location(0) vec4<f32> {
+    var N: vec3<f32> = normalize(in.v_normal.xyz);
+    if N.y > 0.5 {
+        N = wave_normal(in.v_position.xz, camera.time.x);
+    }
+    let V: vec3<f32> = normalize(camera.view_position.xyz - in.v_position.xyz);
+    let R: vec3<f32> = reflect(-V, N);
+    let day = daylight();
+
+    // Schlick's approximation, water reflects about 2% of the light head on
+    let fresnel = 0.02 + 0.98 * pow(1.0 - max(dot(N, V), 0.0), 5.0);
+    let base = textureSample(t_diffuse, s_diffuse, in.v_tex_cords) * in.v_color;
+    let glint = pow(max(dot(R, normalize(camera.sun_direction.xyz)), 0.0), 200.0) * day;
+
+    let color = mix(base.rgb * (0.3 + 0.7 * day), sky_color(R), fresnel) + vec3<f32>(glint);
+    return vec4<f32>(color, mix(base.a, 1.0, fresnel));
+}
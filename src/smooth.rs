@@ -0,0 +1,214 @@
+#![allow(dead_code)]
+use crate::{
+    atlas,
+    block::Block,
+    common::{block_color, Mesh, Vertex},
+    generator::{self, WorldGenerator},
+    implicit,
+    world::{chunk_pos, ChunkPos, World, CHUNK_SIZE},
+};
+
+// the rows of the grass tile that show grass rather than dirt
+const GRASS_ROWS: std::ops::Range<u32> = 0..atlas::TILE_SIZE / 8;
+
+// samples between a chunk's blocks and the edge of its density grid: one for
+// the blur and one shared with the neighbouring chunk
+const MARGIN: usize = 2;
+
+// smooth terrain: the ground is a density field, blurred over the blocks
+// around each sample and turned into a mesh without steps by surface nets.
+// The field is the world generator's density wherever it agrees with the
+// blocks, so slopes sit where the generator's heights are rather than on the
+// block steps, and -1 inside solid blocks and 1 in the open elsewhere, so
+// caves and edits show. Worlds whose generator has no density, such as flat
+// and imported ones, are just the blurred blocks. Each chunk is meshed on its
+// own from the blocks around it, so chunks can be meshed as they're
+// generated or loaded
+pub struct SmoothMesher {
+    // the mean colour of each block's tile, by block id. For grass only the
+    // grass at the top of its tile
+    tile_colors: Vec<[f32; 3]>,
+    // the generator the world remembers, None if it doesn't
+    generator: Option<Box<dyn WorldGenerator>>,
+}
+
+impl SmoothMesher {
+    pub fn new(world: &World) -> Self {
+        let atlas = atlas::block_atlas();
+        Self {
+            generator: world
+                .generator
+                .as_ref()
+                .and_then(|setting| generator::from_setting(setting, world.seed).ok()),
+            tile_colors: Block::ALL
+                .iter()
+                .map(|&block| {
+                    let rows = match block {
+                        Block::Grass => GRASS_ROWS,
+                        _ => 0..atlas::TILE_SIZE,
+                    };
+                    atlas::average_color(&atlas, block.tile(), rows)
+                })
+                .collect(),
+        }
+    }
+
+    // the chunk's part of the smooth surface. The density grid reaches into
+    // the neighbouring chunks so the meshes join, and the quads on the low
+    // border are left to the neighbour if there is one
+    pub fn mesh_chunk(&self, world: &World, pos: ChunkPos) -> Mesh {
+        // neighbours can be taller, and their cliffs end in this chunk
+        let top = (-1..=1)
+            .flat_map(|dz| (-1..=1).map(move |dx| [pos[0] + dx, pos[1] + dz]))
+            .filter_map(|pos| world.chunk(pos))
+            .map(|chunk| chunk.top())
+            .max()
+            .unwrap_or(0);
+        if top == 0 {
+            return Mesh::default();
+        }
+        let origin = [
+            pos[0] * CHUNK_SIZE as i32 - MARGIN as i32,
+            -(MARGIN as i32),
+            pos[1] * CHUNK_SIZE as i32 - MARGIN as i32,
+        ];
+        let mut size = [
+            CHUNK_SIZE + 2 * MARGIN,
+            top + 2 * MARGIN,
+            CHUNK_SIZE + 2 * MARGIN,
+        ];
+        let density = self
+            .generator
+            .as_ref()
+            .and_then(|generator| generator.density(origin, size));
+        let mut values = Vec::with_capacity(size[0] * size[1] * size[2]);
+        for z in 0..size[2] {
+            for y in 0..size[1] {
+                for x in 0..size[0] {
+                    let [wx, wy, wz] = world_pos(origin, [x as f32, y as f32, z as f32]);
+                    let solid = is_solid(world, wx, wy, wz);
+                    let value = density.as_ref().map(|density| density[values.len()]);
+                    values.push(match value {
+                        Some(value) if (value < 0.0) == solid => value,
+                        _ if solid => -1.0,
+                        _ => 1.0,
+                    });
+                }
+            }
+        }
+        for axis in 0..3 {
+            (size, values) = blur(size, &values, axis);
+        }
+        // the blur took a sample off every side
+        let origin = origin.map(|o| o + 1);
+        // the low border is ours when there's no neighbour to mesh it
+        let start = [
+            world.chunk([pos[0] - 1, pos[1]]).is_some() as usize,
+            1,
+            world.chunk([pos[0], pos[1] - 1]).is_some() as usize,
+        ];
+        let (positions, indices) = implicit::surface_nets_from(size, &values, start);
+
+        let vertices = positions
+            .iter()
+            .map(|&p| {
+                let [x, y, z] = std::array::from_fn(|axis| origin[axis] as f32 + p[axis]);
+                let normal = normalize(implicit::gradient_at(size, &values, p));
+                Vertex {
+                    position: [x, y, z, 1.0],
+                    normal: [normal[0], normal[1], normal[2], 1.0],
+                    color: self.surface_color(world, [x, y, z]),
+                    tex_coords: [0.0, 0.0],
+                }
+            })
+            .collect();
+        Mesh { vertices, indices }
+    }
+
+    // the colour of the highest solid block among the eight whose centres
+    // surround a point, textures averaged. Grass always takes its biome's
+    // colour since the sides of a smooth slope are also its top
+    fn surface_color(&self, world: &World, p: [f32; 3]) -> [f32; 4] {
+        let [x, y, z] = p.map(|v| v.floor() as i32);
+        let found = [1, 0].into_iter().find_map(|dy| {
+            (0..4).find_map(|i| {
+                let (bx, by, bz) = (x + (i & 1), y + dy, z + (i >> 1));
+                let block = world.get_block(bx, by, bz);
+                is_ground(block).then_some((bx, bz, block))
+            })
+        });
+        let Some((bx, bz, block)) = found else {
+            return Block::Stone.color();
+        };
+        let biome = world.chunk(chunk_pos(bx, bz)).and_then(|chunk| {
+            chunk.biome(
+                bx.rem_euclid(CHUNK_SIZE as i32) as usize,
+                bz.rem_euclid(CHUNK_SIZE as i32) as usize,
+            )
+        });
+        let mut color = block_color(block, biome, true);
+        for (channel, tile) in color.iter_mut().zip(self.tile_colors[block.id() as usize]) {
+            *channel *= tile;
+        }
+        color
+    }
+}
+
+fn world_pos(origin: [i32; 3], sample: [f32; 3]) -> [i32; 3] {
+    std::array::from_fn(|axis| origin[axis] + sample[axis] as i32)
+}
+
+// the natural ground that is smoothed. Everything else, such as logs and
+// buildings, stays blocks, and thin features wouldn't survive the blur anyway
+pub fn is_ground(block: Block) -> bool {
+    matches!(
+        block,
+        Block::Grass
+            | Block::Dirt
+            | Block::Stone
+            | Block::Sand
+            | Block::Bedrock
+            | Block::CoalOre
+            | Block::IronOre
+            | Block::GoldOre
+            | Block::DiamondOre
+    )
+}
+
+// below the world is as solid as the bottom of the world, so the bottom isn't
+// meshed
+fn is_solid(world: &World, x: i32, y: i32, z: i32) -> bool {
+    is_ground(world.get_block(x, y.max(0), z))
+}
+
+// each sample averaged with its two neighbours along an axis, dropping the
+// samples at both ends of that axis
+fn blur(size: [usize; 3], values: &[f32], axis: usize) -> ([usize; 3], Vec<f32>) {
+    let mut blurred_size = size;
+    blurred_size[axis] -= 2;
+    let index = |[x, y, z]: [usize; 3]| x + size[0] * (y + size[1] * z);
+    let mut blurred = Vec::with_capacity(blurred_size.iter().product());
+    for z in 0..blurred_size[2] {
+        for y in 0..blurred_size[1] {
+            for x in 0..blurred_size[0] {
+                let mut p = [x, y, z];
+                let sum: f32 = (0..3)
+                    .map(|i| {
+                        p[axis] = [x, y, z][axis] + i;
+                        values[index(p)]
+                    })
+                    .sum();
+                blurred.push(sum / 3.0);
+            }
+        }
+    }
+    (blurred_size, blurred)
+}
+
+fn normalize([x, y, z]: [f32; 3]) -> [f32; 3] {
+    let length = (x * x + y * y + z * z).sqrt();
+    match length > 0.0 {
+        true => [x / length, y / length, z / length],
+        false => [0.0, 1.0, 0.0],
+    }
+}
@@ -0,0 +1,644 @@
+#![allow(dead_code)]
+use anyhow::{anyhow, bail, Context, Result};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    biome::Biome,
+    block::Block,
+    world::{
+        packed_len, Chunk, ChunkPos, PendingBlocks, Section, World, CHUNK_SIZE, SECTION_COUNT,
+        WORLD_HEIGHT,
+    },
+};
+
+// a world directory holds a level file and a region directory. Each region file
+// stores REGION_SIZE x REGION_SIZE chunks: a header with an (offset, length) pair
+// per chunk, followed by the compressed chunk data. An offset of 0 means the chunk
+// was never saved. Blocks of trees reaching into chunks that weren't generated
+// yet are kept in a pending file until those chunks are.
+pub const REGION_SIZE: i32 = 32;
+const REGION_CHUNKS: usize = (REGION_SIZE * REGION_SIZE) as usize;
+const HEADER_LEN: usize = REGION_CHUNKS * 8;
+// first byte of every chunk blob, leaves room for other compression schemes
+const COMPRESSION_ZLIB: u8 = 1;
+const LEVEL_FILE: &str = "level.txt";
+const REGION_DIR: &str = "region";
+const PENDING_FILE: &str = "pending.bin";
+
+// everything about a world that isn't blocks
+#[derive(Debug, Clone, PartialEq)]
+pub struct Level {
+    pub seed: u64,
+    pub spawn: [f32; 3],
+    pub time_of_day: f32,
+    pub player_position: [f32; 3],
+    // the setting of the generator that makes its missing chunks, None for
+    // imported worlds and ones saved before generators were recorded
+    pub generator: Option<String>,
+}
+
+impl Level {
+    fn to_text(&self) -> String {
+        let [sx, sy, sz] = self.spawn;
+        let [px, py, pz] = self.player_position;
+        let mut text = format!(
+            "seed = {}\nspawn = {} {} {}\ntime_of_day = {}\nplayer_position = {} {} {}\n",
+            self.seed, sx, sy, sz, self.time_of_day, px, py, pz
+        );
+        if let Some(generator) = &self.generator {
+            text += &format!("generator = {}\n", generator);
+        }
+        text
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let values: HashMap<&str, &str> = text
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .collect();
+        let get = |key: &str| {
+            values
+                .get(key)
+                .copied()
+                .ok_or_else(|| anyhow!("missing {} in level file", key))
+        };
+        let vector = |key: &str| -> Result<[f32; 3]> {
+            let parts = get(key)?
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<Vec<f32>, _>>()
+                .with_context(|| format!("invalid {} in level file", key))?;
+            parts
+                .try_into()
+                .map_err(|_| anyhow!("{} needs three components", key))
+        };
+        Ok(Self {
+            seed: get("seed")?.parse().context("invalid seed in level file")?,
+            spawn: vector("spawn")?,
+            time_of_day: get("time_of_day")?
+                .parse()
+                .context("invalid time_of_day in level file")?,
+            player_position: vector("player_position")?,
+            generator: values.get("generator").map(|g| g.to_string()),
+        })
+    }
+}
+
+fn region_of(pos: ChunkPos) -> [i32; 2] {
+    [
+        pos[0].div_euclid(REGION_SIZE),
+        pos[1].div_euclid(REGION_SIZE),
+    ]
+}
+
+// index of a chunk in its region's header
+fn header_index(pos: ChunkPos) -> usize {
+    (pos[1].rem_euclid(REGION_SIZE) * REGION_SIZE + pos[0].rem_euclid(REGION_SIZE)) as usize
+}
+
+// section mask followed by each non-empty section as stored in memory: index
+// width, palette length, palette block ids and the packed index words. Then
+// the column biomes, if any
+fn encode_chunk(chunk: &Chunk) -> Result<Vec<u8>> {
+    let mut raw = Vec::new();
+    let mut mask: u16 = 0;
+    for (i, section) in chunk.sections().iter().enumerate() {
+        if section.is_some() {
+            mask |= 1 << i;
+        }
+    }
+    raw.extend_from_slice(&mask.to_le_bytes());
+    for section in chunk.sections().iter().flatten() {
+        raw.push(section.bits() as u8);
+        raw.extend_from_slice(&(section.palette().len() as u16).to_le_bytes());
+        for block in section.palette() {
+            raw.extend_from_slice(&block.id().to_le_bytes());
+        }
+        for word in section.data() {
+            raw.extend_from_slice(&word.to_le_bytes());
+        }
+    }
+    // a flag, then one biome id per column if the chunk has them
+    match chunk.biomes() {
+        Some(biomes) => {
+            raw.push(1);
+            raw.extend(biomes.iter().map(|biome| biome.id()));
+        }
+        None => raw.push(0),
+    }
+
+    let mut encoder = ZlibEncoder::new(vec![COMPRESSION_ZLIB], Compression::default());
+    encoder.write_all(&raw)?;
+    Ok(encoder.finish()?)
+}
+
+fn decode_chunk(data: &[u8]) -> Result<Chunk> {
+    let (&compression, compressed) = data.split_first().ok_or_else(|| anyhow!("empty chunk"))?;
+    if compression != COMPRESSION_ZLIB {
+        bail!("unknown chunk compression {}", compression);
+    }
+    let mut raw = Vec::new();
+    ZlibDecoder::new(compressed).read_to_end(&mut raw)?;
+
+    let mut offset = 0;
+    let mut read = |len: usize| -> Result<&[u8]> {
+        let bytes = raw
+            .get(offset..offset + len)
+            .ok_or_else(|| anyhow!("truncated chunk"))?;
+        offset += len;
+        Ok(bytes)
+    };
+    let read_u16 = |bytes: &[u8]| u16::from_le_bytes([bytes[0], bytes[1]]);
+
+    let mask = read_u16(read(2)?);
+    let mut chunk = Chunk::new();
+    for i in 0..SECTION_COUNT {
+        if mask & (1 << i) == 0 {
+            continue;
+        }
+        let bits = read(1)?[0] as u32;
+        if bits > 16 {
+            bail!("invalid index width {} in section {}", bits, i);
+        }
+        let palette_len = read_u16(read(2)?) as usize;
+        let palette = read(palette_len * 2)?
+            .chunks_exact(2)
+            .map(|bytes| {
+                let id = read_u16(bytes);
+                Block::from_id(id).ok_or_else(|| anyhow!("unknown block id {}", id))
+            })
+            .collect::<Result<Vec<Block>>>()?;
+        let data = read(packed_len(bits) * 8)?
+            .chunks_exact(8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        let section = Section::from_parts(palette, bits, data)
+            .ok_or_else(|| anyhow!("invalid section {}", i))?;
+        chunk.set_section(i, Some(section));
+    }
+    // chunks saved before biomes existed end here
+    if read(1).is_ok_and(|flag| flag[0] == 1) {
+        let biomes = read(CHUNK_SIZE * CHUNK_SIZE)?
+            .iter()
+            .map(|&id| Biome::from_id(id).ok_or_else(|| anyhow!("unknown biome id {}", id)))
+            .collect::<Result<Vec<Biome>>>()?;
+        chunk.set_biomes(Some(biomes));
+    }
+    chunk.dirty = false;
+    Ok(chunk)
+}
+
+// the number of chunks, then per chunk its position, the number of blocks and
+// each block as x, y, z and block id
+fn encode_pending(world: &World) -> Result<Vec<u8>> {
+    let mut raw = Vec::new();
+    raw.extend_from_slice(&(world.pending().len() as u32).to_le_bytes());
+    for (pos, blocks) in world.pending() {
+        raw.extend_from_slice(&pos[0].to_le_bytes());
+        raw.extend_from_slice(&pos[1].to_le_bytes());
+        raw.extend_from_slice(&(blocks.len() as u32).to_le_bytes());
+        for ([x, y, z], block) in blocks {
+            raw.push(*x as u8);
+            raw.extend_from_slice(&(*y as u16).to_le_bytes());
+            raw.push(*z as u8);
+            raw.extend_from_slice(&block.id().to_le_bytes());
+        }
+    }
+
+    let mut encoder = ZlibEncoder::new(vec![COMPRESSION_ZLIB], Compression::default());
+    encoder.write_all(&raw)?;
+    Ok(encoder.finish()?)
+}
+
+fn decode_pending(data: &[u8]) -> Result<Vec<(ChunkPos, PendingBlocks)>> {
+    let (&compression, compressed) = data
+        .split_first()
+        .ok_or_else(|| anyhow!("empty pending file"))?;
+    if compression != COMPRESSION_ZLIB {
+        bail!("unknown pending file compression {}", compression);
+    }
+    let mut raw = Vec::new();
+    ZlibDecoder::new(compressed).read_to_end(&mut raw)?;
+
+    let mut offset = 0;
+    let mut read = |len: usize| -> Result<&[u8]> {
+        let bytes = raw
+            .get(offset..offset + len)
+            .ok_or_else(|| anyhow!("truncated pending file"))?;
+        offset += len;
+        Ok(bytes)
+    };
+    let read_u32 = |bytes: &[u8]| u32::from_le_bytes(bytes.try_into().unwrap());
+
+    let count = read_u32(read(4)?);
+    let mut pending = Vec::new();
+    for _ in 0..count {
+        let pos = [read_u32(read(4)?) as i32, read_u32(read(4)?) as i32];
+        let len = read_u32(read(4)?);
+        let mut blocks = Vec::new();
+        for _ in 0..len {
+            let bytes = read(6)?;
+            let local = [
+                bytes[0] as usize,
+                u16::from_le_bytes([bytes[1], bytes[2]]) as usize,
+                bytes[3] as usize,
+            ];
+            if local[0] >= CHUNK_SIZE || local[1] >= WORLD_HEIGHT || local[2] >= CHUNK_SIZE {
+                bail!("pending block {:?} is outside its chunk", local);
+            }
+            let id = u16::from_le_bytes([bytes[4], bytes[5]]);
+            let block = Block::from_id(id).ok_or_else(|| anyhow!("unknown block id {}", id))?;
+            blocks.push((local, block));
+        }
+        pending.push((pos, blocks));
+    }
+    Ok(pending)
+}
+
+// writes to a temporary file first so a crash mid-save can't corrupt the old file
+fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data).with_context(|| format!("failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+pub struct WorldStorage {
+    dir: PathBuf,
+}
+
+impl WorldStorage {
+    // creates the directory layout if the world is new
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(dir.join(REGION_DIR))
+            .with_context(|| format!("failed to create world directory {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn region_path(&self, region: [i32; 2]) -> PathBuf {
+        self.dir
+            .join(REGION_DIR)
+            .join(format!("r.{}.{}.region", region[0], region[1]))
+    }
+
+    // None for a world that was never saved
+    pub fn load_level(&self) -> Result<Option<Level>> {
+        let path = self.dir.join(LEVEL_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Level::parse(&text).map(Some)
+    }
+
+    pub fn save_level(&self, level: &Level) -> Result<()> {
+        write_atomic(&self.dir.join(LEVEL_FILE), level.to_text().as_bytes())
+    }
+
+    // queues the saved blocks waiting for chunks that weren't generated yet, so
+    // trees at the edge of the saved area are finished once those chunks are
+    pub fn load_pending(&self, world: &mut World) -> Result<()> {
+        let path = self.dir.join(PENDING_FILE);
+        if !path.exists() {
+            return Ok(());
+        }
+        let data = fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        let pending =
+            decode_pending(&data).with_context(|| format!("corrupt {}", path.display()))?;
+        for (pos, blocks) in pending {
+            world.add_pending(pos, blocks);
+        }
+        Ok(())
+    }
+
+    // every chunk blob stored in a region file, indexed by header_index
+    fn read_region(&self, region: [i32; 2]) -> Result<Vec<Option<Vec<u8>>>> {
+        let mut blobs = vec![None; REGION_CHUNKS];
+        let path = self.region_path(region);
+        if !path.exists() {
+            return Ok(blobs);
+        }
+        let data = fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        for (i, blob) in blobs.iter_mut().enumerate() {
+            *blob = region_entry(&data, i)
+                .with_context(|| format!("corrupt region file {}", path.display()))?
+                .map(<[u8]>::to_vec);
+        }
+        Ok(blobs)
+    }
+
+    fn write_region(&self, region: [i32; 2], blobs: &[Option<Vec<u8>>]) -> Result<()> {
+        let mut header = vec![0u8; HEADER_LEN];
+        let mut body = Vec::new();
+        for (i, blob) in blobs.iter().enumerate() {
+            if let Some(blob) = blob {
+                let offset = (HEADER_LEN + body.len()) as u32;
+                header[i * 8..i * 8 + 4].copy_from_slice(&offset.to_le_bytes());
+                header[i * 8 + 4..i * 8 + 8].copy_from_slice(&(blob.len() as u32).to_le_bytes());
+                body.extend_from_slice(blob);
+            }
+        }
+        header.extend_from_slice(&body);
+        write_atomic(&self.region_path(region), &header)
+    }
+
+    // reads a single chunk from its region file, None if it was never saved
+    pub fn load_chunk(&self, pos: ChunkPos) -> Result<Option<Chunk>> {
+        let path = self.region_path(region_of(pos));
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        match region_entry(&data, header_index(pos))? {
+            Some(blob) => decode_chunk(blob)
+                .map(Some)
+                .with_context(|| format!("corrupt chunk {:?} in {}", pos, path.display())),
+            None => Ok(None),
+        }
+    }
+
+    // loads the saved chunks within radius chunks of a block position that aren't
+    // in the world yet, and returns where they are
+    pub fn load_around(
+        &self,
+        world: &mut World,
+        center: [f32; 3],
+        radius: i32,
+    ) -> Result<Vec<ChunkPos>> {
+        let center = crate::world::chunk_pos(center[0].floor() as i32, center[2].floor() as i32);
+        let mut loaded = Vec::new();
+        for cz in center[1] - radius..=center[1] + radius {
+            for cx in center[0] - radius..=center[0] + radius {
+                let pos = [cx, cz];
+                if world.chunk(pos).is_some() {
+                    continue;
+                }
+                if let Some(chunk) = self.load_chunk(pos)? {
+                    world.insert_chunk(pos, chunk);
+                    loaded.push(pos);
+                }
+            }
+        }
+        Ok(loaded)
+    }
+
+    // writes every dirty chunk, rewriting only the regions they belong to.
+    // Returns the number of chunks saved
+    pub fn save_chunks(&self, world: &mut World) -> Result<usize> {
+        let mut regions: HashMap<[i32; 2], Vec<(ChunkPos, Vec<u8>)>> = HashMap::new();
+        for (&pos, chunk) in world.chunks_mut() {
+            if chunk.dirty {
+                chunk.compact();
+                regions
+                    .entry(region_of(pos))
+                    .or_default()
+                    .push((pos, encode_chunk(chunk)?));
+            }
+        }
+
+        let mut saved = 0;
+        for (region, chunks) in regions {
+            let mut blobs = self.read_region(region)?;
+            for (pos, blob) in &chunks {
+                blobs[header_index(*pos)] = Some(blob.clone());
+            }
+            self.write_region(region, &blobs)?;
+            // only clean once the region is safely on disk
+            for (pos, _) in &chunks {
+                if let Some(chunk) = world.chunk_mut(*pos) {
+                    chunk.dirty = false;
+                }
+            }
+            saved += chunks.len();
+        }
+
+        let path = self.dir.join(PENDING_FILE);
+        if !world.pending().is_empty() {
+            write_atomic(&path, &encode_pending(world)?)?;
+        } else if path.exists() {
+            fs::remove_file(&path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+        }
+        Ok(saved)
+    }
+}
+
+// the blob of chunk i in a region file, None if the chunk isn't stored
+fn region_entry(data: &[u8], i: usize) -> Result<Option<&[u8]>> {
+    let entry = data
+        .get(i * 8..i * 8 + 8)
+        .ok_or_else(|| anyhow!("truncated header"))?;
+    let offset = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]) as usize;
+    let length = u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]) as usize;
+    if offset == 0 {
+        return Ok(None);
+    }
+    data.get(offset..offset + length)
+        .map(Some)
+        .ok_or_else(|| anyhow!("chunk data out of bounds"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a directory of its own under the system temp dir, emptied first
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("storage-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    // a chunk with a single block section, a mixed palette section and
+    // biomes, varied by seed
+    fn sample_chunk(seed: usize) -> Chunk {
+        let mut chunk = Chunk::new();
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                for y in 0..CHUNK_SIZE {
+                    chunk.set(x, y, z, Block::Stone);
+                }
+                let block = Block::ALL[(x * 7 + z * 3 + seed) % Block::ALL.len()];
+                chunk.set(x, 40 + (x + z + seed) % 5, z, block);
+            }
+        }
+        chunk.set(3, WORLD_HEIGHT - 1, 4, Block::Glass);
+        let biomes = (0..CHUNK_SIZE * CHUNK_SIZE)
+            .map(|i| Biome::ALL[(i + seed) % Biome::ALL.len()])
+            .collect();
+        chunk.set_biomes(Some(biomes));
+        chunk
+    }
+
+    fn assert_same_chunk(a: &Chunk, b: &Chunk) {
+        for y in 0..WORLD_HEIGHT {
+            for z in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    assert_eq!(a.get(x, y, z), b.get(x, y, z), "block at {:?}", (x, y, z));
+                }
+            }
+        }
+        assert_eq!(a.biomes(), b.biomes());
+    }
+
+    #[test]
+    fn chunk_round_trip() {
+        let chunk = sample_chunk(0);
+        let decoded = decode_chunk(&encode_chunk(&chunk).unwrap()).unwrap();
+        assert_same_chunk(&chunk, &decoded);
+        assert!(!decoded.dirty);
+        // the same sections are stored, with their palettes
+        for (a, b) in chunk.sections().iter().zip(decoded.sections()) {
+            assert_eq!(a.is_some(), b.is_some());
+            if let (Some(a), Some(b)) = (a, b) {
+                assert_eq!(a.palette(), b.palette());
+                assert_eq!(a.bits(), b.bits());
+            }
+        }
+        assert!(chunk
+            .sections()
+            .iter()
+            .flatten()
+            .any(|s| s.palette().len() > 2));
+    }
+
+    #[test]
+    fn chunk_without_biomes_round_trip() {
+        let mut chunk = sample_chunk(1);
+        chunk.set_biomes(None);
+        let decoded = decode_chunk(&encode_chunk(&chunk).unwrap()).unwrap();
+        assert_same_chunk(&chunk, &decoded);
+    }
+
+    #[test]
+    fn corrupt_chunk_is_an_error() {
+        let mut data = encode_chunk(&sample_chunk(0)).unwrap();
+        data[0] = 7;
+        assert!(decode_chunk(&data).is_err());
+        assert!(decode_chunk(&[COMPRESSION_ZLIB]).is_err());
+    }
+
+    #[test]
+    fn region_round_trip() {
+        let dir = temp_dir("region");
+        let storage = WorldStorage::open(&dir).unwrap();
+        // chunks sharing region 0, 0 and one in region -1, 0
+        let positions = [[0, 0], [1, 0], [5, 9], [31, 31], [-1, 0]];
+        let mut world = World::new(1);
+        for (i, &pos) in positions.iter().enumerate() {
+            world.insert_chunk(pos, sample_chunk(i));
+        }
+        assert_eq!(storage.save_chunks(&mut world).unwrap(), positions.len());
+        assert!(world.chunks().all(|(_, chunk)| !chunk.dirty));
+        // nothing is dirty, so nothing is written again
+        assert_eq!(storage.save_chunks(&mut world).unwrap(), 0);
+
+        for &pos in &positions {
+            let loaded = storage.load_chunk(pos).unwrap().expect("saved chunk");
+            assert_same_chunk(world.chunk(pos).unwrap(), &loaded);
+        }
+        assert!(storage.load_chunk([2, 2]).unwrap().is_none());
+        assert!(storage.load_chunk([100, 100]).unwrap().is_none());
+
+        // rewriting one chunk keeps the others in its region
+        world.set_block(16 + 2, 70, 3, Block::Planks);
+        assert_eq!(storage.save_chunks(&mut world).unwrap(), 1);
+        let loaded = storage.load_chunk([1, 0]).unwrap().unwrap();
+        assert_eq!(loaded.get(2, 70, 3), Block::Planks);
+        assert_same_chunk(
+            world.chunk([0, 0]).unwrap(),
+            &storage.load_chunk([0, 0]).unwrap().unwrap(),
+        );
+
+        let mut reloaded = World::new(1);
+        let mut loaded = storage
+            .load_around(&mut reloaded, [8.0, 64.0, 8.0], 2)
+            .unwrap();
+        loaded.sort();
+        assert_eq!(loaded, [[-1, 0], [0, 0], [1, 0]]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pending_round_trip() {
+        let dir = temp_dir("pending");
+        let storage = WorldStorage::open(&dir).unwrap();
+        let mut world = World::new(1);
+        world.insert_chunk([0, 0], sample_chunk(0));
+        // a tree at the edge of chunk 0, 0 with leaves reaching into 1, 0 and
+        // -1, -1, which don't exist yet
+        for y in 60..66 {
+            world.place_block(15, y, 8, Block::Log);
+        }
+        world.place_block(16, 65, 8, Block::Leaves);
+        world.place_block(17, 65, 8, Block::Leaves);
+        world.place_block(-1, 64, -1, Block::Leaves);
+        storage.save_chunks(&mut world).unwrap();
+        assert!(dir.join(PENDING_FILE).exists());
+
+        let mut reloaded = World::new(1);
+        storage.load_pending(&mut reloaded).unwrap();
+        storage
+            .load_around(&mut reloaded, [8.0, 64.0, 8.0], 0)
+            .unwrap();
+        assert_eq!(reloaded.get_block(15, 65, 8), Block::Log);
+        assert_eq!(reloaded.pending().len(), 2);
+        reloaded.insert_chunk([1, 0], Chunk::new());
+        reloaded.insert_chunk([-1, -1], Chunk::new());
+        assert_eq!(reloaded.get_block(16, 65, 8), Block::Leaves);
+        assert_eq!(reloaded.get_block(17, 65, 8), Block::Leaves);
+        assert_eq!(reloaded.get_block(-1, 64, -1), Block::Leaves);
+
+        // nothing left waiting, so the file goes
+        storage.save_chunks(&mut reloaded).unwrap();
+        assert!(!dir.join(PENDING_FILE).exists());
+        assert!(decode_pending(&[COMPRESSION_ZLIB, 1, 2]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn level_round_trip() {
+        for generator in [Some("flat:bedrock,dirt*3,grass".to_string()), None] {
+            let level = Level {
+                seed: 12345678901234,
+                spawn: [0.5, 71.0, -3.25],
+                time_of_day: 0.375,
+                player_position: [-120.5, 80.125, 64.0],
+                generator,
+            };
+            assert_eq!(Level::parse(&level.to_text()).unwrap(), level);
+        }
+        assert!(Level::parse("seed = 1\n").is_err());
+        assert!(Level::parse("seed = x\n").is_err());
+    }
+
+    #[test]
+    fn level_file_round_trip() {
+        let dir = temp_dir("level");
+        let storage = WorldStorage::open(&dir).unwrap();
+        assert!(storage.load_level().unwrap().is_none());
+        let level = Level {
+            seed: 7,
+            spawn: [1.0, 2.0, 3.0],
+            time_of_day: 0.5,
+            player_position: [4.0, 5.0, 6.0],
+            generator: Some("noise".to_string()),
+        };
+        storage.save_level(&level).unwrap();
+        assert_eq!(storage.load_level().unwrap(), Some(level));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
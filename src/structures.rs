@@ -0,0 +1,964 @@
+#![allow(dead_code)]
+use anyhow::{anyhow, bail, Context, Result};
+use rand::{rngs::StdRng, Rng};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    path::Path,
+    sync::Mutex,
+};
+
+use crate::{
+    biome::Biome,
+    block::Block,
+    schematic::Rotation,
+    terrain::{chunk_rng, Column, TerrainGenerator},
+    world::{Chunk, ChunkPos, CHUNK_SIZE, WORLD_HEIGHT},
+};
+
+const STRUCTURE_SALT: u64 = 0x7374_7275_6374;
+// no piece of a structure reaches further than this from its start, so only
+// regions this close to a chunk are looked at when generating it
+const MAX_RADIUS: i32 = 64;
+// pieces on the surface are left out where the ground under their corners
+// differs by more than this
+const MAX_SLOPE: i32 = 4;
+// layouts kept for chunks still to be generated. A chunk looks at the regions
+// within MAX_RADIUS of it, so as the player walks the recent ones are the
+// ones needed again and the least recently used is dropped
+const MAX_LAYOUTS: usize = 256;
+const ROTATIONS: [Rotation; 4] = [
+    Rotation::None,
+    Rotation::Clockwise90,
+    Rotation::Clockwise180,
+    Rotation::Clockwise270,
+];
+
+// the templates that come with the game, more can be loaded from a directory
+const BUILTIN_TEMPLATES: [(&str, &str); 9] = [
+    ("village_well", include_str!("structures/village_well.txt")),
+    (
+        "village_street",
+        include_str!("structures/village_street.txt"),
+    ),
+    (
+        "village_crossroads",
+        include_str!("structures/village_crossroads.txt"),
+    ),
+    (
+        "village_house",
+        include_str!("structures/village_house.txt"),
+    ),
+    (
+        "village_large_house",
+        include_str!("structures/village_large_house.txt"),
+    ),
+    (
+        "village_garden",
+        include_str!("structures/village_garden.txt"),
+    ),
+    ("dungeon_room", include_str!("structures/dungeon_room.txt")),
+    (
+        "dungeon_corridor",
+        include_str!("structures/dungeon_corridor.txt"),
+    ),
+    ("ruin", include_str!("structures/ruin.txt")),
+];
+
+// horizontal directions, north is towards -z
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Facing {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Facing {
+    // clockwise, in the same order as ROTATIONS
+    const ALL: [Facing; 4] = [Facing::North, Facing::East, Facing::South, Facing::West];
+
+    fn from_name(name: &str) -> Option<Facing> {
+        match name {
+            "north" => Some(Facing::North),
+            "east" => Some(Facing::East),
+            "south" => Some(Facing::South),
+            "west" => Some(Facing::West),
+            _ => None,
+        }
+    }
+
+    fn index(self) -> usize {
+        Self::ALL.iter().position(|&f| f == self).unwrap()
+    }
+
+    fn rotated(self, rotation: Rotation) -> Facing {
+        let steps = ROTATIONS.iter().position(|&r| r == rotation).unwrap();
+        Self::ALL[(self.index() + steps) % 4]
+    }
+
+    fn opposite(self) -> Facing {
+        Self::ALL[(self.index() + 2) % 4]
+    }
+
+    fn offset(self) -> [i32; 2] {
+        match self {
+            Facing::North => [0, -1],
+            Facing::East => [1, 0],
+            Facing::South => [0, 1],
+            Facing::West => [-1, 0],
+        }
+    }
+}
+
+// a place on a template's side where another piece can be joined on. The
+// other piece comes from the pool and joins with a connector facing this one
+#[derive(Debug, Clone)]
+pub struct Connector {
+    pub position: [i32; 3],
+    pub facing: Facing,
+    pub pool: String,
+}
+
+// a piece of a structure, read from a text file. See src/structures for examples:
+// `key <char> <block>` lines name the characters used in the layers, `.` leaves
+// the world as it is. Each `layer` is a grid of rows along x, one row per z,
+// starting at the bottom
+#[derive(Debug, Clone)]
+pub struct Template {
+    pub name: String,
+    // the pool connectors pick this template from
+    pub pool: String,
+    // how often it's picked compared to the others in its pool
+    pub weight: u32,
+    // width (x), height (y) and length (z)
+    pub size: [i32; 3],
+    // streets and paths follow the ground column by column instead of sitting
+    // at one height
+    pub follows_terrain: bool,
+    // filled in under the bottom layer down to the ground
+    pub foundation: Option<Block>,
+    pub connectors: Vec<Connector>,
+    blocks: Vec<Option<Block>>,
+}
+
+impl Template {
+    pub fn parse(name: &str, text: &str) -> Result<Self> {
+        let mut pool = None;
+        let mut weight = 1;
+        let mut follows_terrain = false;
+        let mut foundation = None;
+        let mut keys = HashMap::new();
+        let mut connectors = Vec::new();
+        let mut layers: Vec<Vec<&str>> = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            let error = || anyhow!("{} line {}: can't read {:?}", name, number + 1, line);
+            let block = |name: &str| Block::from_name(name).ok_or_else(error);
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["layer"] => layers.push(Vec::new()),
+                _ if !layers.is_empty() => layers.last_mut().unwrap().push(line),
+                ["pool", name] => pool = Some(name.to_string()),
+                ["weight", n] => weight = n.parse().map_err(|_| error())?,
+                ["terrain"] => follows_terrain = true,
+                ["foundation", name] => foundation = Some(block(name)?),
+                ["key", key, name] => {
+                    let mut chars = key.chars();
+                    let (Some(key), None) = (chars.next(), chars.next()) else {
+                        return Err(error());
+                    };
+                    if key == '.' {
+                        return Err(error());
+                    }
+                    keys.insert(key, block(name)?);
+                }
+                ["connector", x, y, z, facing, pool] => {
+                    let coordinate = |v: &str| v.parse().map_err(|_| error());
+                    connectors.push(Connector {
+                        position: [coordinate(x)?, coordinate(y)?, coordinate(z)?],
+                        facing: Facing::from_name(facing).ok_or_else(error)?,
+                        pool: pool.to_string(),
+                    });
+                }
+                _ => return Err(error()),
+            }
+        }
+
+        let pool = pool.ok_or_else(|| anyhow!("{} has no pool", name))?;
+        let length = layers.first().map_or(0, Vec::len);
+        let width = layers
+            .first()
+            .and_then(|rows| rows.first())
+            .map_or(0, |row| row.len());
+        if width == 0 || length == 0 {
+            bail!("{} has no blocks", name);
+        }
+        let mut blocks = Vec::with_capacity(width * length * layers.len());
+        for (y, rows) in layers.iter().enumerate() {
+            if rows.len() != length || rows.iter().any(|row| row.chars().count() != width) {
+                bail!("layer {} of {} isn't {} by {}", y, name, width, length);
+            }
+            for row in rows {
+                for c in row.chars() {
+                    blocks.push(match c {
+                        '.' => None,
+                        c => Some(
+                            *keys
+                                .get(&c)
+                                .ok_or_else(|| anyhow!("{} uses {:?} without a key", name, c))?,
+                        ),
+                    });
+                }
+            }
+        }
+        let size = [width as i32, layers.len() as i32, length as i32];
+        for connector in &connectors {
+            let [x, y, z] = connector.position;
+            let on_side = match connector.facing {
+                Facing::North => z == 0,
+                Facing::East => x == size[0] - 1,
+                Facing::South => z == size[2] - 1,
+                Facing::West => x == 0,
+            };
+            let inside = (0..3).all(|i| (0..size[i]).contains(&connector.position[i]));
+            if !inside || !on_side {
+                bail!(
+                    "connector at {:?} of {} isn't on its {:?} side",
+                    [x, y, z],
+                    name,
+                    connector.facing
+                );
+            }
+        }
+        Ok(Self {
+            name: name.to_string(),
+            pool,
+            weight,
+            size,
+            follows_terrain,
+            foundation,
+            connectors,
+            blocks,
+        })
+    }
+
+    pub fn get(&self, x: i32, y: i32, z: i32) -> Option<Block> {
+        self.blocks[((y * self.size[2] + z) * self.size[0] + x) as usize]
+    }
+
+    // width and length on the ground after rotating
+    fn footprint(&self, rotation: Rotation) -> [i32; 2] {
+        match rotation {
+            Rotation::Clockwise90 | Rotation::Clockwise270 => [self.size[2], self.size[0]],
+            _ => [self.size[0], self.size[2]],
+        }
+    }
+}
+
+// how the start of a structure is placed
+#[derive(Copy, Clone, Debug)]
+pub enum Placement {
+    // on the ground, sunk this many blocks into it
+    Surface { sink: i32 },
+    // somewhere between these heights, whatever the ground above
+    Underground { min_height: i32, max_height: i32 },
+}
+
+// one kind of structure and where it appears
+#[derive(Debug, Clone)]
+pub struct StructureKind {
+    pub name: String,
+    // the pool the first piece is picked from, the rest grow from its connectors
+    pub start_pool: String,
+    // the world is split into regions of this many chunks a side, each with
+    // at most one structure of this kind
+    pub region: i32,
+    pub chance: f64,
+    // biomes it can start in, any if empty
+    pub biomes: Vec<Biome>,
+    // how many pieces away from the start it grows
+    pub max_depth: u32,
+    pub placement: Placement,
+}
+
+impl StructureKind {
+    pub fn defaults() -> Vec<StructureKind> {
+        let kind = |name: &str, start_pool: &str, region, chance, biomes: &[Biome]| StructureKind {
+            name: name.to_string(),
+            start_pool: start_pool.to_string(),
+            region,
+            chance,
+            biomes: biomes.to_vec(),
+            max_depth: 0,
+            placement: Placement::Surface { sink: 0 },
+        };
+        vec![
+            StructureKind {
+                max_depth: 5,
+                ..kind(
+                    "village",
+                    "village/centers",
+                    12,
+                    0.6,
+                    &[Biome::Plains, Biome::Desert, Biome::Taiga, Biome::Forest],
+                )
+            },
+            StructureKind {
+                max_depth: 3,
+                placement: Placement::Underground {
+                    min_height: 12,
+                    max_height: 40,
+                },
+                ..kind("dungeon", "dungeon/rooms", 6, 0.5, &[])
+            },
+            StructureKind {
+                placement: Placement::Surface { sink: 2 },
+                ..kind(
+                    "ruin",
+                    "ruins",
+                    8,
+                    0.3,
+                    &[
+                        Biome::Plains,
+                        Biome::Forest,
+                        Biome::Taiga,
+                        Biome::Swamp,
+                        Biome::Desert,
+                        Biome::Mountains,
+                    ],
+                )
+            },
+        ]
+    }
+
+    fn is_surface(&self) -> bool {
+        matches!(self.placement, Placement::Surface { .. })
+    }
+}
+
+// a template placed in the world
+#[derive(Debug, Clone)]
+struct Piece {
+    template: usize,
+    rotation: Rotation,
+    // the minimum corner after rotating. Pieces that follow the terrain only
+    // use its x and z
+    origin: [i32; 3],
+    // ground heights under the corners of pieces that follow the terrain, in
+    // x, z order from the minimum corner. The ground in between is flattened
+    // out to a slope between them
+    corners: [i32; 4],
+}
+
+impl Piece {
+    fn footprint(&self, templates: &[Template]) -> ([i32; 2], [i32; 2]) {
+        let [width, length] = templates[self.template].footprint(self.rotation);
+        let min = [self.origin[0], self.origin[2]];
+        (min, [min[0] + width - 1, min[1] + length - 1])
+    }
+
+    // the height of the bottom layer over a column, relative to the footprint
+    fn base(&self, templates: &[Template], [x, z]: [i32; 2]) -> i32 {
+        if !templates[self.template].follows_terrain {
+            return self.origin[1];
+        }
+        let [width, length] = templates[self.template].footprint(self.rotation);
+        let t = |v: i32, size: i32| v as f32 / (size - 1).max(1) as f32;
+        let (tx, tz) = (t(x, width), t(z, length));
+        let [a, b, c, d] = self.corners.map(|h| h as f32);
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        lerp(lerp(a, b, tx), lerp(c, d, tx), tz).round() as i32
+    }
+
+    // connectors in world space: the block they're on, where they face and their pool
+    fn connectors<'a>(
+        &'a self,
+        templates: &'a [Template],
+    ) -> impl Iterator<Item = ([i32; 3], Facing, &'a str)> + 'a {
+        let template = &templates[self.template];
+        let [width, _, length] = template.size;
+        let base = move |column| self.base(templates, column);
+        template.connectors.iter().map(move |connector| {
+            let [x, y, z] = connector.position;
+            let column = self.rotation.rotate([x, z], [width, length]);
+            (
+                [
+                    self.origin[0] + column[0],
+                    base(column) + y,
+                    self.origin[2] + column[1],
+                ],
+                connector.facing.rotated(self.rotation),
+                connector.pool.as_str(),
+            )
+        })
+    }
+}
+
+// a structure kind and a region of it
+type LayoutKey = (usize, [i32; 2]);
+
+// the pieces of the structure of each kind and region, None where there isn't
+// one, with when each was last used
+#[derive(Debug, Default)]
+struct Layouts {
+    entries: HashMap<LayoutKey, (Option<Vec<Piece>>, u64)>,
+    clock: u64,
+}
+
+impl Layouts {
+    fn get(&mut self, key: LayoutKey) -> Option<Option<Vec<Piece>>> {
+        self.clock += 1;
+        let (layout, used) = self.entries.get_mut(&key)?;
+        *used = self.clock;
+        Some(layout.clone())
+    }
+
+    fn insert(&mut self, key: LayoutKey, layout: Option<Vec<Piece>>) {
+        if self.entries.len() >= MAX_LAYOUTS && !self.entries.contains_key(&key) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.entries.insert(key, (layout, self.clock));
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+// structure templates and kinds, placed into chunks as they're generated.
+// Structures are laid out per region from the seed alone, so every chunk they
+// cross places its part of the same layout
+#[derive(Debug)]
+pub struct Structures {
+    pub kinds: Vec<StructureKind>,
+    templates: Vec<Template>,
+    // layouts are needed by every chunk a structure crosses, so the recently
+    // used ones are kept
+    layouts: Mutex<Layouts>,
+}
+
+impl Structures {
+    pub fn defaults() -> Self {
+        let templates = BUILTIN_TEMPLATES
+            .iter()
+            .map(|(name, text)| Template::parse(name, text).unwrap())
+            .collect();
+        Self {
+            kinds: StructureKind::defaults(),
+            templates,
+            layouts: Mutex::new(Layouts::default()),
+        }
+    }
+
+    pub fn templates(&self) -> &[Template] {
+        &self.templates
+    }
+
+    // adds the templates of every .txt file in a directory, replacing built in
+    // ones with the same file name
+    pub fn load_templates(&mut self, dir: &Path) -> Result<usize> {
+        let entries =
+            fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))?;
+        let mut count = 0;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("txt") {
+                continue;
+            }
+            let name = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .ok_or_else(|| anyhow!("invalid file name {}", path.display()))?;
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            let template = Template::parse(name, &text)?;
+            match self.templates.iter_mut().find(|t| t.name == template.name) {
+                Some(existing) => *existing = template,
+                None => self.templates.push(template),
+            }
+            count += 1;
+        }
+        self.layouts.lock().unwrap().clear();
+        Ok(count)
+    }
+
+    // a template from a pool, more likely the higher its weight. Ones already
+    // tried are left out
+    fn pick(&self, rng: &mut StdRng, pool: &str, tried: &[usize]) -> Option<usize> {
+        let candidates: Vec<usize> = (0..self.templates.len())
+            .filter(|i| self.templates[*i].pool == pool && !tried.contains(i))
+            .collect();
+        let total: u32 = candidates.iter().map(|&i| self.templates[i].weight).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut n = rng.gen_range(0..total);
+        for i in candidates {
+            let weight = self.templates[i].weight;
+            if n < weight {
+                return Some(i);
+            }
+            n -= weight;
+        }
+        None
+    }
+
+    // where a structure of a kind starts in a region, if there's one
+    fn start(
+        &self,
+        generator: &TerrainGenerator,
+        kind: usize,
+        region: [i32; 2],
+    ) -> Option<(StdRng, [i32; 3])> {
+        let structure = &self.kinds[kind];
+        let mut rng = chunk_rng(generator.seed(), region, STRUCTURE_SALT + kind as u64);
+        if !rng.gen_bool(structure.chance) {
+            return None;
+        }
+        // starts keep a quarter of a region away from the next region, so
+        // structures in neighbouring regions don't run into each other
+        let spread = (structure.region * 3 / 4).max(1);
+        let chunk = [0, 1].map(|i| region[i] * structure.region + rng.gen_range(0..spread));
+        let size = CHUNK_SIZE as i32;
+        let (x, z) = (chunk[0] * size + size / 2, chunk[1] * size + size / 2);
+        let biome = generator.biome_at(x, z);
+        if !structure.biomes.is_empty() && !structure.biomes.contains(&biome) {
+            return None;
+        }
+        let y = match structure.placement {
+            Placement::Surface { sink } => {
+                let column = generator.column_at(x, z);
+                if column.is_underwater() {
+                    return None;
+                }
+                column.height - sink
+            }
+            Placement::Underground {
+                min_height,
+                max_height,
+            } => rng.gen_range(min_height..=max_height),
+        };
+        Some((rng, [x, y, z]))
+    }
+
+    // grows a structure from its start piece: every connector gets a piece from
+    // its pool joined on with a connector facing back, unless none fits
+    fn assemble(
+        &self,
+        generator: &TerrainGenerator,
+        kind: usize,
+        region: [i32; 2],
+    ) -> Option<Vec<Piece>> {
+        let (mut rng, start) = self.start(generator, kind, region)?;
+        let structure = &self.kinds[kind];
+        let templates = &self.templates;
+        let mut ground = HashMap::new();
+        let mut column = |x: i32, z: i32| -> Column {
+            *ground
+                .entry([x, z])
+                .or_insert_with(|| generator.column_at(x, z))
+        };
+
+        let first = self.pick(&mut rng, &structure.start_pool, &[])?;
+        let rotation = ROTATIONS[rng.gen_range(0..4)];
+        let [width, length] = templates[first].footprint(rotation);
+        let mut pieces = vec![Piece {
+            template: first,
+            rotation,
+            origin: [start[0] - width / 2, start[1], start[2] - length / 2],
+            corners: [start[1]; 4],
+        }];
+        let mut queue = VecDeque::from([(0, 0)]);
+        while let Some((index, depth)) = queue.pop_front() {
+            if depth >= structure.max_depth {
+                continue;
+            }
+            let connectors: Vec<_> = pieces[index]
+                .connectors(templates)
+                .map(|(position, facing, pool)| (position, facing, pool.to_string()))
+                .collect();
+            for (position, facing, pool) in connectors {
+                let [dx, dz] = facing.offset();
+                let target = [position[0] + dx, position[1], position[2] + dz];
+                let taken = |pieces: &[Piece]| {
+                    pieces.iter().any(|piece| {
+                        let (min, max) = piece.footprint(templates);
+                        (min[0]..=max[0]).contains(&target[0])
+                            && (min[1]..=max[1]).contains(&target[2])
+                    })
+                };
+                if taken(&pieces) {
+                    continue;
+                }
+                let mut tried = Vec::new();
+                let mut joined = None;
+                while joined.is_none() {
+                    let Some(template) = self.pick(&mut rng, &pool, &tried) else {
+                        break;
+                    };
+                    tried.push(template);
+                    let first_rotation = rng.gen_range(0..4);
+                    for step in 0..4 {
+                        let rotation = ROTATIONS[(first_rotation + step) % 4];
+                        let candidate = self.join(template, rotation, target, facing, &mut column);
+                        let fits = candidate.as_ref().is_some_and(|piece| {
+                            self.fits(piece, &pieces, start, structure, &mut column)
+                        });
+                        if fits {
+                            joined = candidate;
+                            break;
+                        }
+                    }
+                }
+                if let Some(piece) = joined {
+                    pieces.push(piece);
+                    queue.push_back((pieces.len() - 1, depth + 1));
+                }
+            }
+        }
+        Some(pieces)
+    }
+
+    // a template rotated and moved so one of its connectors sits on target,
+    // facing back the way it was reached
+    fn join(
+        &self,
+        template: usize,
+        rotation: Rotation,
+        target: [i32; 3],
+        facing: Facing,
+        column: &mut impl FnMut(i32, i32) -> Column,
+    ) -> Option<Piece> {
+        let t = &self.templates[template];
+        let [width, _, length] = t.size;
+        let connector = t
+            .connectors
+            .iter()
+            .find(|c| c.facing.rotated(rotation) == facing.opposite())?;
+        let [x, y, z] = connector.position;
+        let [cx, cz] = rotation.rotate([x, z], [width, length]);
+        let mut piece = Piece {
+            template,
+            rotation,
+            origin: [target[0] - cx, target[1] - y, target[2] - cz],
+            corners: [0; 4],
+        };
+        if t.follows_terrain {
+            let (min, max) = piece.footprint(&self.templates);
+            piece.corners = [
+                column(min[0], min[1]).height,
+                column(max[0], min[1]).height,
+                column(min[0], max[1]).height,
+                column(max[0], max[1]).height,
+            ];
+        }
+        Some(piece)
+    }
+
+    // whether a piece can be added without overlapping the others, leaving the
+    // structure's radius or standing in water or on a slope
+    fn fits(
+        &self,
+        piece: &Piece,
+        pieces: &[Piece],
+        start: [i32; 3],
+        structure: &StructureKind,
+        column: &mut impl FnMut(i32, i32) -> Column,
+    ) -> bool {
+        let (min, max) = piece.footprint(&self.templates);
+        let far = |v: i32, s: i32| (v - s).abs() > MAX_RADIUS;
+        if far(min[0], start[0]) || far(max[0], start[0]) {
+            return false;
+        }
+        if far(min[1], start[2]) || far(max[1], start[2]) {
+            return false;
+        }
+        let overlaps = pieces.iter().any(|other| {
+            let (other_min, other_max) = other.footprint(&self.templates);
+            min[0] <= other_max[0]
+                && max[0] >= other_min[0]
+                && min[1] <= other_max[1]
+                && max[1] >= other_min[1]
+        });
+        if overlaps {
+            return false;
+        }
+        if !structure.is_surface() {
+            return (0..WORLD_HEIGHT as i32).contains(&piece.origin[1]);
+        }
+        let corners = [
+            column(min[0], min[1]),
+            column(max[0], min[1]),
+            column(min[0], max[1]),
+            column(max[0], max[1]),
+        ];
+        let lowest = corners.iter().map(|c| c.height).min().unwrap();
+        let highest = corners.iter().map(|c| c.height).max().unwrap();
+        !corners.iter().any(Column::is_underwater) && highest - lowest <= MAX_SLOPE
+    }
+
+    fn layout(
+        &self,
+        generator: &TerrainGenerator,
+        kind: usize,
+        region: [i32; 2],
+    ) -> Option<Vec<Piece>> {
+        if let Some(layout) = self.layouts.lock().unwrap().get((kind, region)) {
+            return layout;
+        }
+        let layout = self.assemble(generator, kind, region);
+        self.layouts
+            .lock()
+            .unwrap()
+            .insert((kind, region), layout.clone());
+        layout
+    }
+
+    // writes the parts of all structures that reach into a chunk. Columns are
+    // the chunk's ground, which foundations are built down to
+    pub fn place(
+        &self,
+        chunk: &mut Chunk,
+        pos: ChunkPos,
+        generator: &TerrainGenerator,
+        columns: &[Column],
+    ) {
+        let size = CHUNK_SIZE as i32;
+        let origin = [pos[0] * size, pos[1] * size];
+        for kind in 0..self.kinds.len() {
+            let region_size = self.kinds[kind].region * size;
+            let regions = |axis: usize| {
+                (origin[axis] - MAX_RADIUS).div_euclid(region_size)
+                    ..=(origin[axis] + size - 1 + MAX_RADIUS).div_euclid(region_size)
+            };
+            for rz in regions(1) {
+                for rx in regions(0) {
+                    let Some(pieces) = self.layout(generator, kind, [rx, rz]) else {
+                        continue;
+                    };
+                    for piece in &pieces {
+                        self.place_piece(
+                            chunk,
+                            origin,
+                            piece,
+                            columns,
+                            self.kinds[kind].is_surface(),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn place_piece(
+        &self,
+        chunk: &mut Chunk,
+        origin: [i32; 2],
+        piece: &Piece,
+        columns: &[Column],
+        surface: bool,
+    ) {
+        let template = &self.templates[piece.template];
+        let (min, max) = piece.footprint(&self.templates);
+        let size = CHUNK_SIZE as i32;
+        if max[0] < origin[0]
+            || min[0] >= origin[0] + size
+            || max[1] < origin[1]
+            || min[1] >= origin[1] + size
+        {
+            return;
+        }
+        let [width, height, length] = template.size;
+        let mut set = |x: i32, y: i32, z: i32, block: Block| {
+            if (0..WORLD_HEIGHT as i32).contains(&y) {
+                chunk.set(x as usize, y as usize, z as usize, block);
+            }
+        };
+        for z in 0..length {
+            for x in 0..width {
+                let column = piece.rotation.rotate([x, z], [width, length]);
+                let (lx, lz) = (
+                    min[0] + column[0] - origin[0],
+                    min[1] + column[1] - origin[1],
+                );
+                if !(0..size).contains(&lx) || !(0..size).contains(&lz) {
+                    continue;
+                }
+                let base = piece.base(&self.templates, column);
+                for y in 0..height {
+                    if let Some(block) = template.get(x, y, z) {
+                        set(lx, base + y, lz, block);
+                    }
+                }
+                // surface pieces stand on a foundation where the ground is lower
+                // and have the ground above them dug away where it's higher
+                if !surface || template.get(x, 0, z).is_none() {
+                    continue;
+                }
+                let ground = columns[(lz * size + lx) as usize].height;
+                if let Some(foundation) = template.foundation {
+                    for y in ground + 1..base {
+                        set(lx, y, lz, foundation);
+                    }
+                }
+                for y in base + height..=ground {
+                    set(lx, y, lz, Block::Air);
+                }
+            }
+        }
+    }
+
+    // the start of the nearest structure of a kind to a point, looking at most
+    // this many regions away. Regions are searched in growing squares until
+    // the closest any start in the next square could be is further than the
+    // nearest one found
+    pub fn locate(
+        &self,
+        generator: &TerrainGenerator,
+        name: &str,
+        from: [i32; 2],
+        max_regions: i32,
+    ) -> Option<[i32; 3]> {
+        let kind = self.kinds.iter().position(|k| k.name == name)?;
+        let region_size = self.kinds[kind].region * CHUNK_SIZE as i32;
+        let center = from.map(|v| v.div_euclid(region_size));
+        let distance = |p: &[i32; 3]| {
+            let (dx, dz) = ((p[0] - from[0]) as i64, (p[2] - from[1]) as i64);
+            dx * dx + dz * dz
+        };
+        // how far the point is from the nearest edge of its own region
+        let edge = [0, 1]
+            .map(|axis| {
+                let inside = from[axis] - center[axis] * region_size;
+                (inside + 1).min(region_size - inside)
+            })
+            .into_iter()
+            .min()
+            .unwrap() as i64;
+        let mut nearest: Option<[i32; 3]> = None;
+        for ring in 0..=max_regions {
+            if let Some(found) = &nearest {
+                let closest = (ring as i64 - 1) * region_size as i64 + edge;
+                if closest * closest > distance(found) {
+                    break;
+                }
+            }
+            let starts = (-ring..=ring)
+                .flat_map(|rz| (-ring..=ring).map(move |rx| [rx, rz]))
+                .filter(|[rx, rz]| rx.abs() == ring || rz.abs() == ring)
+                .filter_map(|[rx, rz]| {
+                    self.start(generator, kind, [center[0] + rx, center[1] + rz])
+                })
+                .map(|(_, start)| start);
+            // the earlier one wins a tie
+            nearest = nearest.into_iter().chain(starts).min_by_key(distance);
+        }
+        nearest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn same_blocks(a: &Chunk, b: &Chunk) -> bool {
+        (0..WORLD_HEIGHT).all(|y| {
+            (0..CHUNK_SIZE).all(|z| (0..CHUNK_SIZE).all(|x| a.get(x, y, z) == b.get(x, y, z)))
+        })
+    }
+
+    #[test]
+    fn placement_does_not_depend_on_order() {
+        let generator = TerrainGenerator::new(9);
+        let start = generator
+            .structures
+            .locate(&generator, "village", [0, 0], 8)
+            .expect("no village");
+        let pos = [start[0], start[2]].map(|v| v.div_euclid(CHUNK_SIZE as i32));
+        let first = generator.generate_chunk(pos);
+        assert!((0..WORLD_HEIGHT).any(|y| {
+            (0..CHUNK_SIZE).any(|z| (0..CHUNK_SIZE).any(|x| first.get(x, y, z) == Block::Path))
+        }));
+        // another generator that lays out the neighbours first
+        let other = TerrainGenerator::new(9);
+        for dz in [1, -1] {
+            for dx in [1, -1] {
+                other.generate_chunk([pos[0] + dx, pos[1] + dz]);
+            }
+        }
+        assert!(same_blocks(&first, &other.generate_chunk(pos)));
+        assert!(same_blocks(&first, &generator.generate_chunk(pos)));
+    }
+
+    #[test]
+    fn layouts_drop_the_least_recently_used() {
+        let mut layouts = Layouts::default();
+        for i in 0..MAX_LAYOUTS as i32 {
+            layouts.insert((0, [i, 0]), None);
+        }
+        // the first is used again, so the second is the oldest
+        assert!(layouts.get((0, [0, 0])).is_some());
+        layouts.insert((1, [0, 0]), Some(Vec::new()));
+        assert_eq!(layouts.entries.len(), MAX_LAYOUTS);
+        assert!(layouts.get((0, [0, 0])).is_some());
+        assert!(layouts.get((0, [1, 0])).is_none());
+        assert!(matches!(layouts.get((1, [0, 0])), Some(Some(_))));
+        // replacing an entry drops nothing
+        layouts.insert((0, [2, 0]), None);
+        assert_eq!(layouts.entries.len(), MAX_LAYOUTS);
+        assert!(layouts.get((0, [3, 0])).is_some());
+    }
+
+    #[test]
+    fn locate_finds_the_nearest() {
+        let generator = TerrainGenerator::new(4);
+        let structures = &generator.structures;
+        let max_regions = 4;
+        for (name, from) in [
+            ("ruin", [0, 0]),
+            ("ruin", [-700, 300]),
+            ("dungeon", [50, -20]),
+        ] {
+            let kind = structures
+                .kinds
+                .iter()
+                .position(|k| k.name == name)
+                .unwrap();
+            let region_size = structures.kinds[kind].region * CHUNK_SIZE as i32;
+            let center = from.map(|v: i32| v.div_euclid(region_size));
+            let distance = |p: &[i32; 3]| {
+                let (dx, dz) = ((p[0] - from[0]) as i64, (p[2] - from[1]) as i64);
+                dx * dx + dz * dz
+            };
+            let nearest = (-max_regions..=max_regions)
+                .flat_map(|rz| (-max_regions..=max_regions).map(move |rx| [rx, rz]))
+                .filter_map(|[rx, rz]| {
+                    structures.start(&generator, kind, [center[0] + rx, center[1] + rz])
+                })
+                .map(|(_, start)| distance(&start))
+                .min();
+            assert!(nearest.is_some(), "no {} near {:?}", name, from);
+            let located = structures.locate(&generator, name, from, max_regions);
+            assert_eq!(
+                located.as_ref().map(distance),
+                nearest,
+                "{} from {:?}",
+                name,
+                from
+            );
+        }
+        assert_eq!(structures.locate(&generator, "castle", [0, 0], 4), None);
+    }
+}
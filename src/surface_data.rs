@@ -1,13 +1,59 @@
 #![allow(dead_code)]
 use cgmath::*;
-use std::f32::consts::PI;
-
-use crate::common::{Mesh, Vertex};
-mod colormap;
+use std::{f32::consts::PI, sync::Arc};
+
+use crate::{
+    axes::PlotBox,
+    common::{Mesh, Vertex},
+    expr::Expr,
+    heightfield::HeightField,
+};
+use colormap::Colormap;
+pub mod colormap;
 mod math_func;
 
+// a point, or a normal, at (u, v) of a surface with up to five parameters
+pub type SurfaceFn = fn(f32, f32, [f32; 5]) -> [f32; 3];
+
+// the shape of a surface: a function built in, the x, y and z expressions
+// of a surface file, taking the variables in expr::SURFACE_VARIABLES order, or
+// a height field whose heights are y at (x, z) = (u, v). A field fitted into
+// -1..1 keeps the range its heights were measured in
+#[derive(Clone)]
+pub enum SurfaceFunction {
+    Native(SurfaceFn),
+    Expressions(Arc<[Expr; 3]>),
+    Grid(Arc<HeightField>, [f32; 2]),
+}
+
+impl SurfaceFunction {
+    pub fn eval(&self, u: f32, v: f32, params: [f32; 5]) -> [f32; 3] {
+        match self {
+            SurfaceFunction::Native(f) => f(u, v, params),
+            SurfaceFunction::Expressions(expressions) => {
+                let [p0, p1, p2, p3, p4] = params;
+                let values = [u, v, p0, p1, p2, p3, p4];
+                [0, 1, 2].map(|i| expressions[i].eval(&values))
+            }
+            SurfaceFunction::Grid(field, _) => [u, field.height(u, v), v],
+        }
+    }
+
+    // a point of eval back in the units of the data it came from
+    pub fn data_point(&self, p: [f32; 3]) -> [f32; 3] {
+        match self {
+            SurfaceFunction::Grid(_, [min, max]) => {
+                [p[0], min + (p[1] + 1.0) / 2.0 * (max - min), p[2]]
+            }
+            _ => p,
+        }
+    }
+}
+
 pub struct ParametricSurface {
-    pub f: fn(f32, f32, [f32; 5]) -> [f32; 3],
+    pub f: SurfaceFunction,
+    // the surface normal at (u, v), None to work it out from f
+    pub normal: Option<SurfaceFn>,
     pub umin: f32,
     pub umax: f32,
     pub vmin: f32,
@@ -17,7 +63,7 @@ pub struct ParametricSurface {
     pub scale: f32,
     pub aspect: f32,
     pub use_colormap: bool,
-    pub colormap_name: &'static str,
+    pub colormap: Colormap,
     pub colormap_direction: &'static str,
     pub color: [f32; 3],
     pub params: [f32; 5],
@@ -26,7 +72,8 @@ pub struct ParametricSurface {
 impl Default for ParametricSurface {
     fn default() -> Self {
         ParametricSurface {
-            f: math_func::torus,
+            f: SurfaceFunction::Native(math_func::torus),
+            normal: Some(math_func::torus_normal),
             umin: 0.0,
             umax: 2.0 * PI,
             vmin: 0.0,
@@ -36,7 +83,7 @@ impl Default for ParametricSurface {
             scale: 1.5,
             aspect: 1.0,
             use_colormap: true,
-            colormap_name: "jet",
+            colormap: Colormap::default(),
             colormap_direction: "y",
             color: [1.0, 0.0, 0.0],
             params: [1.0, 0.3, 0.0, 0.0, 0.0],
@@ -45,144 +92,142 @@ impl Default for ParametricSurface {
 }
 
 impl ParametricSurface {
-    // pub fn new(ps: ParametricSurface) -> (Vec<[f32;3]>, Vec<[f32;3]>, Vec<[f32;3]>, Vec<u32>) {
-    // pub fn new(ps: ParametricSurface) -> Mesh {
-    //     let n_vertices = (ps.u_segments + 1) * (ps.v_segments + 1);
-    //     let mut positions: Vec<[f32; 3]> = Vec::with_capacity(n_vertices);
-    //     let mut normals: Vec<[f32; 3]> = Vec::with_capacity(n_vertices);
-    //     let mut colors: Vec<[f32; 3]> = Vec::with_capacity(n_vertices);
-
-    //     let du = (ps.umax - ps.umin) / ps.u_segments as f32;
-    //     let dv = (ps.vmax - ps.vmin) / ps.v_segments as f32;
-
-    //     let eps = 0.00001;
-    //     let mut p0: Vector3<f32>;
-    //     let mut p1: Vector3<f32>;
-    //     let mut p2: Vector3<f32>;
-    //     let mut p3: Vector3<f32>;
-    //     let mut pa: [f32; 3];
-
-    //     let mut cd = 1;
-    //     if ps.colormap_direction == "x" {
-    //         cd = 0;
-    //     } else if ps.colormap_direction == "z" {
-    //         cd = 2;
-    //     }
-
-    //     let (min, max) = parametric_surface_range(
-    //         ps.f,
-    //         ps.umin,
-    //         ps.umax,
-    //         ps.vmin,
-    //         ps.vmax,
-    //         ps.u_segments,
-    //         ps.v_segments,
-    //         ps.scale,
-    //         ps.aspect,
-    //         ps.params,
-    //         cd,
-    //     );
-
-    //     for i in 0..=ps.u_segments {
-    //         let u = ps.umin + i as f32 * du;
-    //         for j in 0..=ps.v_segments {
-    //             let v = ps.vmin + j as f32 * dv;
-    //             pa = (ps.f)(u, v, ps.params);
-    //             p0 = Vector3::new(
-    //                 pa[0] * ps.scale,
-    //                 ps.scale * ps.aspect * pa[1],
-    //                 ps.scale * pa[2],
-    //             );
-
-    //             // calculate normals
-    //             if u - eps >= 0.0 {
-    //                 pa = (ps.f)(u - eps, v, ps.params);
-    //                 p1 = Vector3::new(
-    //                     pa[0] * ps.scale,
-    //                     ps.scale * ps.aspect * pa[1],
-    //                     ps.scale * pa[2],
-    //                 );
-    //                 p2 = p0 - p1;
-    //             } else {
-    //                 pa = (ps.f)(u + eps, v, ps.params);
-    //                 p1 = Vector3::new(
-    //                     pa[0] * ps.scale,
-    //                     ps.scale * ps.aspect * pa[1],
-    //                     ps.scale * pa[2],
-    //                 );
-    //                 p2 = p1 - p0;
-    //             }
-
-    //             if v - eps >= 0.0 {
-    //                 pa = (ps.f)(u, v - eps, ps.params);
-    //                 p1 = Vector3::new(
-    //                     pa[0] * ps.scale,
-    //                     ps.scale * ps.aspect * pa[1],
-    //                     ps.scale * pa[2],
-    //                 );
-    //                 p3 = p0 - p1;
-    //             } else {
-    //                 pa = (ps.f)(u, v + eps, ps.params);
-    //                 p1 = Vector3::new(
-    //                     pa[0] * ps.scale,
-    //                     ps.scale * ps.aspect * pa[1],
-    //                     ps.scale * pa[2],
-    //                 );
-    //                 p3 = p1 - p0;
-    //             }
-    //             let normal = p3.cross(p2).normalize();
-
-    //             // calculate colrmap
-    //             let mut color = ps.color;
-    //             if ps.use_colormap {
-    //                 color = colormap::color_interp(ps.colormap_name, min, max, p0[cd]);
-    //             }
-
-    //             positions.push(p0.into());
-    //             normals.push(normal.into());
-    //             colors.push(color);
-    //         }
-    //     }
-
-    //     let n_faces = ps.u_segments * ps.v_segments;
-    //     let n_triangles = n_faces * 2;
-    //     let n_indices = n_triangles * 3;
-
-    //     let mut indices: Vec<u32> = Vec::with_capacity(n_indices);
-
-    //     let n_vertices_per_row = ps.v_segments + 1;
-
-    //     for i in 0..ps.u_segments {
-    //         for j in 0..ps.v_segments {
-    //             let idx0 = j + i * n_vertices_per_row;
-    //             let idx1 = j + 1 + i * n_vertices_per_row;
-    //             let idx2 = j + 1 + (i + 1) * n_vertices_per_row;
-    //             let idx3 = j + (i + 1) * n_vertices_per_row;
-
-    //             indices.push(idx0 as u32);
-    //             indices.push(idx1 as u32);
-    //             indices.push(idx2 as u32);
-
-    //             indices.push(idx2 as u32);
-    //             indices.push(idx3 as u32);
-    //             indices.push(idx0 as u32);
-    //         }
-    //     }
-    //     let vertices: Vec<Vertex> = positions
-    //         .iter()
-    //         .zip(colors.iter().zip(normals.iter()))
-    //         .map(|(position, (color, normal))| Vertex {
-    //             position: [position[0], position[1], position[2], 1.0],
-    //             normal: [normal[0], normal[1], normal[2], 1.0],
-    //             color: [color[0], color[1], color[2], 1.0],
-    //         })
-    //         .collect();
-    //     Mesh { vertices, indices }
-    // }
+    // a grid of (u_segments + 1) x (v_segments + 1) shared vertices, two
+    // triangles per cell. Normals come from `normal` when it's set and from
+    // central differences otherwise, uvs are u and v scaled to [0, 1]
+    pub fn build(&self) -> Mesh {
+        let du = (self.umax - self.umin) / self.u_segments as f32;
+        let dv = (self.vmax - self.vmin) / self.v_segments as f32;
+        let cd = self.colormap_axis();
+        let (min, max) = parametric_surface_range(
+            &self.f,
+            self.umin,
+            self.umax,
+            self.vmin,
+            self.vmax,
+            self.u_segments,
+            self.v_segments,
+            self.scale,
+            self.aspect,
+            self.params,
+            cd,
+        );
+
+        let mut vertices = Vec::with_capacity((self.u_segments + 1) * (self.v_segments + 1));
+        for i in 0..=self.u_segments {
+            let u = self.umin + i as f32 * du;
+            for j in 0..=self.v_segments {
+                let v = self.vmin + j as f32 * dv;
+                let p = self.point(u, v);
+                let normal = self.normal_at(u, v, du, dv);
+                let color = match self.use_colormap && max > min {
+                    true => self.colormap.interp(min, max, p[cd]),
+                    false => self.color,
+                };
+                vertices.push(Vertex {
+                    position: [p[0], p[1], p[2], 1.0],
+                    normal: [normal[0], normal[1], normal[2], 1.0],
+                    color: [color[0], color[1], color[2], 1.0],
+                    tex_coords: [
+                        i as f32 / self.u_segments as f32,
+                        j as f32 / self.v_segments as f32,
+                    ],
+                });
+            }
+        }
+
+        let mut indices = Vec::with_capacity(self.u_segments * self.v_segments * 6);
+        let row = self.v_segments + 1;
+        for i in 0..self.u_segments {
+            for j in 0..self.v_segments {
+                let idx0 = (j + i * row) as u32;
+                let idx1 = (j + 1 + i * row) as u32;
+                let idx2 = (j + 1 + (i + 1) * row) as u32;
+                let idx3 = (j + (i + 1) * row) as u32;
+                indices.extend([idx0, idx1, idx2, idx2, idx3, idx0]);
+            }
+        }
+        Mesh { vertices, indices }
+    }
+
+    // the box around the surface on the same grid as build, with the values of
+    // f at its corners for plot axes
+    pub fn plot_box(&self) -> PlotBox {
+        let du = (self.umax - self.umin) / self.u_segments as f32;
+        let dv = (self.vmax - self.vmin) / self.v_segments as f32;
+        let mut plot = PlotBox {
+            min: [f32::MAX; 3],
+            max: [f32::MIN; 3],
+            data_min: [f32::MAX; 3],
+            data_max: [f32::MIN; 3],
+        };
+        for i in 0..=self.u_segments {
+            for j in 0..=self.v_segments {
+                let (u, v) = (self.umin + i as f32 * du, self.vmin + j as f32 * dv);
+                let p = self.point(u, v);
+                let data = self.f.data_point(self.f.eval(u, v, self.params));
+                for axis in 0..3 {
+                    plot.min[axis] = plot.min[axis].min(p[axis]);
+                    plot.max[axis] = plot.max[axis].max(p[axis]);
+                    plot.data_min[axis] = plot.data_min[axis].min(data[axis]);
+                    plot.data_max[axis] = plot.data_max[axis].max(data[axis]);
+                }
+            }
+        }
+        plot
+    }
+
+    // the axis colormap_direction names, y unless it's "x" or "z"
+    pub fn colormap_axis(&self) -> usize {
+        match self.colormap_direction {
+            "x" => 0,
+            "z" => 2,
+            _ => 1,
+        }
+    }
+
+    // f scaled by scale and aspect
+    pub fn point(&self, u: f32, v: f32) -> Vector3<f32> {
+        let p = self.f.eval(u, v, self.params);
+        Vector3::new(
+            self.scale * p[0],
+            self.scale * self.aspect * p[1],
+            self.scale * p[2],
+        )
+    }
+
+    fn normal_at(&self, u: f32, v: f32, du: f32, dv: f32) -> Vector3<f32> {
+        if let Some(normal) = self.normal {
+            // stretching y by aspect squashes the normal's y the other way
+            let n = normal(u, v, self.params);
+            let n = Vector3::new(n[0], n[1] / self.aspect, n[2]);
+            if n.magnitude2() > 0.0 {
+                return n.normalize();
+            }
+        }
+        // a step much smaller than a cell but well above f32 rounding
+        let (hu, hv) = (du * 0.01, dv * 0.01);
+        let tu = self.point(u + hu, v) - self.point(u - hu, v);
+        let tv = self.point(u, v + hv) - self.point(u, v - hv);
+        let n = tu.cross(tv);
+        if n.magnitude2() > f32::EPSILON * tu.magnitude2() * tv.magnitude2() {
+            return n.normalize();
+        }
+        // poles and other points where a tangent vanishes take the normal of a
+        // point just inside the cell next to them
+        let (u1, v1) = (u + 0.5 * du, v + 0.5 * dv);
+        let tu = self.point(u1 + hu, v1) - self.point(u1 - hu, v1);
+        let tv = self.point(u1, v1 + hv) - self.point(u1, v1 - hv);
+        let n = tu.cross(tv);
+        match n.magnitude2() > 0.0 {
+            true => n.normalize(),
+            false => Vector3::unit_y(),
+        }
+    }
 }
 
 fn parametric_surface_range(
-    f: fn(f32, f32, [f32; 5]) -> [f32; 3],
+    f: &SurfaceFunction,
     umin: f32,
     umax: f32,
     vmin: f32,
@@ -203,7 +248,7 @@ fn parametric_surface_range(
         let u = umin + i as f32 * du;
         for j in 0..=nv {
             let v = vmin + j as f32 * dv;
-            let mut pt = f(u, v, params);
+            let mut pt = f.eval(u, v, params);
             pt = [pt[0] * scale, scale * aspect * pt[1], scale * pt[2]];
             min = if pt[dir] < min { pt[dir] } else { min };
             max = if pt[dir] > max { pt[dir] } else { max };
@@ -217,7 +262,7 @@ pub fn simple_surface_colors(
     nx: usize,
     nz: usize,
     yrange: [f32; 2],
-    colormap_name: &str,
+    colormap: &Colormap,
 ) -> Vec<[f32; 3]> {
     let mut colors: Vec<[f32; 3]> = Vec::with_capacity((4 * (nx - 1) * (nz - 1)) as usize);
     for i in 0..nx - 1 {
@@ -227,10 +272,10 @@ pub fn simple_surface_colors(
             let p2 = pts[i + 1][j + 1];
             let p3 = pts[i + 1][j];
 
-            let c0 = colormap::color_interp(colormap_name, yrange[0], yrange[1], p0[1]);
-            let c1 = colormap::color_interp(colormap_name, yrange[0], yrange[1], p1[1]);
-            let c2 = colormap::color_interp(colormap_name, yrange[0], yrange[1], p2[1]);
-            let c3 = colormap::color_interp(colormap_name, yrange[0], yrange[1], p3[1]);
+            let c0 = colormap::color_interp(colormap, yrange[0], yrange[1], p0[1]);
+            let c1 = colormap::color_interp(colormap, yrange[0], yrange[1], p1[1]);
+            let c2 = colormap::color_interp(colormap, yrange[0], yrange[1], p2[1]);
+            let c3 = colormap::color_interp(colormap, yrange[0], yrange[1], p3[1]);
 
             colors.push(c0);
             colors.push(c1);
@@ -300,8 +345,6 @@ pub fn simple_surface_points(
 ) -> (Vec<Vec<[f32; 3]>>, [f32; 2]) {
     let dx = (xmax - xmin) / (nx as f32 - 1.0);
     let dz = (zmax - zmin) / (nz as f32 - 1.0);
-    let mut ymin: f32 = 0.0;
-    let mut ymax: f32 = 0.0;
 
     let mut pts: Vec<Vec<[f32; 3]>> = vec![vec![Default::default(); nz]; nx];
     for i in 0..nx {
@@ -309,21 +352,35 @@ pub fn simple_surface_points(
         let mut pt1: Vec<[f32; 3]> = Vec::with_capacity(nz);
         for j in 0..nz {
             let z = zmin + j as f32 * dz;
-            let pt = f(x, z);
-            pt1.push(pt);
-            ymin = if pt[1] < ymin { pt[1] } else { ymin };
-            ymax = if pt[1] > ymax { pt[1] } else { ymax };
+            pt1.push(f(x, z));
         }
         pts[i] = pt1;
     }
+    normalize_surface_points(pts, [xmin, xmax], [zmin, zmax], scale, aspect)
+}
+
+// fits points y = f(x, z) into -scale..scale on every axis, heights squashed by
+// aspect, and returns them with the colormap range of their heights. The
+// height range always includes 0
+pub fn normalize_surface_points(
+    mut pts: Vec<Vec<[f32; 3]>>,
+    [xmin, xmax]: [f32; 2],
+    [zmin, zmax]: [f32; 2],
+    scale: f32,
+    aspect: f32,
+) -> (Vec<Vec<[f32; 3]>>, [f32; 2]) {
+    let mut ymin: f32 = 0.0;
+    let mut ymax: f32 = 0.0;
+    for pt in pts.iter().flatten() {
+        ymin = ymin.min(pt[1]);
+        ymax = ymax.max(pt[1]);
+    }
 
     let ymin1 = ymin - (1.0 - aspect) * (ymax - ymin);
     let ymax1 = ymax + (1.0 - aspect) * (ymax - ymin);
 
-    for i in 0..nx {
-        for j in 0..nz {
-            pts[i][j] = normalize_point(pts[i][j], xmin, xmax, ymin1, ymax1, zmin, zmax, scale);
-        }
+    for pt in pts.iter_mut().flatten() {
+        *pt = normalize_point(*pt, xmin, xmax, ymin1, ymax1, zmin, zmax, scale);
     }
 
     let cmin = normalize_point(
@@ -350,6 +407,29 @@ pub fn simple_surface_points(
     return (pts, [cmin, cmax]);
 }
 
+// the box normalize_surface_points fits points into, with the data values at
+// its corners: x and z over their ranges and y over the heights of the points
+pub fn normalized_box(
+    [xmin, xmax]: [f32; 2],
+    [ymin, ymax]: [f32; 2],
+    [zmin, zmax]: [f32; 2],
+    scale: f32,
+    aspect: f32,
+) -> PlotBox {
+    let (ymin, ymax) = (ymin.min(0.0), ymax.max(0.0));
+    let ymin1 = ymin - (1.0 - aspect) * (ymax - ymin);
+    let ymax1 = ymax + (1.0 - aspect) * (ymax - ymin);
+    let corner = |y: f32, x: f32, z: f32| {
+        normalize_point([x, y, z], xmin, xmax, ymin1, ymax1, zmin, zmax, scale)
+    };
+    PlotBox {
+        min: corner(ymin, xmin, zmin),
+        max: corner(ymax, xmax, zmax),
+        data_min: [xmin, ymin, zmin],
+        data_max: [xmax, ymax, zmax],
+    }
+}
+
 fn normalize_point(
     pt: [f32; 3],
     xmin: f32,
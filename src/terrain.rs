@@ -0,0 +1,352 @@
+#![allow(dead_code)]
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    biome::Biome,
+    block::Block,
+    caves::CaveCarver,
+    decoration,
+    hydrology::{Hydrology, Lake},
+    ores::{self, Ore},
+    structures::Structures,
+    world::{Chunk, ChunkPos, World, CHUNK_SIZE, WORLD_HEIGHT},
+};
+
+pub const SEA_LEVEL: i32 = 62;
+// bedrock thins out from a full floor at y 0 to nothing at this height, and
+// caves never reach below it
+pub const BEDROCK_HEIGHT: i32 = 5;
+const BEDROCK_SALT: u64 = 0x0062_6564_726f_636b;
+// columns of a biome this far above sea level are bare rock
+const BARE_ROCK_HEIGHT: i32 = SEA_LEVEL + 40;
+// dirt or sand between the surface and stone
+const SUBSURFACE_DEPTH: i32 = 3;
+// shores this close to sea level and this close to the ocean are sand
+const BEACH_HEIGHT: i32 = 2;
+const BEACH_CONTINENTALNESS: f64 = -0.1;
+// biomes are sampled on a coarse grid and averaged over this many blocks
+// around each column so heights don't step at borders
+const BLEND_STEP: i32 = 4;
+const BLEND_RADIUS: i32 = 8;
+
+// a random generator for one chunk, always the same for a seed, chunk and salt.
+// Each kind of feature has its own salt so adding one doesn't shift the others
+pub fn chunk_rng(seed: u64, pos: ChunkPos, salt: u64) -> StdRng {
+    let mut h = seed ^ salt.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    for v in pos {
+        h = (h ^ v as u32 as u64).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h ^= h >> 31;
+    }
+    StdRng::seed_from_u64(h)
+}
+
+// generates chunks from noise, the same seed always gives the same terrain
+pub struct TerrainGenerator {
+    seed: u64,
+    height: Fbm<Perlin>,
+    temperature: Fbm<Perlin>,
+    humidity: Fbm<Perlin>,
+    // how far inland a column is, low values are ocean and high ones mountains
+    continentalness: Fbm<Perlin>,
+    hydrology: Hydrology,
+    caves: CaveCarver,
+    // veins placed into the stone of every chunk, in this order
+    pub ores: Vec<Ore>,
+    pub structures: Structures,
+}
+
+// the ground and water of one column of a chunk
+#[derive(Copy, Clone, Debug)]
+pub struct Column {
+    pub height: i32,
+    // water fills the column from above the ground up to here
+    pub water_level: i32,
+}
+
+impl Column {
+    pub fn is_underwater(&self) -> bool {
+        self.height < self.water_level
+    }
+}
+
+// what the blended biomes around a column say its terrain should look like
+#[derive(Copy, Clone, Default)]
+struct Shape {
+    base_height: f32,
+    variation: f32,
+}
+
+impl Shape {
+    // the lowest the hills of the shape go, where its rivers run
+    fn valley_floor(self) -> f32 {
+        SEA_LEVEL as f32 + self.base_height - self.variation
+    }
+}
+
+// bilinear interpolation between the shapes at four corners, the ones at
+// the smaller z first
+fn interpolate([a, b, c, d]: [Shape; 4], tx: f32, tz: f32) -> Shape {
+    let lerp = |a: Shape, b: Shape, t: f32| Shape {
+        base_height: a.base_height + (b.base_height - a.base_height) * t,
+        variation: a.variation + (b.variation - a.variation) * t,
+    };
+    lerp(lerp(a, b, tx), lerp(c, d, tx), tz)
+}
+
+impl TerrainGenerator {
+    pub fn new(seed: u64) -> Self {
+        // each map gets its own seed so they don't line up
+        let noise = |offset: u32, frequency: f64, octaves: usize| {
+            Fbm::<Perlin>::new((seed as u32).wrapping_add(offset))
+                .set_frequency(frequency)
+                .set_octaves(octaves)
+        };
+        Self {
+            seed,
+            height: noise(0, 1.0 / 128.0, 5),
+            temperature: noise(1, 1.0 / 1024.0, 3),
+            humidity: noise(2, 1.0 / 1024.0, 3),
+            continentalness: noise(3, 1.0 / 768.0, 4),
+            hydrology: Hydrology::new(seed),
+            caves: CaveCarver::new(seed),
+            ores: Ore::defaults(),
+            structures: Structures::defaults(),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // temperature and humidity in about [-1, 1]
+    pub fn climate(&self, x: i32, z: i32) -> (f64, f64) {
+        let point = [x as f64, z as f64];
+        (self.temperature.get(point), self.humidity.get(point))
+    }
+
+    pub fn continentalness(&self, x: i32, z: i32) -> f64 {
+        self.continentalness.get([x as f64, z as f64])
+    }
+
+    pub fn biome_at(&self, x: i32, z: i32) -> Biome {
+        let continentalness = self.continentalness(x, z);
+        if continentalness < -0.2 {
+            Biome::Ocean
+        } else if continentalness > 0.45 {
+            Biome::Mountains
+        } else {
+            let (temperature, humidity) = self.climate(x, z);
+            Biome::from_climate(temperature, humidity)
+        }
+    }
+
+    // the average shape of the biomes within BLEND_RADIUS of a point
+    fn blended_shape(&self, x: i32, z: i32) -> Shape {
+        let mut shape = Shape::default();
+        let mut samples = 0.0;
+        for dz in (-BLEND_RADIUS..=BLEND_RADIUS).step_by(BLEND_STEP as usize) {
+            for dx in (-BLEND_RADIUS..=BLEND_RADIUS).step_by(BLEND_STEP as usize) {
+                let biome = self.biome_at(x + dx, z + dz);
+                shape.base_height += biome.base_height();
+                shape.variation += biome.height_variation();
+                samples += 1.0;
+            }
+        }
+        shape.base_height /= samples;
+        shape.variation /= samples;
+        shape
+    }
+
+    // the blended shapes of the grid corners around a point, interpolated the
+    // same way as in columns so single columns come out the same
+    fn shape_at(&self, x: i32, z: i32) -> Shape {
+        let (gx, gz) = (x.div_euclid(BLEND_STEP), z.div_euclid(BLEND_STEP));
+        let corner =
+            |dx: i32, dz: i32| self.blended_shape((gx + dx) * BLEND_STEP, (gz + dz) * BLEND_STEP);
+        let t = |v: i32| v.rem_euclid(BLEND_STEP) as f32 / BLEND_STEP as f32;
+        interpolate(
+            [corner(0, 0), corner(1, 0), corner(0, 1), corner(1, 1)],
+            t(x),
+            t(z),
+        )
+    }
+
+    // height of the land before lakes are dug, including river valleys
+    fn land_height(&self, x: i32, z: i32, shape: Shape) -> f32 {
+        let detail = self.height.get([x as f64, z as f64]) as f32;
+        let height = SEA_LEVEL as f32 + shape.base_height + detail * shape.variation;
+        self.hydrology
+            .carve_river(x, z, height, shape.valley_floor())
+    }
+
+    // the shape of every column in a rectangle from min to max, in z, x order.
+    // Blended shapes on the grid corners around it, interpolated in between
+    fn shapes(&self, min: [i32; 2], max: [i32; 2]) -> Vec<Shape> {
+        let first = min.map(|v| v.div_euclid(BLEND_STEP));
+        let corners =
+            [0, 1].map(|axis| (max[axis].div_euclid(BLEND_STEP) - first[axis] + 2) as usize);
+        let mut grid = Vec::with_capacity(corners[0] * corners[1]);
+        for gz in 0..corners[1] as i32 {
+            for gx in 0..corners[0] as i32 {
+                grid.push(
+                    self.blended_shape((first[0] + gx) * BLEND_STEP, (first[1] + gz) * BLEND_STEP),
+                );
+            }
+        }
+
+        let mut shapes = Vec::new();
+        for z in min[1]..=max[1] {
+            for x in min[0]..=max[0] {
+                let gx = (x.div_euclid(BLEND_STEP) - first[0]) as usize;
+                let gz = (z.div_euclid(BLEND_STEP) - first[1]) as usize;
+                let corner = |dx: usize, dz: usize| grid[(gz + dz) * corners[0] + gx + dx];
+                let t = |v: i32| v.rem_euclid(BLEND_STEP) as f32 / BLEND_STEP as f32;
+                shapes.push(interpolate(
+                    [corner(0, 0), corner(1, 0), corner(0, 1), corner(1, 1)],
+                    t(x),
+                    t(z),
+                ));
+            }
+        }
+        shapes
+    }
+
+    // ground and water of every column in a chunk, in z, x order
+    fn columns(&self, origin: [i32; 2]) -> Vec<Column> {
+        let size = CHUNK_SIZE as i32;
+        let max = [origin[0] + size - 1, origin[1] + size - 1];
+        let lakes = self.lakes_near(origin, max);
+        let shapes = self.shapes(origin, max);
+        let mut columns = Vec::with_capacity(CHUNK_SIZE * CHUNK_SIZE);
+        for z in 0..size {
+            for x in 0..size {
+                let shape = shapes[(z * size + x) as usize];
+                columns.push(self.column(origin[0] + x, origin[1] + z, shape, &lakes));
+            }
+        }
+        columns
+    }
+
+    // the ground as a density field over a box of blocks from origin, x
+    // changing fastest and z slowest: below 0 in the ground and above 0 over
+    // it, crossing 0 where the ground height is before it's rounded to
+    // blocks, and clamped to [-1, 1]. Caves and structures aren't in it
+    pub fn density(&self, origin: [i32; 3], size: [usize; 3]) -> Vec<f32> {
+        let min = [origin[0], origin[2]];
+        let max = [min[0] + size[0] as i32 - 1, min[1] + size[2] as i32 - 1];
+        let lakes = self.lakes_near(min, max);
+        let heights: Vec<f32> = self
+            .shapes(min, max)
+            .into_iter()
+            .enumerate()
+            .map(|(i, shape)| {
+                let (x, z) = (min[0] + (i % size[0]) as i32, min[1] + (i / size[0]) as i32);
+                let height = lakes
+                    .iter()
+                    .filter_map(|lake| lake.bed(x, z))
+                    .fold(self.land_height(x, z, shape), f32::min);
+                height.clamp(1.0, WORLD_HEIGHT as f32 - 1.0)
+            })
+            .collect();
+
+        let mut values = Vec::with_capacity(size.iter().product());
+        for z in 0..size[2] {
+            for y in 0..size[1] {
+                for x in 0..size[0] {
+                    // a block is ground up to its height rounded, so halfway
+                    // between the top block and the air over it for whole heights
+                    let y = (origin[1] + y as i32) as f32;
+                    values.push((y - heights[z * size[0] + x] - 0.5).clamp(-1.0, 1.0));
+                }
+            }
+        }
+        values
+    }
+
+    fn lakes_near(&self, min: [i32; 2], max: [i32; 2]) -> Vec<Lake> {
+        self.hydrology.lakes_near(min, max, &|x, z| {
+            self.land_height(x, z, self.blended_shape(x, z))
+        })
+    }
+
+    fn column(&self, x: i32, z: i32, shape: Shape, lakes: &[Lake]) -> Column {
+        let height = self.land_height(x, z, shape).round() as i32;
+        let mut column = Column {
+            height: height.clamp(1, WORLD_HEIGHT as i32 - 1),
+            water_level: SEA_LEVEL,
+        };
+        self.hydrology
+            .apply_river(x, z, shape.valley_floor(), &mut column);
+        Hydrology::apply_lakes(lakes, x, z, &mut column);
+        column
+    }
+
+    // the ground and water of a single column, the same as in its generated chunk
+    pub fn column_at(&self, x: i32, z: i32) -> Column {
+        let lakes = self.lakes_near([x, z], [x, z]);
+        self.column(x, z, self.shape_at(x, z), &lakes)
+    }
+
+    pub fn generate_chunk(&self, pos: ChunkPos) -> Chunk {
+        let origin = [pos[0] * CHUNK_SIZE as i32, pos[1] * CHUNK_SIZE as i32];
+        let columns = self.columns(origin);
+        let mut biomes = Vec::with_capacity(CHUNK_SIZE * CHUNK_SIZE);
+        let mut chunk = Chunk::new();
+        let mut rng = chunk_rng(self.seed, pos, BEDROCK_SALT);
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let (world_x, world_z) = (origin[0] + x as i32, origin[1] + z as i32);
+                let biome = self.biome_at(world_x, world_z);
+                biomes.push(biome);
+                let column = columns[z * CHUNK_SIZE + x];
+                let height = column.height;
+                let beach = (height - SEA_LEVEL).abs() <= BEACH_HEIGHT
+                    && column.water_level == SEA_LEVEL
+                    && self.continentalness(world_x, world_z) < BEACH_CONTINENTALNESS;
+
+                let (surface, subsurface) = if height > BARE_ROCK_HEIGHT {
+                    (Block::Stone, Block::Stone)
+                } else if column.is_underwater() || beach {
+                    // sea, river and lake beds are sandy whatever the biome
+                    (Block::Sand, Block::Sand)
+                } else {
+                    (biome.surface(), biome.subsurface())
+                };
+                for y in 0..=height.max(column.water_level) {
+                    let block = if y < BEDROCK_HEIGHT
+                        && rng.gen_ratio((BEDROCK_HEIGHT - y) as u32, BEDROCK_HEIGHT as u32)
+                    {
+                        Block::Bedrock
+                    } else if y == height {
+                        surface
+                    } else if y < height - SUBSURFACE_DEPTH {
+                        Block::Stone
+                    } else if y < height {
+                        subsurface
+                    } else if y == column.water_level && biome.is_frozen() {
+                        Block::Ice
+                    } else {
+                        Block::Water
+                    };
+                    chunk.set(x, y as usize, z, block);
+                }
+            }
+        }
+        // ores before caves, so caves cut through veins and expose them
+        ores::place_ores(&mut chunk, self.seed, pos, &self.ores);
+        self.caves.carve_chunk(&mut chunk, pos, &columns);
+        // structures last, so caves don't cut through floors and walls
+        self.structures.place(&mut chunk, pos, self, &columns);
+        chunk.set_biomes(Some(biomes));
+        chunk.compact();
+        chunk
+    }
+
+    // generates a chunk into the world and decorates it, trees from it reaching
+    // into chunks that don't exist yet are finished when those are generated
+    pub fn generate(&self, world: &mut World, pos: ChunkPos) {
+        world.insert_chunk(pos, self.generate_chunk(pos));
+        decoration::decorate(world, pos, self.seed);
+    }
+}
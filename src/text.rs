@@ -0,0 +1,99 @@
+#![allow(dead_code)]
+use image::{Rgba, RgbaImage};
+
+// a 5 x 7 bitmap font for numbers and axis names, each glyph pixel drawn as a
+// GLYPH_SCALE square with a dark outline so text reads on any background
+const GLYPH_SIZE: [u32; 2] = [5, 7];
+const GLYPH_SCALE: u32 = 2;
+// pixels between glyphs, and around a text for its outline
+const GLYPH_SPACING: u32 = 2;
+const OUTLINE: u32 = 1;
+const TEXT_COLOR: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const OUTLINE_COLOR: Rgba<u8> = Rgba([20, 20, 20, 255]);
+
+// rows from the top, the high bit of the five on the left. Characters that
+// aren't here are drawn as spaces
+#[rustfmt::skip]
+const GLYPHS: [(char, [u8; 7]); 17] = [
+    ('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+    ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+    ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+    ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+    ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+    ('-', [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000]),
+    ('+', [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000]),
+    ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100]),
+    ('e', [0b00000, 0b00000, 0b01110, 0b10001, 0b11111, 0b10000, 0b01110]),
+    ('x', [0b00000, 0b00000, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001]),
+    ('y', [0b00000, 0b00000, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110]),
+    ('z', [0b00000, 0b00000, 0b11111, 0b00010, 0b00100, 0b01000, 0b11111]),
+];
+
+fn glyph(c: char) -> [u8; 7] {
+    GLYPHS
+        .iter()
+        .find(|(g, _)| *g == c)
+        .map_or([0; 7], |(_, rows)| *rows)
+}
+
+// width and height in pixels of a line of text, outline included
+pub fn text_size(text: &str) -> [u32; 2] {
+    let count = text.chars().count() as u32;
+    let [w, h] = GLYPH_SIZE.map(|n| n * GLYPH_SCALE);
+    [
+        count * w + count.saturating_sub(1) * GLYPH_SPACING + 2 * OUTLINE,
+        h + 2 * OUTLINE,
+    ]
+}
+
+// draws text with its top left corner at [left, top]
+pub fn draw_text(image: &mut RgbaImage, text: &str, [left, top]: [u32; 2]) {
+    let advance = GLYPH_SIZE[0] * GLYPH_SCALE + GLYPH_SPACING;
+    let mut pixels = Vec::new();
+    for (i, c) in text.chars().enumerate() {
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_SIZE[0] {
+                if bits & (1 << (GLYPH_SIZE[0] - 1 - col)) != 0 {
+                    pixels.push([
+                        left + OUTLINE + i as u32 * advance + col * GLYPH_SCALE,
+                        top + OUTLINE + row as u32 * GLYPH_SCALE,
+                    ]);
+                }
+            }
+        }
+    }
+    // every outline first, so it never covers a neighbouring glyph
+    for (color, grow) in [(OUTLINE_COLOR, OUTLINE), (TEXT_COLOR, 0)] {
+        for &[x, y] in &pixels {
+            for py in y - grow..y + GLYPH_SCALE + grow {
+                for px in x - grow..x + GLYPH_SCALE + grow {
+                    if px < image.width() && py < image.height() {
+                        image.put_pixel(px, py, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// every text on its own row of one image, and where each one is in it as
+// [left, top, width, height]
+pub fn text_atlas(texts: &[&str]) -> (RgbaImage, Vec<[u32; 4]>) {
+    let sizes: Vec<[u32; 2]> = texts.iter().map(|text| text_size(text)).collect();
+    let width = sizes.iter().map(|s| s[0]).max().unwrap_or(0).max(1);
+    let height = sizes.iter().map(|s| s[1]).sum::<u32>().max(1);
+    let mut image = RgbaImage::new(width, height);
+    let mut rects = Vec::with_capacity(texts.len());
+    let mut top = 0;
+    for (text, [w, h]) in texts.iter().zip(sizes) {
+        draw_text(&mut image, text, [0, top]);
+        rects.push([0, top, w, h]);
+        top += h;
+    }
+    (image, rects)
+}
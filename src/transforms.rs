@@ -1,4 +1,5 @@
 #![allow(dead_code)]
+use anyhow::{anyhow, Result};
 use cgmath::*;
 use std::f32::consts::PI;
 use winit::window::Window;
@@ -9,21 +10,24 @@ use crate::texture;
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
     0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.5,
-    0.0, 0.0, 0.0, 1.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
 );
 
 pub struct InitWgpu {
     pub instance: wgpu::Instance,
-    pub surface: wgpu::Surface,
+    // None when rendering offscreen
+    pub surface: Option<wgpu::Surface>,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
+    // MSAA samples per pixel, 1 when multisampling is off
+    pub sample_count: u32,
 }
 
 impl InitWgpu {
-    pub async fn init_wgpu(window: &Window) -> Self {
+    pub async fn init_wgpu(window: &Window, sample_count: u32) -> Self {
         let size = window.inner_size();
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
             backends: wgpu::Backends::all(),
@@ -63,16 +67,88 @@ impl InitWgpu {
             view_formats: vec![],
         };
         surface.configure(&device, &config);
+        let sample_count = supported_sample_count(&adapter, format, sample_count);
 
         Self {
             instance,
-            surface,
+            surface: Some(surface),
             device,
             queue,
             config,
             size,
+            sample_count,
         }
     }
+
+    // no window or surface, frames are rendered into textures and read back.
+    // Falls back to a software adapter when there is no GPU, e.g. on CI
+    pub async fn init_headless(width: u32, height: u32, sample_count: u32) -> Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+        let mut adapter = None;
+        for force_fallback_adapter in [false, true] {
+            adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::default(),
+                    force_fallback_adapter,
+                    compatible_surface: None,
+                })
+                .await;
+            if adapter.is_some() {
+                break;
+            }
+        }
+        let adapter = adapter.ok_or_else(|| anyhow!("no graphics adapter available"))?;
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features: wgpu::Features::empty(),
+                    limits: wgpu::Limits::default(),
+                },
+                None, // Trace path
+            )
+            .await?;
+
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+        };
+        let sample_count = supported_sample_count(&adapter, format, sample_count);
+
+        Ok(Self {
+            instance,
+            surface: None,
+            device,
+            queue,
+            config,
+            size: winit::dpi::PhysicalSize::new(width, height),
+            sample_count,
+        })
+    }
+}
+
+// sample_count is a request, it falls back to 1 if the adapter can't multisample the format
+fn supported_sample_count(
+    adapter: &wgpu::Adapter,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> u32 {
+    let format_flags = adapter.get_texture_format_features(format).flags;
+    if format_flags.sample_count_supported(sample_count) {
+        sample_count
+    } else {
+        1
+    }
 }
 
 pub fn create_view(
@@ -0,0 +1,355 @@
+#![allow(dead_code)]
+use anyhow::{bail, Result};
+use cgmath::InnerSpace;
+use std::collections::{HashSet, VecDeque};
+
+use crate::{
+    block::Block,
+    sd::{colormap::Colormap, ParametricSurface, SurfaceFunction},
+    world::World,
+};
+
+// samples closer together than this many blocks hit every block the surface
+// passes through, so the shell has no holes
+const SAMPLE_SPACING: f32 = 0.35;
+// at most this many samples along u or v, however large the scale
+const MAX_SAMPLES: usize = 4096;
+// solid fills give up on shapes whose bounding box has more blocks than this
+const MAX_FILL_VOLUME: usize = 16_000_000;
+// height functions are refused with more blocks than this
+const MAX_HEIGHT_BLOCKS: usize = MAX_FILL_VOLUME;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Fill {
+    // only the blocks the surface passes through
+    Shell,
+    // the shell and everything it encloses. Open surfaces enclose nothing and
+    // stay a shell
+    Solid,
+}
+
+// y = f(x, z) over a rectangle, like math_func::peaks and math_func::sinc.
+// f takes x and z in place of u and v and gives [x, y, z]
+pub struct HeightFunction {
+    pub f: SurfaceFunction,
+    pub xmin: f32,
+    pub xmax: f32,
+    pub zmin: f32,
+    pub zmax: f32,
+    // stretches the heights, so flat functions don't end up a few blocks tall
+    pub aspect: f32,
+    pub colormap: Colormap,
+    pub params: [f32; 5],
+}
+
+// turns math shapes into blocks, coloured by matching colormap colours to the
+// closest block in the palette
+pub struct Voxelizer {
+    // the block the shape's origin lands on
+    pub origin: [i32; 3],
+    // blocks per unit of the shape
+    pub scale: f32,
+    pub fill: Fill,
+    pub palette: Vec<Block>,
+}
+
+// the full opaque blocks that get their colour from their tint. Grass is left
+// out, its colour comes from its texture
+pub fn default_palette() -> Vec<Block> {
+    Block::ALL
+        .into_iter()
+        .filter(|&block| block.is_opaque() && block != Block::Grass)
+        .collect()
+}
+
+impl Voxelizer {
+    pub fn new(origin: [i32; 3], scale: f32, fill: Fill) -> Self {
+        Self {
+            origin,
+            scale,
+            fill,
+            palette: default_palette(),
+        }
+    }
+
+    // writes the surface into the world and returns how many blocks it set.
+    // The surface is sampled finely enough that neighbouring samples are less
+    // than SAMPLE_SPACING blocks apart
+    pub fn parametric(&self, world: &mut World, surface: &ParametricSurface) -> usize {
+        let (nu, nv) = self.sample_counts(surface);
+        let du = (surface.umax - surface.umin) / nu as f32;
+        let dv = (surface.vmax - surface.vmin) / nv as f32;
+        let mut shell = HashSet::new();
+        for i in 0..=nu {
+            let u = surface.umin + i as f32 * du;
+            for j in 0..=nv {
+                let v = surface.vmin + j as f32 * dv;
+                let p = surface.point(u, v);
+                shell.insert(self.block_at([p.x, p.y, p.z]));
+            }
+        }
+        let voxels = match self.fill {
+            Fill::Shell => shell.into_iter().collect(),
+            Fill::Solid => fill_enclosed(&shell),
+        };
+
+        let axis = surface.colormap_axis();
+        let (min, max) = range(voxels.iter().map(|voxel| voxel[axis]));
+        self.paint(world, &voxels, |voxel| match surface.use_colormap {
+            true => surface
+                .colormap
+                .interp(min as f32, max as f32, voxel[axis] as f32),
+            false => surface.color,
+        })
+    }
+
+    // writes the height function into the world and returns how many blocks it
+    // set. A shell column reaches down to its lowest neighbour so steep slopes
+    // stay closed, a solid one down to the lowest point of the function. An
+    // empty rectangle sets nothing. Fails without setting anything when the
+    // rectangle is more than MAX_SAMPLES blocks along x or z, or the function
+    // comes to more than MAX_HEIGHT_BLOCKS blocks
+    pub fn height(&self, world: &mut World, function: &HeightFunction) -> Result<usize> {
+        let [x0, _, z0] = self.block_at([function.xmin, 0.0, function.zmin]);
+        let [x1, _, z1] = self.block_at([function.xmax, 0.0, function.zmax]);
+        if x1 < x0 || z1 < z0 {
+            return Ok(0);
+        }
+        let size = |min: i32, max: i32| (max as i64 - min as i64 + 1) as usize;
+        let (width, length) = (size(x0, x1), size(z0, z1));
+        if width > MAX_SAMPLES || length > MAX_SAMPLES {
+            bail!(
+                "the height function would be {} by {} blocks, at most {} along each side fit",
+                width,
+                length,
+                MAX_SAMPLES
+            );
+        }
+        let mut heights = vec![0; width * length];
+        for k in 0..length {
+            for i in 0..width {
+                let x = (x0 + i as i32 - self.origin[0]) as f32 / self.scale;
+                let z = (z0 + k as i32 - self.origin[2]) as f32 / self.scale;
+                let y = function.f.eval(x, z, function.params)[1] * function.aspect;
+                heights[i + k * width] = self.block_at([0.0, y, 0.0])[1];
+            }
+        }
+        let (lowest, highest) = range(heights.iter().copied());
+
+        let mut columns = Vec::with_capacity(width * length);
+        for k in 0..length {
+            for i in 0..width {
+                let top = heights[i + k * width];
+                let bottom = match self.fill {
+                    Fill::Solid => lowest,
+                    Fill::Shell => [[-1, 0], [1, 0], [0, -1], [0, 1]]
+                        .into_iter()
+                        .filter_map(|[di, dk]| {
+                            let (ni, nk) = (i as i32 + di, k as i32 + dk);
+                            let inside =
+                                (0..width as i32).contains(&ni) && (0..length as i32).contains(&nk);
+                            inside.then(|| heights[ni as usize + nk as usize * width] + 1)
+                        })
+                        .fold(top, i32::min),
+                };
+                columns.push((bottom, top));
+            }
+        }
+        let count: usize = columns
+            .iter()
+            .map(|&(bottom, top)| (top as i64 - bottom as i64 + 1) as usize)
+            .sum();
+        if count > MAX_HEIGHT_BLOCKS {
+            bail!(
+                "the height function would set {} blocks, at most {} can be voxelized",
+                count,
+                MAX_HEIGHT_BLOCKS
+            );
+        }
+
+        let mut voxels = Vec::with_capacity(count);
+        for (column, &(bottom, top)) in columns.iter().enumerate() {
+            let (i, k) = (column % width, column / width);
+            for y in bottom..=top {
+                voxels.push([x0 + i as i32, y, z0 + k as i32]);
+            }
+        }
+        Ok(self.paint(world, &voxels, |voxel| {
+            function
+                .colormap
+                .interp(lowest as f32, highest as f32, voxel[1] as f32)
+        }))
+    }
+
+    fn block_at(&self, p: [f32; 3]) -> [i32; 3] {
+        let mut block = [0; 3];
+        for axis in 0..3 {
+            block[axis] = self.origin[axis] + (p[axis] * self.scale).round() as i32;
+        }
+        block
+    }
+
+    // samples along u and v, enough that the largest step between two samples
+    // of the surface's own grid is split into pieces under SAMPLE_SPACING
+    fn sample_counts(&self, surface: &ParametricSurface) -> (usize, usize) {
+        let (nu, nv) = (surface.u_segments.max(1), surface.v_segments.max(1));
+        let du = (surface.umax - surface.umin) / nu as f32;
+        let dv = (surface.vmax - surface.vmin) / nv as f32;
+        let (mut step_u, mut step_v) = (0.0f32, 0.0f32);
+        for i in 0..=nu {
+            let u = surface.umin + i as f32 * du;
+            for j in 0..=nv {
+                let v = surface.vmin + j as f32 * dv;
+                let p = surface.point(u, v);
+                step_u = step_u.max((surface.point(u + du, v) - p).magnitude());
+                step_v = step_v.max((surface.point(u, v + dv) - p).magnitude());
+            }
+        }
+        let split = |segments: usize, step: f32| {
+            let pieces = (step * self.scale / SAMPLE_SPACING).ceil().max(1.0) as usize;
+            (segments * pieces).min(MAX_SAMPLES)
+        };
+        (split(nu, step_u), split(nv, step_v))
+    }
+
+    // sets every voxel to the palette block closest to its colour
+    fn paint(
+        &self,
+        world: &mut World,
+        voxels: &[[i32; 3]],
+        color: impl Fn([i32; 3]) -> [f32; 3],
+    ) -> usize {
+        for &voxel in voxels {
+            let [x, y, z] = voxel;
+            world.set_block(x, y, z, nearest_block(&self.palette, color(voxel)));
+        }
+        voxels.len()
+    }
+}
+
+// the palette block whose tint is closest to the colour, stone if the palette
+// is empty
+pub fn nearest_block(palette: &[Block], color: [f32; 3]) -> Block {
+    let distance = |block: &Block| -> f32 {
+        let tint = block.color();
+        (0..3).map(|i| (tint[i] - color[i]).powi(2)).sum()
+    };
+    palette
+        .iter()
+        .copied()
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+        .unwrap_or(Block::Stone)
+}
+
+fn range(values: impl Iterator<Item = i32>) -> (i32, i32) {
+    values.fold((i32::MAX, i32::MIN), |(min, max), value| {
+        (min.min(value), max.max(value))
+    })
+}
+
+// the shell and everything it encloses: the box around the shell is flooded
+// from outside, and whatever the flood doesn't reach is inside
+fn fill_enclosed(shell: &HashSet<[i32; 3]>) -> Vec<[i32; 3]> {
+    let mut min = [i32::MAX; 3];
+    let mut max = [i32::MIN; 3];
+    for voxel in shell {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(voxel[axis] - 1);
+            max[axis] = max[axis].max(voxel[axis] + 1);
+        }
+    }
+    let size = [0, 1, 2].map(|axis| (max[axis] - min[axis] + 1).max(0) as usize);
+    let volume = size[0] * size[1] * size[2];
+    if shell.is_empty() || volume > MAX_FILL_VOLUME {
+        return shell.iter().copied().collect();
+    }
+    let index = |[x, y, z]: [i32; 3]| {
+        (x - min[0]) as usize + size[0] * ((y - min[1]) as usize + size[1] * (z - min[2]) as usize)
+    };
+
+    let mut outside = vec![false; volume];
+    let mut queue = VecDeque::from([min]);
+    outside[index(min)] = true;
+    while let Some([x, y, z]) = queue.pop_front() {
+        for [dx, dy, dz] in [
+            [1, 0, 0],
+            [-1, 0, 0],
+            [0, 1, 0],
+            [0, -1, 0],
+            [0, 0, 1],
+            [0, 0, -1],
+        ] {
+            let next = [x + dx, y + dy, z + dz];
+            if (0..3).any(|axis| next[axis] < min[axis] || next[axis] > max[axis]) {
+                continue;
+            }
+            if outside[index(next)] || shell.contains(&next) {
+                continue;
+            }
+            outside[index(next)] = true;
+            queue.push_back(next);
+        }
+    }
+
+    let mut voxels = Vec::new();
+    for z in min[2]..=max[2] {
+        for y in min[1]..=max[1] {
+            for x in min[0]..=max[0] {
+                if !outside[index([x, y, z])] {
+                    voxels.push([x, y, z]);
+                }
+            }
+        }
+    }
+    voxels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sd::colormap::Colormap;
+
+    // a slope along x, as steep as the first parameter
+    fn slope(x: f32, z: f32, params: [f32; 5]) -> [f32; 3] {
+        [x, x * params[0], z]
+    }
+
+    fn function(size: f32, steepness: f32) -> HeightFunction {
+        HeightFunction {
+            f: SurfaceFunction::Native(slope),
+            xmin: 0.0,
+            xmax: size,
+            zmin: 0.0,
+            zmax: size,
+            aspect: 1.0,
+            colormap: Colormap::builtin("jet"),
+            params: [steepness, 0.0, 0.0, 0.0, 0.0],
+        }
+    }
+
+    #[test]
+    fn height_sets_one_column_per_block() {
+        let voxelizer = Voxelizer::new([0, 10, 0], 1.0, Fill::Shell);
+        let mut world = World::new(0);
+        // flat, so every column is a single block
+        assert_eq!(
+            voxelizer.height(&mut world, &function(9.0, 0.0)).unwrap(),
+            100
+        );
+        assert_ne!(world.get_block(9, 10, 9), Block::Air);
+        assert_eq!(world.get_block(10, 10, 9), Block::Air);
+    }
+
+    #[test]
+    fn height_refuses_huge_functions() {
+        let mut world = World::new(0);
+        let shell = Voxelizer::new([0, 0, 0], 1.0, Fill::Shell);
+        let wide = function(MAX_SAMPLES as f32, 0.0);
+        assert!(shell.height(&mut world, &wide).is_err());
+        // within the sides but too tall once filled down to the lowest point
+        let solid = Voxelizer::new([0, 0, 0], 1.0, Fill::Solid);
+        let steep = function(2000.0, 1000.0);
+        assert!(solid.height(&mut world, &steep).is_err());
+        assert_eq!(world.chunks().count(), 0);
+    }
+}
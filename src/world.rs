@@ -0,0 +1,522 @@
+#![allow(dead_code)]
+use std::collections::HashMap;
+
+use crate::{biome::Biome, block::Block};
+
+// chunks are columns of CHUNK_SIZE x WORLD_HEIGHT x CHUNK_SIZE blocks,
+// split vertically into cubic sections
+pub const CHUNK_SIZE: usize = 16;
+pub const SECTION_COUNT: usize = 16;
+pub const WORLD_HEIGHT: usize = CHUNK_SIZE * SECTION_COUNT;
+pub const SECTION_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+// chunk coordinates, x and z in units of CHUNK_SIZE blocks
+pub type ChunkPos = [i32; 2];
+
+// a cube of blocks stored as a palette of the block types it contains plus one
+// packed palette index per block. Indices use the fewest bits that fit the palette,
+// 0 when there's a single block type, and never straddle two words
+#[derive(Debug, Clone)]
+pub struct Section {
+    palette: Vec<Block>,
+    bits: u32,
+    data: Vec<u64>,
+}
+
+// words needed to pack SECTION_VOLUME indices of the given width
+pub fn packed_len(bits: u32) -> usize {
+    64u32
+        .checked_div(bits)
+        .map_or(0, |per_word| SECTION_VOLUME.div_ceil(per_word as usize))
+}
+
+// smallest index width that can address a palette of this size
+fn bits_for(palette_len: usize) -> u32 {
+    if palette_len <= 1 {
+        0
+    } else {
+        usize::BITS - (palette_len - 1).leading_zeros()
+    }
+}
+
+impl Section {
+    pub fn new() -> Self {
+        Self::filled(Block::Air)
+    }
+
+    pub fn filled(block: Block) -> Self {
+        Self {
+            palette: vec![block],
+            bits: 0,
+            data: Vec::new(),
+        }
+    }
+
+    fn index(x: usize, y: usize, z: usize) -> usize {
+        (y * CHUNK_SIZE + z) * CHUNK_SIZE + x
+    }
+
+    fn palette_index(&self, i: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        let per_word = (64 / self.bits) as usize;
+        let shift = (i % per_word) as u32 * self.bits;
+        ((self.data[i / per_word] >> shift) & ((1 << self.bits) - 1)) as usize
+    }
+
+    fn set_palette_index(&mut self, i: usize, value: usize) {
+        let per_word = (64 / self.bits) as usize;
+        let shift = (i % per_word) as u32 * self.bits;
+        let mask = ((1u64 << self.bits) - 1) << shift;
+        let word = &mut self.data[i / per_word];
+        *word = (*word & !mask) | ((value as u64) << shift);
+    }
+
+    // repacks every index with a new width
+    fn resize(&mut self, bits: u32) {
+        let indices: Vec<usize> = (0..SECTION_VOLUME).map(|i| self.palette_index(i)).collect();
+        self.bits = bits;
+        self.data = vec![0; packed_len(bits)];
+        if bits > 0 {
+            for (i, value) in indices.into_iter().enumerate() {
+                self.set_palette_index(i, value);
+            }
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> Block {
+        self.palette[self.palette_index(Self::index(x, y, z))]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, z: usize, block: Block) {
+        let value = match self.palette.iter().position(|&b| b == block) {
+            Some(value) => value,
+            None => {
+                self.palette.push(block);
+                let bits = bits_for(self.palette.len());
+                if bits != self.bits {
+                    self.resize(bits);
+                }
+                self.palette.len() - 1
+            }
+        };
+        if self.bits > 0 {
+            self.set_palette_index(Self::index(x, y, z), value);
+        }
+    }
+
+    // drops palette entries no block uses any more and shrinks the indices to match
+    pub fn compact(&mut self) {
+        let mut used = vec![false; self.palette.len()];
+        for i in 0..SECTION_VOLUME {
+            used[self.palette_index(i)] = true;
+        }
+        if used.iter().all(|&u| u) {
+            return;
+        }
+        let indices: Vec<usize> = (0..SECTION_VOLUME).map(|i| self.palette_index(i)).collect();
+        let mut remap = vec![0; self.palette.len()];
+        let mut palette = Vec::new();
+        for (old, &block) in self.palette.iter().enumerate() {
+            if used[old] {
+                remap[old] = palette.len();
+                palette.push(block);
+            }
+        }
+        self.palette = palette;
+        self.bits = bits_for(self.palette.len());
+        self.data = vec![0; packed_len(self.bits)];
+        if self.bits > 0 {
+            for (i, old) in indices.into_iter().enumerate() {
+                self.set_palette_index(i, remap[old]);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self.palette.as_slice() {
+            [block] => *block == Block::Air,
+            _ => (0..SECTION_VOLUME).all(|i| self.palette[self.palette_index(i)] == Block::Air),
+        }
+    }
+
+    pub fn palette(&self) -> &[Block] {
+        &self.palette
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    // packed indices, blocks in y, z, x order
+    pub fn data(&self) -> &[u64] {
+        &self.data
+    }
+
+    // rebuilds a section from its saved parts, None if they don't fit together
+    pub fn from_parts(palette: Vec<Block>, bits: u32, data: Vec<u64>) -> Option<Self> {
+        if palette.is_empty() || bits != bits_for(palette.len()) || data.len() != packed_len(bits) {
+            return None;
+        }
+        let section = Self {
+            palette,
+            bits,
+            data,
+        };
+        (0..SECTION_VOLUME)
+            .all(|i| section.palette_index(i) < section.palette.len())
+            .then_some(section)
+    }
+
+    // bytes used by the block data, for comparing against a flat array
+    pub fn memory_size(&self) -> usize {
+        self.palette.len() * std::mem::size_of::<Block>() + self.data.len() * 8
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    // None for sections that are all air
+    sections: Vec<Option<Section>>,
+    // one biome per column in z, x order, None for chunks that weren't generated
+    biomes: Option<Vec<Biome>>,
+    // changed since it was last saved
+    pub dirty: bool,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Self {
+            sections: vec![None; SECTION_COUNT],
+            biomes: None,
+            dirty: true,
+        }
+    }
+
+    // x and z are local to the chunk, y is the world height
+    pub fn get(&self, x: usize, y: usize, z: usize) -> Block {
+        match &self.sections[y / CHUNK_SIZE] {
+            Some(section) => section.get(x, y % CHUNK_SIZE, z),
+            None => Block::Air,
+        }
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, z: usize, block: Block) {
+        let section = &mut self.sections[y / CHUNK_SIZE];
+        if section.is_none() && block == Block::Air {
+            return;
+        }
+        section
+            .get_or_insert_with(Section::new)
+            .set(x, y % CHUNK_SIZE, z, block);
+        self.dirty = true;
+    }
+
+    pub fn sections(&self) -> &[Option<Section>] {
+        &self.sections
+    }
+
+    pub fn set_section(&mut self, index: usize, section: Option<Section>) {
+        self.sections[index] = section;
+        self.dirty = true;
+    }
+
+    pub fn biome(&self, x: usize, z: usize) -> Option<Biome> {
+        self.biomes
+            .as_ref()
+            .map(|biomes| biomes[z * CHUNK_SIZE + x])
+    }
+
+    pub fn biomes(&self) -> Option<&[Biome]> {
+        self.biomes.as_deref()
+    }
+
+    pub fn set_biomes(&mut self, biomes: Option<Vec<Biome>>) {
+        if let Some(biomes) = &biomes {
+            assert_eq!(biomes.len(), CHUNK_SIZE * CHUNK_SIZE);
+        }
+        self.biomes = biomes;
+        self.dirty = true;
+    }
+
+    // shrinks section palettes and drops sections that became all air
+    pub fn compact(&mut self) {
+        for section in &mut self.sections {
+            if let Some(s) = section {
+                s.compact();
+                if s.is_empty() {
+                    *section = None;
+                }
+            }
+        }
+    }
+
+    // height of the highest non-empty section, used to skip air when meshing
+    pub fn top(&self) -> usize {
+        self.sections
+            .iter()
+            .rposition(|section| section.is_some())
+            .map_or(0, |i| (i + 1) * CHUNK_SIZE)
+    }
+}
+
+fn place(chunk: &mut Chunk, [x, y, z]: [usize; 3], block: Block) {
+    let existing = chunk.get(x, y, z);
+    if existing.is_replaceable() || (existing == Block::Leaves && block == Block::Log) {
+        chunk.set(x, y, z, block);
+    }
+}
+
+pub fn chunk_pos(x: i32, z: i32) -> ChunkPos {
+    [
+        x.div_euclid(CHUNK_SIZE as i32),
+        z.div_euclid(CHUNK_SIZE as i32),
+    ]
+}
+
+// feature blocks waiting for a chunk, by their position in it
+pub type PendingBlocks = Vec<([usize; 3], Block)>;
+
+#[derive(Debug, Clone, Default)]
+pub struct World {
+    chunks: HashMap<ChunkPos, Chunk>,
+    // blocks placed into chunks that don't exist yet, written when they're inserted
+    pending: HashMap<ChunkPos, PendingBlocks>,
+    pub seed: u64,
+    pub spawn: [f32; 3],
+    // the setting of the generator that made it, saved with it
+    pub generator: Option<String>,
+}
+
+impl World {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            ..Default::default()
+        }
+    }
+
+    pub fn get_block(&self, x: i32, y: i32, z: i32) -> Block {
+        if y < 0 || y >= WORLD_HEIGHT as i32 {
+            return Block::Air;
+        }
+        match self.chunks.get(&chunk_pos(x, z)) {
+            Some(chunk) => chunk.get(
+                x.rem_euclid(CHUNK_SIZE as i32) as usize,
+                y as usize,
+                z.rem_euclid(CHUNK_SIZE as i32) as usize,
+            ),
+            None => Block::Air,
+        }
+    }
+
+    // creates the chunk if needed, blocks outside the world height are ignored
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, block: Block) {
+        if y < 0 || y >= WORLD_HEIGHT as i32 {
+            return;
+        }
+        self.chunks
+            .entry(chunk_pos(x, z))
+            .or_insert_with(Chunk::new)
+            .set(
+                x.rem_euclid(CHUNK_SIZE as i32) as usize,
+                y as usize,
+                z.rem_euclid(CHUNK_SIZE as i32) as usize,
+                block,
+            );
+    }
+
+    // writes a block for a generated feature such as a tree. Only air, plants and
+    // leaves in the way of a log are overwritten, so features don't cut into the
+    // ground or each other. Blocks in chunks that don't exist yet are kept until
+    // the chunk is inserted
+    pub fn place_block(&mut self, x: i32, y: i32, z: i32, block: Block) {
+        if y < 0 || y >= WORLD_HEIGHT as i32 {
+            return;
+        }
+        let pos = chunk_pos(x, z);
+        let local = [
+            x.rem_euclid(CHUNK_SIZE as i32) as usize,
+            y as usize,
+            z.rem_euclid(CHUNK_SIZE as i32) as usize,
+        ];
+        match self.chunks.get_mut(&pos) {
+            Some(chunk) => place(chunk, local, block),
+            None => self.pending.entry(pos).or_default().push((local, block)),
+        }
+    }
+
+    // y of the highest non-air block in a column, None if it's all air
+    pub fn height_at(&self, x: i32, z: i32) -> Option<i32> {
+        let chunk = self.chunks.get(&chunk_pos(x, z))?;
+        let (x, z) = (
+            x.rem_euclid(CHUNK_SIZE as i32) as usize,
+            z.rem_euclid(CHUNK_SIZE as i32) as usize,
+        );
+        (0..chunk.top())
+            .rev()
+            .find(|&y| chunk.get(x, y, z) != Block::Air)
+            .map(|y| y as i32)
+    }
+
+    // a new world holding only the box between two corners, both inclusive
+    pub fn extract(&self, a: [i32; 3], b: [i32; 3]) -> World {
+        let mut world = World::new(self.seed);
+        world.spawn = self.spawn;
+        world.generator = self.generator.clone();
+        for x in a[0].min(b[0])..=a[0].max(b[0]) {
+            for z in a[2].min(b[2])..=a[2].max(b[2]) {
+                for y in a[1].min(b[1])..=a[1].max(b[1]) {
+                    world.set_block(x, y, z, self.get_block(x, y, z));
+                }
+            }
+        }
+        world
+    }
+
+    pub fn chunk(&self, pos: ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(&pos)
+    }
+
+    pub fn chunk_mut(&mut self, pos: ChunkPos) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&pos)
+    }
+
+    pub fn insert_chunk(&mut self, pos: ChunkPos, mut chunk: Chunk) {
+        for (local, block) in self.pending.remove(&pos).unwrap_or_default() {
+            place(&mut chunk, local, block);
+        }
+        self.chunks.insert(pos, chunk);
+    }
+
+    // feature blocks waiting for chunks that don't exist yet, by chunk
+    pub fn pending(&self) -> &HashMap<ChunkPos, PendingBlocks> {
+        &self.pending
+    }
+
+    // places blocks into a chunk as place_block does, queueing them if the
+    // chunk doesn't exist yet
+    pub fn add_pending(&mut self, pos: ChunkPos, blocks: PendingBlocks) {
+        match self.chunks.get_mut(&pos) {
+            Some(chunk) => {
+                for (local, block) in blocks {
+                    place(chunk, local, block);
+                }
+            }
+            None => self.pending.entry(pos).or_default().extend(blocks),
+        }
+    }
+
+    pub fn remove_chunk(&mut self, pos: ChunkPos) -> Option<Chunk> {
+        self.chunks.remove(&pos)
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = (&ChunkPos, &Chunk)> {
+        self.chunks.iter()
+    }
+
+    pub fn chunks_mut(&mut self) -> impl Iterator<Item = (&ChunkPos, &mut Chunk)> {
+        self.chunks.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a section using the first `kinds` block types, spread so that neighbours differ
+    fn mixed_section(kinds: usize) -> Section {
+        let mut section = Section::new();
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    section.set(x, y, z, mixed_block(kinds, x, y, z));
+                }
+            }
+        }
+        section
+    }
+
+    fn mixed_block(kinds: usize, x: usize, y: usize, z: usize) -> Block {
+        Block::ALL[Section::index(x, y, z) * 7 % kinds]
+    }
+
+    #[test]
+    fn reads_back_every_block() {
+        // there are only Block::ALL.len() block types, so that is the widest palette
+        for kinds in [1, 2, 17, Block::ALL.len()] {
+            let section = mixed_section(kinds);
+            assert_eq!(section.palette().len(), kinds.max(1));
+            assert_eq!(section.bits(), bits_for(kinds));
+            assert_eq!(section.data().len(), packed_len(section.bits()));
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    for x in 0..CHUNK_SIZE {
+                        assert_eq!(
+                            section.get(x, y, z),
+                            mixed_block(kinds, x, y, z),
+                            "{} kinds at {:?}",
+                            kinds,
+                            (x, y, z)
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn compact_lowers_the_bit_width() {
+        let mut section = mixed_section(17);
+        assert_eq!(section.bits(), 5);
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    let block = if (x + z) % 2 == 0 {
+                        Block::Stone
+                    } else {
+                        Block::Dirt
+                    };
+                    section.set(x, y, z, block);
+                }
+            }
+        }
+        assert_eq!(section.bits(), 5);
+        section.compact();
+        assert_eq!(section.bits(), 1);
+        assert_eq!(section.palette().len(), 2);
+        assert_eq!(section.get(0, 3, 0), Block::Stone);
+        assert_eq!(section.get(1, 3, 0), Block::Dirt);
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    section.set(x, y, z, Block::Air);
+                }
+            }
+        }
+        section.compact();
+        assert_eq!(section.bits(), 0);
+        assert!(section.data().is_empty());
+        assert!(section.is_empty());
+    }
+
+    #[test]
+    fn from_parts_checks_the_data_length() {
+        let section = mixed_section(5);
+        let palette = section.palette().to_vec();
+        let data = section.data().to_vec();
+        let rebuilt = Section::from_parts(palette.clone(), section.bits(), data.clone()).unwrap();
+        assert_eq!(rebuilt.get(4, 5, 6), section.get(4, 5, 6));
+        assert!(Section::from_parts(palette.clone(), section.bits(), data[1..].to_vec()).is_none());
+        let mut longer = data.clone();
+        longer.push(0);
+        assert!(Section::from_parts(palette.clone(), section.bits(), longer).is_none());
+        assert!(Section::from_parts(palette.clone(), section.bits() + 1, data).is_none());
+        assert!(Section::from_parts(Vec::new(), 0, Vec::new()).is_none());
+        // an index past the end of the palette
+        let mut data = vec![0; packed_len(3)];
+        data[0] = 7;
+        assert!(Section::from_parts(palette, 3, data).is_none());
+    }
+}
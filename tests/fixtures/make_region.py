@@ -0,0 +1,97 @@
+# writes r.0.0.mca, the Anvil region used by the import tests in src/anvil.rs,
+# independently of the Rust NBT code. Chunk 0, 0 uses the 1.18 layout with
+# non-spanning block state indices, chunk 1, 0 the pre-1.16 layout whose
+# indices span two longs. Run from this directory: python3 make_region.py
+import gzip, struct, zlib
+
+def string(s):
+    b = s.encode()
+    return struct.pack(">H", len(b)) + b
+
+def payload(tag):
+    kind, value = tag
+    if kind == "byte": return struct.pack(">b", value)
+    if kind == "int": return struct.pack(">i", value)
+    if kind == "string": return string(value)
+    if kind == "longs": return struct.pack(">i", len(value)) + b"".join(struct.pack(">q", v) for v in value)
+    if kind == "list":
+        first = TAG_IDS[value[0][0]] if value else 0
+        return bytes([first]) + struct.pack(">i", len(value)) + b"".join(payload(v) for v in value)
+    if kind == "compound":
+        out = b""
+        for name, child in value.items():
+            out += bytes([TAG_IDS[child[0]]]) + string(name) + payload(child)
+        return out + b"\0"
+
+TAG_IDS = {"byte": 1, "int": 3, "string": 8, "list": 9, "compound": 10, "longs": 12}
+
+def nbt(root):
+    return bytes([10]) + string("") + payload(root)
+
+def to_signed(v):
+    return v - (1 << 64) if v >= 1 << 63 else v
+
+def pack(indices, bits, spanning):
+    if spanning:
+        longs = [0] * ((len(indices) * bits + 63) // 64)
+        for i, v in enumerate(indices):
+            bit = i * bits
+            longs[bit // 64] |= (v << (bit % 64)) & ((1 << 64) - 1)
+            if bit % 64 + bits > 64:
+                longs[bit // 64 + 1] |= v >> (64 - bit % 64)
+    else:
+        per_long = 64 // bits
+        longs = [0] * ((len(indices) + per_long - 1) // per_long)
+        for i, v in enumerate(indices):
+            longs[i // per_long] |= v << ((i % per_long) * bits)
+    return [to_signed(v) for v in longs]
+
+# the index of the block at section index i, the tests work it out the same way
+def pattern(i, count):
+    x, z, y = i % 16, (i // 16) % 16, i // 256
+    return (x + 2 * z + 3 * y) % count
+
+def palette(names):
+    return ("list", [("compound", {"Name": ("string", "minecraft:" + n)}) for n in names])
+
+MODERN = ["air", "stone", "grass_block", "oak_log", "amethyst_block"]
+LEGACY = ["air", "dirt", "sand", "birch_leaves", "white_stained_glass", "spruce_planks",
+          "coal_ore", "iron_ore", "gold_ore", "diamond_ore", "bedrock", "water", "ice",
+          "cactus", "poppy", "cobblestone", "dirt_path"]
+
+modern = ("compound", {
+    "DataVersion": ("int", 3465),
+    "xPos": ("int", 0),
+    "zPos": ("int", 0),
+    "sections": ("list", [
+        # below the bottom of our world, dropped on import
+        ("compound", {"Y": ("byte", -1), "block_states": ("compound", {"palette": palette(["stone"])})}),
+        ("compound", {"Y": ("byte", 1), "block_states": ("compound", {
+            "palette": palette(MODERN),
+            "data": ("longs", pack([pattern(i, len(MODERN)) for i in range(4096)], 4, False)),
+        })}),
+    ]),
+})
+legacy = ("compound", {
+    "DataVersion": ("int", 2230),
+    "Level": ("compound", {
+        "xPos": ("int", 1),
+        "zPos": ("int", 0),
+        "Sections": ("list", [
+            ("compound", {"Y": ("byte", 2),
+                          "Palette": palette(LEGACY),
+                          "BlockStates": ("longs", pack([pattern(i, len(LEGACY)) for i in range(4096)], 5, True))}),
+        ]),
+    }),
+})
+
+chunks = [(0, 2, zlib.compress(nbt(modern))), (1, 1, gzip.compress(nbt(legacy), mtime=0))]
+header = bytearray(8192)
+body = b""
+for index, compression, data in chunks:
+    blob = struct.pack(">I", len(data) + 1) + bytes([compression]) + data
+    blob += b"\0" * (-len(blob) % 4096)
+    sector = 2 + len(body) // 4096
+    header[index * 4:index * 4 + 4] = struct.pack(">I", (sector << 8) | (len(blob) // 4096))
+    body += blob
+open("r.0.0.mca", "wb").write(bytes(header) + body)
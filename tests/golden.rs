@@ -0,0 +1,65 @@
+// golden image regression tests: render a scene headlessly through the binary
+// and compare it with a checked in PNG. They need a GPU or a software adapter
+// such as lavapipe, so they only run with `cargo test -- --ignored`. Set
+// UPDATE_GOLDEN=1 to write the current render as the new golden image
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const SIZE: [u32; 2] = [320, 240];
+// a channel may differ this much before its pixel counts as changed, to allow
+// for rasterization and MSAA differences between adapters
+const CHANNEL_TOLERANCE: u8 = 16;
+// the share of pixels allowed to change
+const CHANGED_TOLERANCE: f64 = 0.01;
+
+fn render(args: &[&str], out: &Path) {
+    let status = Command::new(env!("CARGO_BIN_EXE_wgpu18-klein-bottle"))
+        .args(args)
+        .arg("--screenshot")
+        .arg(out)
+        .args(SIZE.map(|n| n.to_string()))
+        .status()
+        .expect("failed to run the renderer");
+    assert!(status.success(), "rendering {:?} failed", args);
+}
+
+fn check_golden(name: &str, args: &[&str]) {
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.png", name));
+    let out: PathBuf = std::env::temp_dir().join(format!("golden-{}.png", name));
+    render(args, &out);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::copy(&out, &golden).unwrap();
+        return;
+    }
+
+    let actual = image::open(&out).unwrap().to_rgba8();
+    let expected = image::open(&golden)
+        .unwrap_or_else(|e| panic!("can't read {}: {}", golden.display(), e))
+        .to_rgba8();
+    assert_eq!(actual.dimensions(), expected.dimensions());
+    let changed = actual
+        .pixels()
+        .zip(expected.pixels())
+        .filter(|(a, b)| {
+            a.0.iter()
+                .zip(b.0)
+                .any(|(&a, b)| a.abs_diff(b) > CHANNEL_TOLERANCE)
+        })
+        .count();
+    let share = changed as f64 / (SIZE[0] * SIZE[1]) as f64;
+    assert!(
+        share <= CHANGED_TOLERANCE,
+        "{:.2}% of the pixels differ from {}, the render is at {}",
+        share * 100.0,
+        golden.display(),
+        out.display()
+    );
+}
+
+#[test]
+#[ignore]
+fn cube_generator() {
+    check_golden("cube", &["--generator", "cube"]);
+}